@@ -0,0 +1,115 @@
+//! The Windows "HTML Format" clipboard payload (CF_HTML): a short ASCII
+//! header with byte offsets into the payload, followed by the HTML itself.
+//!
+//! ```text
+//! Version:0.9
+//! StartHTML:0000000105
+//! EndHTML:0000000199
+//! StartFragment:0000000141
+//! EndFragment:0000000163
+//! <html><body>
+//! <!--StartFragment--><b>bold</b><!--EndFragment-->
+//! </body></html>
+//! ```
+
+const START_FRAGMENT: &str = "<!--StartFragment-->";
+const END_FRAGMENT: &str = "<!--EndFragment-->";
+
+/// Offsets are written as fixed-width numbers so the header length is known up front
+const HEADER_TEMPLATE: &str = "Version:0.9\r\nStartHTML:{sh}\r\nEndHTML:{eh}\r\nStartFragment:{sf}\r\nEndFragment:{ef}\r\n";
+
+/// Extract the HTML from a CF_HTML payload.
+///
+/// Returns the HTML section (StartHTML..EndHTML) so document-level styles
+/// survive, falling back to the fragment, then to whatever follows the header.
+pub fn parse(data: &[u8]) -> Option<String> {
+    let end = data.iter().rposition(|&b| b != 0).map(|i| i + 1).unwrap_or(0);
+    let data = &data[..end];
+    let text = String::from_utf8_lossy(data);
+
+    let mut start_html = None;
+    let mut end_html = None;
+    let mut start_fragment = None;
+    let mut end_fragment = None;
+    let mut header_end = 0;
+    for line in text.split_inclusive('\n') {
+        let (key, value) = match line.trim_end().split_once(':') {
+            Some(kv) if !kv.0.starts_with('<') => kv,
+            _ => break,
+        };
+        // Offsets of -1 mean "not present"
+        let offset = value.trim().parse::<i64>().ok().and_then(|v| usize::try_from(v).ok());
+        match key {
+            "StartHTML" => start_html = offset,
+            "EndHTML" => end_html = offset,
+            "StartFragment" => start_fragment = offset,
+            "EndFragment" => end_fragment = offset,
+            _ => {}
+        }
+        header_end += line.len();
+    }
+
+    let slice = |start: Option<usize>, end: Option<usize>| -> Option<String> {
+        let (start, end) = (start?, end?);
+        if start >= end {
+            return None;
+        }
+        data.get(start..end)
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    };
+
+    let html = slice(start_html, end_html)
+        .or_else(|| slice(start_fragment, end_fragment))
+        .or_else(|| text.get(header_end..).map(str::to_string))?;
+    let html = html.trim().to_string();
+    if html.is_empty() {
+        None
+    } else {
+        Some(html)
+    }
+}
+
+/// Build a CF_HTML payload for `html`, which may be a fragment or a full document
+pub fn build(html: &str) -> String {
+    let body = if html.contains(START_FRAGMENT) {
+        html.to_string()
+    } else if let Some((open_end, close_start)) = body_bounds(html) {
+        format!(
+            "{}{}{}{}{}",
+            &html[..open_end],
+            START_FRAGMENT,
+            &html[open_end..close_start],
+            END_FRAGMENT,
+            &html[close_start..]
+        )
+    } else {
+        format!("<html><body>{}{}{}</body></html>", START_FRAGMENT, html, END_FRAGMENT)
+    };
+
+    let header_len = header(0, 0, 0, 0).len();
+    let start_html = header_len;
+    let end_html = header_len + body.len();
+    let start_fragment = header_len + body.find(START_FRAGMENT).unwrap_or(0) + START_FRAGMENT.len();
+    let end_fragment = header_len + body.rfind(END_FRAGMENT).unwrap_or(body.len());
+
+    let mut out = header(start_html, end_html, start_fragment, end_fragment);
+    out.push_str(&body);
+    out
+}
+
+fn header(sh: usize, eh: usize, sf: usize, ef: usize) -> String {
+    HEADER_TEMPLATE
+        .replace("{sh}", &format!("{:010}", sh))
+        .replace("{eh}", &format!("{:010}", eh))
+        .replace("{sf}", &format!("{:010}", sf))
+        .replace("{ef}", &format!("{:010}", ef))
+}
+
+/// Byte range between the end of `<body ...>` and the start of `</body>`
+fn body_bounds(html: &str) -> Option<(usize, usize)> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<body")?;
+    let open_end = open + lower[open..].find('>')? + 1;
+    let close_start = lower.rfind("</body")?;
+    (close_start >= open_end).then_some((open_end, close_start))
+}
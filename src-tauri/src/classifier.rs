@@ -0,0 +1,521 @@
+//! Content classification for copied text.
+//!
+//! Each detector looks at the text and either rejects it or returns detail
+//! tags (e.g. the color notation or the programming language). Whole values
+//! (a link, a color, a date...) are tried first and are exclusive. Otherwise
+//! the document detectors (JSON, YAML, SQL, markdown, code) run in priority
+//! order: the first match becomes the item's category and the others become
+//! secondary tags.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Heuristics only look at the start of very large texts
+const SAMPLE_CHARS: usize = 20_000;
+/// JSON is parsed in full up to this size
+const MAX_JSON_BYTES: usize = 1024 * 1024;
+
+/// Primary category plus secondary tags for a piece of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub category: &'static str,
+    pub tags: Vec<String>,
+}
+
+type Detector = fn(&str) -> Option<Vec<String>>;
+
+/// Detectors for text that is a single value, in priority order
+const VALUE_DETECTORS: &[(&str, Detector)] = &[
+    ("link", link),
+    ("email", email),
+    ("uuid", uuid),
+    ("ip", ip),
+    ("color", color),
+    ("date", date),
+    ("phone", phone),
+    ("number", number),
+    ("path", path),
+];
+
+/// Detectors for documents and snippets, in priority order
+const DOCUMENT_DETECTORS: &[(&str, Detector)] = &[
+    ("json", json),
+    ("yaml", yaml),
+    ("sql", sql),
+    ("markdown", markdown),
+    ("code", code),
+];
+
+/// Classify copied text
+pub fn classify(text: &str) -> Classification {
+    let t = text.trim();
+    if t.is_empty() {
+        return Classification { category: "text", tags: Vec::new() };
+    }
+    let sample = match t.char_indices().nth(SAMPLE_CHARS) {
+        Some((end, _)) => &t[..end],
+        None => t,
+    };
+
+    for (name, detect) in VALUE_DETECTORS {
+        if let Some(tags) = detect(sample) {
+            return Classification { category: name, tags };
+        }
+    }
+
+    let mut category = None;
+    let mut tags: Vec<String> = Vec::new();
+    for (name, detect) in DOCUMENT_DETECTORS {
+        let input = if *name == "json" { t } else { sample };
+        let Some(details) = detect(input) else { continue };
+        let new_tags = if category.is_none() {
+            category = Some(*name);
+            details
+        } else {
+            vec![name.to_string()]
+        };
+        for tag in new_tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    let mentions_url = sample.contains("http://") || sample.contains("https://");
+    match category {
+        Some(category) => {
+            if mentions_url {
+                tags.push("link".into());
+            }
+            Classification { category, tags }
+        }
+        // Text that mentions a URL still belongs with the links
+        None if mentions_url => Classification { category: "link", tags },
+        None => Classification { category: "text", tags },
+    }
+}
+
+fn single_line(t: &str) -> bool {
+    !t.contains('\n')
+}
+
+fn no_space(t: &str) -> bool {
+    !t.contains(char::is_whitespace)
+}
+
+// ─── Whole values ─────────────────────────────────────────────────────────────
+
+fn link(t: &str) -> Option<Vec<String>> {
+    let is_link = no_space(t)
+        && ["http://", "https://", "ftp://", "www."]
+            .iter()
+            .any(|p| t.len() > p.len() && t.get(..p.len()).is_some_and(|s| s.eq_ignore_ascii_case(p)));
+    is_link.then(Vec::new)
+}
+
+fn email(t: &str) -> Option<Vec<String>> {
+    let t = t.strip_prefix("mailto:").unwrap_or(t);
+    let (local, domain) = t.split_once('@')?;
+    let valid = no_space(t)
+        && !local.is_empty()
+        && local.chars().all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+    valid.then(Vec::new)
+}
+
+fn uuid(t: &str) -> Option<Vec<String>> {
+    let t = t.strip_prefix('{').and_then(|s| s.strip_suffix('}')).unwrap_or(t);
+    let groups: Vec<&str> = t.split('-').collect();
+    let lengths = [8, 4, 4, 4, 12];
+    let valid = groups.len() == 5
+        && groups
+            .iter()
+            .zip(lengths)
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()));
+    valid.then(Vec::new)
+}
+
+/// IPv4 or IPv6 address, optionally with a port or CIDR prefix
+fn ip(t: &str) -> Option<Vec<String>> {
+    if !no_space(t) {
+        return None;
+    }
+    let addr = t.split_once('/').map_or(t, |(a, _)| a);
+    if let Some((host, port)) = addr.rsplit_once(':') {
+        if host.parse::<Ipv4Addr>().is_ok() && port.parse::<u16>().is_ok() {
+            return Some(vec!["ipv4".into()]);
+        }
+    }
+    if addr.parse::<Ipv4Addr>().is_ok() {
+        return Some(vec!["ipv4".into()]);
+    }
+    let v6 = addr.strip_prefix('[').and_then(|a| a.split_once(']')).map_or(addr, |(a, _)| a);
+    v6.parse::<Ipv6Addr>().is_ok().then(|| vec!["ipv6".into()])
+}
+
+/// `#rgb`, `#rrggbb(aa)`, `rgb()`/`rgba()` and `hsl()`/`hsla()`
+fn color(t: &str) -> Option<Vec<String>> {
+    if let Some(hex) = t.strip_prefix('#') {
+        let valid = matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+        return valid.then(|| vec!["hex".into()]);
+    }
+    let lower = t.to_ascii_lowercase();
+    for (func, tag) in [("rgba(", "rgb"), ("rgb(", "rgb"), ("hsla(", "hsl"), ("hsl(", "hsl")] {
+        if let Some(args) = lower.strip_prefix(func).and_then(|r| r.strip_suffix(')')) {
+            let parts: Vec<&str> = args
+                .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+                .filter(|p| !p.is_empty())
+                .collect();
+            let numeric = parts.iter().all(|p| {
+                p.trim_end_matches(['%']).trim_end_matches("deg").parse::<f32>().is_ok()
+            });
+            return ((3..=4).contains(&parts.len()) && numeric).then(|| vec![tag.into()]);
+        }
+    }
+    None
+}
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y"];
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d/%m/%Y %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+];
+
+/// ISO, RFC 3339 and day-first dates, with a four-digit year
+fn date(t: &str) -> Option<Vec<String>> {
+    let four_digit_year = t.split(|c: char| !c.is_ascii_digit()).any(|run| run.len() == 4);
+    if !single_line(t) || t.len() > 40 || !four_digit_year {
+        return None;
+    }
+    if DateTime::parse_from_rfc3339(t).is_ok()
+        || DATETIME_FORMATS.iter().any(|f| NaiveDateTime::parse_from_str(t, f).is_ok())
+    {
+        return Some(vec!["datetime".into()]);
+    }
+    DATE_FORMATS
+        .iter()
+        .any(|f| NaiveDate::parse_from_str(t, f).is_ok())
+        .then(Vec::new)
+}
+
+/// Vietnamese numbers (0xx / +84) get a "vn" tag; other numbers need a
+/// leading "+" or grouping to tell them apart from plain numbers
+fn phone(t: &str) -> Option<Vec<String>> {
+    if !single_line(t) || !t.chars().all(|c| c.is_ascii_digit() || " -+().".contains(c)) {
+        return None;
+    }
+    let digits: String = t.chars().filter(|c| c.is_ascii_digit()).collect();
+    let national = if let Some(rest) = t.strip_prefix("+84") {
+        Some(format!("0{}", rest.chars().filter(|c| c.is_ascii_digit()).collect::<String>()))
+    } else if digits.starts_with('0') && !t.starts_with('+') {
+        Some(digits.clone())
+    } else {
+        None
+    };
+    if let Some(n) = national {
+        // Mobile: 03/05/07/08/09 + 8 digits. Landline: 02 + area code, 11 digits.
+        let mobile = n.len() == 10 && matches!(&n[..2], "03" | "05" | "07" | "08" | "09");
+        let landline = n.len() == 11 && n.starts_with("02");
+        if mobile || landline {
+            return Some(vec!["vn".into()]);
+        }
+    }
+    let grouped = t.starts_with('+') || t.contains(['(', ' ', '-']);
+    ((7..=15).contains(&digits.len()) && grouped && !t.contains('.')).then(Vec::new)
+}
+
+/// Longer spellings first so "vnđ" isn't taken for "đ"
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("vnđ", "vnd"),
+    ("vnd", "vnd"),
+    ("₫", "vnd"),
+    ("đ", "vnd"),
+    ("$", "usd"),
+    ("usd", "usd"),
+    ("€", "eur"),
+    ("eur", "eur"),
+    ("£", "gbp"),
+    ("gbp", "gbp"),
+    ("¥", "jpy"),
+    ("jpy", "jpy"),
+];
+
+/// Plain, grouped ("1,234.5", "1.234.567") or currency amounts, and percentages
+fn number(t: &str) -> Option<Vec<String>> {
+    if !single_line(t) || t.len() > 40 {
+        return None;
+    }
+    let lower = t.to_lowercase();
+    let mut body = lower.as_str();
+    let mut tags = Vec::new();
+    for (symbol, code) in CURRENCY_SYMBOLS {
+        if let Some(rest) = body.strip_prefix(symbol).or_else(|| body.strip_suffix(symbol)) {
+            body = rest.trim();
+            tags.push("currency".to_string());
+            tags.push(code.to_string());
+            break;
+        }
+    }
+    if tags.is_empty() {
+        if let Some(rest) = body.strip_suffix('%') {
+            body = rest.trim();
+            tags.push("percent".into());
+        }
+    }
+    let body = body.strip_prefix(['-', '+']).unwrap_or(body);
+    let valid = body.chars().next().is_some_and(|c| c.is_ascii_digit())
+        && body.chars().last().is_some_and(|c| c.is_ascii_digit())
+        && body.chars().all(|c| c.is_ascii_digit() || ",. ".contains(c))
+        && well_grouped(body);
+    valid.then_some(tags)
+}
+
+/// With more than one separator, inner digit groups must be thousands
+/// ("1.234.567", "1,234.5"), which rules out versions like "1.2.3"
+fn well_grouped(number: &str) -> bool {
+    let separators: Vec<char> = number.chars().filter(|c| ",. ".contains(*c)).collect();
+    let groups: Vec<&str> = number.split([',', '.', ' ']).collect();
+    if groups.iter().any(|g| g.is_empty()) {
+        return false;
+    }
+    if separators.len() <= 1 {
+        return true;
+    }
+    let last = separators.len() - 1;
+    let decimal = separators[last] != separators[0];
+    let thousands = if decimal { &groups[1..last] } else { &groups[1..] };
+    thousands.iter().all(|g| g.len() == 3)
+        && separators[..if decimal { last } else { last + 1 }].iter().all(|&c| c == separators[0])
+}
+
+/// Unix (`/`, `~/`, `./`) or Windows (`C:\`, `\\server\`) paths
+fn path(t: &str) -> Option<Vec<String>> {
+    if !single_line(t) || t.contains("://") || t.len() > 1024 {
+        return None;
+    }
+    let t = t.trim_matches('"');
+    let bytes = t.as_bytes();
+    let windows = (bytes.len() > 3 && bytes[0].is_ascii_alphabetic() && &t[1..3] == ":\\")
+        || (t.starts_with("\\\\") && t[2..].contains('\\'));
+    if windows {
+        return Some(vec!["windows".into()]);
+    }
+    let unix = ["/", "~/", "./", "../"].iter().any(|p| t.starts_with(p))
+        && t.len() > 1
+        && t[1..].contains(|c: char| c.is_alphanumeric())
+        && !t.contains("  ");
+    unix.then(|| vec!["unix".into()])
+}
+
+// ─── Structured text ──────────────────────────────────────────────────────────
+
+fn json(t: &str) -> Option<Vec<String>> {
+    let structured = (t.starts_with('{') && t.ends_with('}')) || (t.starts_with('[') && t.ends_with(']'));
+    (structured && t.len() <= MAX_JSON_BYTES && serde_json::from_str::<serde_json::Value>(t).is_ok())
+        .then(Vec::new)
+}
+
+/// Mostly `key: value` and `- item` lines
+fn yaml(t: &str) -> Option<Vec<String>> {
+    let lines: Vec<&str> = t
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .collect();
+    if lines.len() < 2 || (t.contains(['{', ';']) && !t.starts_with("---")) {
+        return None;
+    }
+    let is_key = |key: &str| {
+        !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-.\"'".contains(c))
+    };
+    let mut keys = 0;
+    let yamlish = lines
+        .iter()
+        .filter(|l| {
+            let l = l.trim_start();
+            if l == "---" {
+                return true;
+            }
+            if let Some(item) = l.strip_prefix("- ") {
+                return item.split_once(':').map_or(true, |(k, _)| is_key(k));
+            }
+            match l.split_once(':') {
+                Some((k, v)) if is_key(k) && (v.is_empty() || v.starts_with(' ')) => {
+                    keys += 1;
+                    true
+                }
+                _ => false,
+            }
+        })
+        .count();
+    // Two "Label: value" lines are as likely to be a note; ask for more
+    // keys, nesting or list items
+    let structured = keys >= 3 || t.starts_with("---") || lines.iter().any(|l| l.starts_with([' ', '-']));
+    (keys >= 2 && structured && yamlish * 10 >= lines.len() * 9).then(Vec::new)
+}
+
+/// A statement keyword followed by its companion clause. The first keyword must
+/// be all upper or all lower case, so sentences like "Select one from..." don't count.
+fn sql(t: &str) -> Option<Vec<String>> {
+    let first = t.split_whitespace().next()?;
+    if first != first.to_uppercase() && first != first.to_lowercase() {
+        return None;
+    }
+    let clauses: &[&str] = match first.to_uppercase().as_str() {
+        "SELECT" => &[" FROM "],
+        "INSERT" => &[" INTO "],
+        "UPDATE" => &[" SET "],
+        "DELETE" => &[" FROM "],
+        "CREATE" | "ALTER" | "DROP" => &[" TABLE ", " INDEX ", " VIEW ", " DATABASE "],
+        "WITH" => &[" AS (", " AS("],
+        _ => return None,
+    };
+    let spaced = format!("{} ", t.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase());
+    clauses.iter().any(|c| spaced.contains(c)).then(Vec::new)
+}
+
+/// At least two distinct markdown constructs (headings, lists, links, emphasis...)
+fn markdown(t: &str) -> Option<Vec<String>> {
+    let mut signals = 0;
+    let line_starts = |prefixes: &[&str]| {
+        t.lines()
+            .any(|l| prefixes.iter().any(|p| l.trim_start().starts_with(p)))
+    };
+    if t.lines().any(|l| {
+        let hashes = l.chars().take_while(|&c| c == '#').count();
+        (1..=6).contains(&hashes) && l[hashes..].starts_with(' ')
+    }) {
+        signals += 1;
+    }
+    // Unindented only, so " * " inside doc comments doesn't count
+    if t.lines().any(|l| ["- [ ] ", "- [x] ", "* ", "+ "].iter().any(|p| l.starts_with(p)))
+        || t.lines().filter(|l| l.starts_with("- ")).count() >= 2
+    {
+        signals += 1;
+    }
+    if line_starts(&["> "]) {
+        signals += 1;
+    }
+    if t.contains("](") && t.contains('[') {
+        signals += 1;
+    }
+    if has_bold(t) {
+        signals += 1;
+    }
+    let fence_lang = t
+        .lines()
+        .find_map(|l| l.trim_start().strip_prefix("```"))
+        .map(|info| info.trim().to_lowercase());
+    // A closed fenced block is enough on its own
+    if fence_lang.is_some() {
+        let fences = t.lines().filter(|l| l.trim_start().starts_with("```")).count();
+        signals += if fences >= 2 { 2 } else { 1 };
+    }
+    if line_starts(&["| "]) && (t.contains("|---") || t.contains("| ---")) {
+        signals += 1;
+    }
+    if signals < 2 {
+        return None;
+    }
+    Some(fence_lang.filter(|l| !l.is_empty()).into_iter().collect())
+}
+
+/// `**bold**` on a single line
+fn has_bold(t: &str) -> bool {
+    t.lines().any(|l| {
+        let mut parts = l.split("**");
+        parts.next();
+        match (parts.next(), parts.next()) {
+            (Some(inner), Some(_)) => {
+                !inner.is_empty() && !inner.starts_with(' ') && !inner.ends_with(' ')
+            }
+            _ => false,
+        }
+    })
+}
+
+// ─── Code ─────────────────────────────────────────────────────────────────────
+
+/// Per-language signals. A leading `^` anchors the pattern at the start of a
+/// (trimmed) line, which keeps words like "import" in prose from counting.
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["^fn ", "^pub fn ", "^use std", "^impl ", "let mut ", "println!", "-> ", "&str", "::new(", "#[derive", "^mod ", "Vec<", "Option<", "match "]),
+    ("python", &["^def ", "^import ", "^from ", "^class ", "self.", "elif ", "__init__", "print(", "^@", "^if __name__", "None", " is not "]),
+    ("typescript", &["^interface ", ": string", ": number", ": boolean", "^type ", "^export interface", "as const", "readonly "]),
+    ("javascript", &["^function ", "^const ", "^let ", "=> ", "console.log", "document.", "require(", "===", "^export ", "^import ", "async ", "await "]),
+    ("go", &["^func ", "^package ", ":= ", "fmt.", "go func", "chan ", "^defer ", "err != nil"]),
+    ("java", &["public class ", "public static void", "System.out", "^private ", "@Override", "^import java", "^package ", "new "]),
+    ("csharp", &["^using System", "^namespace ", "Console.Write", "public class ", "{ get; set; }", "^var "]),
+    ("cpp", &["^#include", "int main(", "printf(", "std::", "cout <<", "nullptr", "^template<", "->"]),
+    ("php", &["<?php", "$this->", "^echo ", "^function ", "^namespace ", "$_"]),
+    ("ruby", &["^def ", "^end", "^puts ", "^require '", "do |", ".each", "^module ", "attr_accessor"]),
+    ("shell", &["#!/bin/", "^sudo ", "^export ", "| grep", "^echo ", "$(", "^cd ", " && ", "^fi", "^done"]),
+    ("html", &["<!DOCTYPE", "<html", "<div", "</div>", "<span", "<p>", "</a>", "<body", "<head"]),
+    ("css", &["^@media", "^@import", "color:", "margin:", "padding:", "display:", "font-size:", "}"]),
+];
+
+/// Tools whose name alone makes a single line a shell command
+const SHELL_TOOLS: &[&str] = &[
+    "git", "npm", "npx", "yarn", "pnpm", "cargo", "pip", "pip3", "docker", "kubectl", "sudo", "apt",
+    "apt-get", "brew",
+];
+/// Commands that are also English words: they need a flag or a path too
+const SHELL_COMMANDS: &[&str] = &[
+    "curl", "wget", "ssh", "scp", "ls", "cd", "mkdir", "rm", "cp", "mv", "chmod", "python", "python3",
+    "node", "go", "make", "cat", "grep", "tar",
+];
+
+fn code(t: &str) -> Option<Vec<String>> {
+    if single_line(t) {
+        let words: Vec<&str> = t.split_whitespace().collect();
+        let has_args = words[1..].iter().any(|w| w.starts_with('-') || w.contains(['/', '.', '~', '=']));
+        let command = match words.first() {
+            Some(first) => SHELL_TOOLS.contains(first) || (SHELL_COMMANDS.contains(first) && has_args),
+            None => false,
+        };
+        if command && words.len() >= 2 {
+            return Some(vec!["shell".into()]);
+        }
+    }
+
+    let lines: Vec<&str> = t.lines().map(str::trim_start).collect();
+    let score = |patterns: &[&str]| {
+        patterns
+            .iter()
+            .filter(|p| match p.strip_prefix('^') {
+                Some(anchored) => lines.iter().any(|l| l.starts_with(anchored)),
+                None => t.contains(*p),
+            })
+            .count()
+    };
+    let mut best: Option<(&str, usize)> = None;
+    for (lang, patterns) in LANGUAGES {
+        let s = score(patterns);
+        if s >= 2 && best.map_or(true, |(_, b)| s > b) {
+            best = Some((lang, s));
+        }
+    }
+    // TypeScript is JavaScript plus type annotations
+    if let Some(("javascript", _)) = best {
+        let ts = LANGUAGES.iter().find(|(l, _)| *l == "typescript").map(|(_, p)| score(p));
+        if ts.is_some_and(|s| s >= 1) {
+            best = Some(("typescript", 0));
+        }
+    }
+    if let Some((lang, _)) = best {
+        return Some(vec![lang.into()]);
+    }
+
+    // Unknown language: several lines that end like statements or blocks
+    let code_lines = lines
+        .iter()
+        .filter(|l| l.ends_with(';') || l.ends_with('{') || l.ends_with('}') || l.ends_with("*/"))
+        .count();
+    (lines.len() >= 3 && code_lines * 2 >= lines.len()).then(Vec::new)
+}
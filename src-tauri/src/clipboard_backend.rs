@@ -0,0 +1,957 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Raw RGBA image as read from or written to the clipboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardImage {
+    pub width: usize,
+    pub height: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// MIME keys shared by every platform
+pub const TEXT_PLAIN: &str = "text/plain;charset=utf-8";
+pub const TEXT_HTML: &str = "text/html";
+pub const TEXT_RTF: &str = "text/rtf";
+
+/// Formats password managers add to mark a copy as secret or short-lived
+/// (macOS UTIs, a Windows registered format, a KDE/Linux MIME type)
+pub const CONCEALED_MARKERS: &[&str] = &[
+    "org.nspasteboard.ConcealedType",
+    "org.nspasteboard.TransientType",
+    "ExcludeClipboardContentFromMonitorProcessing",
+    "x-kde-passwordManagerHint",
+];
+
+/// Decode a text flavor: UTF-8, or UTF-16 when it starts with a byte order
+/// mark (some Linux apps send HTML that way), without trailing NULs
+pub fn decode_text(data: &[u8]) -> String {
+    let text = match data {
+        [0xFF, 0xFE, rest @ ..] => String::from_utf16_lossy(
+            &rest.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>(),
+        ),
+        [0xFE, 0xFF, rest @ ..] => String::from_utf16_lossy(
+            &rest.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>(),
+        ),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+/// The flavors for a piece of rich text
+pub fn rich_flavors(text: &str, html: Option<&str>, rtf: Option<&str>) -> Vec<Flavor> {
+    let mut flavors = vec![Flavor {
+        mime: TEXT_PLAIN.into(),
+        data: text.as_bytes().to_vec(),
+    }];
+    if let Some(html) = html {
+        flavors.push(Flavor {
+            mime: TEXT_HTML.into(),
+            data: html.as_bytes().to_vec(),
+        });
+    }
+    if let Some(rtf) = rtf {
+        flavors.push(Flavor {
+            mime: TEXT_RTF.into(),
+            data: rtf.as_bytes().to_vec(),
+        });
+    }
+    flavors
+}
+
+/// One representation of the clipboard contents: a MIME type (or the native
+/// format name when the platform has no MIME equivalent) and its raw bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flavor {
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+/// Access to the system clipboard.
+///
+/// The watcher, paste and commands only talk to the clipboard through this
+/// trait, so the capture logic can be driven by `MemoryClipboard` in tests.
+/// Getters return `None` when the clipboard holds nothing of that kind.
+pub trait ClipboardBackend: Send {
+    fn get_text(&mut self) -> Option<String>;
+    fn set_text(&mut self, text: &str) -> Result<(), String>;
+
+    fn get_html(&mut self) -> Option<String>;
+    /// Set HTML together with a plain text alternative
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String>;
+
+    fn get_rtf(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Set plain text with optional HTML and RTF versions in one go
+    fn set_rich(&mut self, text: &str, html: Option<&str>, rtf: Option<&str>) -> Result<(), String> {
+        self.set_flavors(&rich_flavors(text, html, rtf))
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage>;
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String>;
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>>;
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String>;
+
+    /// Every representation currently on the clipboard.
+    /// Backends that can't enumerate formats report just text and HTML.
+    fn get_flavors(&mut self) -> Vec<Flavor> {
+        let mut flavors = Vec::new();
+        if let Some(text) = self.get_text() {
+            flavors.push(Flavor {
+                mime: TEXT_PLAIN.into(),
+                data: text.into_bytes(),
+            });
+        }
+        if let Some(html) = self.get_html() {
+            flavors.push(Flavor {
+                mime: TEXT_HTML.into(),
+                data: html.into_bytes(),
+            });
+        }
+        flavors
+    }
+
+    /// Replace the clipboard with exactly these representations
+    fn set_flavors(&mut self, flavors: &[Flavor]) -> Result<(), String> {
+        let find = |mime: &str| {
+            flavors
+                .iter()
+                .find(|f| f.mime == mime)
+                .map(|f| String::from_utf8_lossy(&f.data).into_owned())
+        };
+        match (find(TEXT_HTML), find(TEXT_PLAIN)) {
+            (Some(html), text) => self.set_html(&html, &text.unwrap_or_default()),
+            (None, Some(text)) => self.set_text(&text),
+            (None, None) => Err("no supported flavor to restore".into()),
+        }
+    }
+
+    /// The Linux PRIMARY selection (last highlighted text), where there is one
+    fn get_primary_text(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Name of the application that put the current contents on the clipboard,
+    /// where the platform can tell (window class or process name)
+    fn source_app(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Whether the current contents carry a password manager's "concealed"
+    /// or "transient" marker (see `CONCEALED_MARKERS`)
+    fn is_concealed(&mut self) -> bool {
+        false
+    }
+}
+
+/// Open the clipboard backend for this platform
+pub fn system() -> Result<Box<dyn ClipboardBackend>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        Ok(Box::new(MacBackend::new()?))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(LinuxBackend::new()?))
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Box::new(WindowsBackend::new()?))
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        Ok(Box::new(ArboardBackend::new()?))
+    }
+}
+
+// ─── arboard ──────────────────────────────────────────────────────────────────
+
+/// Cross-platform backend on top of arboard
+pub struct ArboardBackend {
+    clipboard: arboard::Clipboard,
+}
+
+impl ArboardBackend {
+    pub fn new() -> Result<Self, String> {
+        arboard::Clipboard::new()
+            .map(|clipboard| Self { clipboard })
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl ClipboardBackend for ArboardBackend {
+    fn get_text(&mut self) -> Option<String> {
+        self.clipboard.get_text().ok().filter(|t| !t.is_empty())
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.clipboard.set_text(text).map_err(|e| e.to_string())
+    }
+
+    fn get_html(&mut self) -> Option<String> {
+        self.clipboard.get().html().ok().filter(|h| !h.is_empty())
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String> {
+        self.clipboard
+            .set_html(html, Some(alt_text))
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage> {
+        let img = self.clipboard.get_image().ok()?;
+        if img.bytes.is_empty() {
+            return None;
+        }
+        Some(ClipboardImage {
+            width: img.width,
+            height: img.height,
+            bytes: img.bytes.into_owned(),
+        })
+    }
+
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String> {
+        self.clipboard
+            .set_image(arboard::ImageData {
+                width: image.width,
+                height: image.height,
+                bytes: std::borrow::Cow::Borrowed(&image.bytes),
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>> {
+        self.clipboard.get().file_list().ok().filter(|f| !f.is_empty())
+    }
+
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String> {
+        self.clipboard.set().file_list(files).map_err(|e| e.to_string())
+    }
+}
+
+// ─── macOS ────────────────────────────────────────────────────────────────────
+
+/// macOS backend: pbcopy/osascript for text and HTML (more reliable than
+/// arboard from a background thread), arboard for images and files
+#[cfg(target_os = "macos")]
+pub struct MacBackend {
+    inner: ArboardBackend,
+}
+
+#[cfg(target_os = "macos")]
+impl MacBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            inner: ArboardBackend::new()?,
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl ClipboardBackend for MacBackend {
+    fn get_text(&mut self) -> Option<String> {
+        self.inner.get_text()
+    }
+
+    /// Set clipboard text via pbcopy (proven reliable)
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        use std::io::Write;
+        let mut child = std::process::Command::new("pbcopy")
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("pbcopy spawn failed: {}", e))?;
+        if let Some(ref mut stdin) = child.stdin {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| format!("pbcopy write failed: {}", e))?;
+        }
+        drop(child.stdin.take());
+        let _ = child.wait();
+        Ok(())
+    }
+
+    /// Read HTML from the pasteboard with osascript
+    fn get_html(&mut self) -> Option<String> {
+        let output = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(r#"use framework "AppKit"
+            set pb to current application's NSPasteboard's generalPasteboard()
+            set htmlText to pb's stringForType:(current application's NSPasteboardTypeHTML)
+            if htmlText is missing value then return ""
+            return htmlText as text"#)
+            .output()
+            .ok()?;
+
+        let html = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if html.is_empty() || html.len() < 10 {
+            None
+        } else {
+            Some(html)
+        }
+    }
+
+    /// Set both HTML and plain text on the general NSPasteboard
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String> {
+        self.set_rich(alt_text, Some(html), None)
+    }
+
+    fn get_rtf(&mut self) -> Option<String> {
+        let output = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(r#"use framework "AppKit"
+            set pb to current application's NSPasteboard's generalPasteboard()
+            set rtfText to pb's stringForType:(current application's NSPasteboardTypeRTF)
+            if rtfText is missing value then return ""
+            return rtfText as text"#)
+            .output()
+            .ok()?;
+        let rtf = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if rtf.is_empty() {
+            None
+        } else {
+            Some(rtf)
+        }
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage> {
+        self.inner.get_image()
+    }
+
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String> {
+        self.inner.set_image(image)
+    }
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>> {
+        self.inner.get_files()
+    }
+
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String> {
+        self.inner.set_files(files)
+    }
+
+    /// macOS doesn't record who wrote the pasteboard; the frontmost app did
+    fn source_app(&mut self) -> Option<String> {
+        let output = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(r#"use framework "AppKit"
+            set frontApp to current application's NSWorkspace's sharedWorkspace()'s frontmostApplication()
+            if frontApp is missing value then return ""
+            return (frontApp's localizedName()) as text"#)
+            .output()
+            .ok()?;
+        let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    fn is_concealed(&mut self) -> bool {
+        let output = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(r#"use framework "AppKit"
+            set types to (current application's NSPasteboard's generalPasteboard()'s types()) as list
+            if types contains "org.nspasteboard.ConcealedType" or types contains "org.nspasteboard.TransientType" then return "1"
+            return "0""#)
+            .output();
+        output.is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "1")
+    }
+
+    /// Dump every pasteboard type to temp files with osascript
+    fn get_flavors(&mut self) -> Vec<Flavor> {
+        let dir = std::env::temp_dir().join(format!("copas_flavors_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        if std::fs::create_dir_all(&dir).is_err() {
+            return Vec::new();
+        }
+
+        let script = r#"use framework "AppKit"
+        on run argv
+            set outDir to item 1 of argv
+            set pb to current application's NSPasteboard's generalPasteboard()
+            set out to ""
+            set n to 0
+            repeat with t in (pb's types() as list)
+                set d to (pb's dataForType:t)
+                if d is not missing value then
+                    set n to n + 1
+                    set p to outDir & "/" & n
+                    (d's writeToFile:p atomically:false)
+                    set out to out & (t as text) & tab & p & linefeed
+                end if
+            end repeat
+            return out
+        end run"#;
+        let output = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(script)
+            .arg(&dir)
+            .output();
+
+        let mut flavors = Vec::new();
+        if let Ok(out) = output {
+            for line in String::from_utf8_lossy(&out.stdout).lines() {
+                if let Some((uti, path)) = line.split_once('\t') {
+                    if let Ok(data) = std::fs::read(path) {
+                        flavors.push(Flavor {
+                            mime: uti_to_mime(uti),
+                            data,
+                        });
+                    }
+                }
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+        flavors
+    }
+
+    /// Write every flavor back to the general NSPasteboard in one go
+    fn set_flavors(&mut self, flavors: &[Flavor]) -> Result<(), String> {
+        let dir = std::env::temp_dir().join(format!("copas_restore_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+        let mut args = Vec::new();
+        for (n, flavor) in flavors.iter().enumerate() {
+            let path = dir.join(n.to_string());
+            std::fs::write(&path, &flavor.data).map_err(|e| e.to_string())?;
+            args.push(mime_to_uti(&flavor.mime));
+            args.push(path.to_string_lossy().into_owned());
+        }
+
+        let script = r#"use framework "AppKit"
+        on run argv
+            set pb to current application's NSPasteboard's generalPasteboard()
+            pb's clearContents()
+            repeat with n from 1 to (count of argv) by 2
+                set d to (current application's NSData's dataWithContentsOfFile:(item (n + 1) of argv))
+                (pb's setData:d forType:(item n of argv))
+            end repeat
+            return "OK"
+        end run"#;
+        let result = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(script)
+            .args(&args)
+            .output();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        match result {
+            Ok(out) if out.status.success() => Ok(()),
+            Ok(out) => Err(format!(
+                "NSPasteboard failed: {}",
+                String::from_utf8_lossy(&out.stderr)
+            )),
+            Err(e) => Err(format!("osascript error: {}", e)),
+        }
+    }
+}
+
+/// Pasteboard types with a MIME equivalent; anything else keeps its UTI
+#[cfg(target_os = "macos")]
+const UTI_MIME: &[(&str, &str)] = &[
+    ("public.utf8-plain-text", TEXT_PLAIN),
+    ("public.html", TEXT_HTML),
+    ("public.rtf", TEXT_RTF),
+    ("public.png", "image/png"),
+    ("public.tiff", "image/tiff"),
+    ("public.jpeg", "image/jpeg"),
+    ("public.svg-image", "image/svg+xml"),
+    ("com.adobe.pdf", "application/pdf"),
+];
+
+#[cfg(target_os = "macos")]
+fn uti_to_mime(uti: &str) -> String {
+    UTI_MIME
+        .iter()
+        .find(|(u, _)| *u == uti)
+        .map(|(_, m)| m.to_string())
+        .unwrap_or_else(|| uti.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn mime_to_uti(mime: &str) -> String {
+    UTI_MIME
+        .iter()
+        .find(|(_, m)| *m == mime)
+        .map(|(u, _)| u.to_string())
+        .unwrap_or_else(|| mime.to_string())
+}
+
+// ─── Linux ────────────────────────────────────────────────────────────────────
+
+/// Linux backend: arboard for the common formats, plus native X11/Wayland
+/// access for the full list of flavors and the PRIMARY selection
+#[cfg(target_os = "linux")]
+pub struct LinuxBackend {
+    inner: ArboardBackend,
+}
+
+#[cfg(target_os = "linux")]
+impl LinuxBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            inner: ArboardBackend::new()?,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ClipboardBackend for LinuxBackend {
+    fn get_text(&mut self) -> Option<String> {
+        self.inner.get_text()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.inner.set_text(text)
+    }
+
+    /// Read the `text/html` target directly, falling back to arboard
+    fn get_html(&mut self) -> Option<String> {
+        crate::clipboard_linux::read_first(&[TEXT_HTML])
+            .map(|data| decode_text(&data))
+            .filter(|html| !html.trim().is_empty())
+            .or_else(|| self.inner.get_html())
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String> {
+        self.set_rich(alt_text, Some(html), None)
+    }
+
+    fn get_rtf(&mut self) -> Option<String> {
+        crate::clipboard_linux::read_first(&[TEXT_RTF, "application/rtf", "text/richtext"])
+            .map(|data| decode_text(&data))
+            .filter(|rtf| !rtf.trim().is_empty())
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage> {
+        self.inner.get_image()
+    }
+
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String> {
+        self.inner.set_image(image)
+    }
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>> {
+        self.inner.get_files()
+    }
+
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String> {
+        self.inner.set_files(files)
+    }
+
+    fn get_flavors(&mut self) -> Vec<Flavor> {
+        match crate::clipboard_linux::read_flavors() {
+            Ok(flavors) => flavors,
+            Err(e) => {
+                log::warn!("Failed to read clipboard flavors: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn set_flavors(&mut self, flavors: &[Flavor]) -> Result<(), String> {
+        crate::clipboard_linux::write_flavors(flavors.to_vec())
+    }
+
+    fn get_primary_text(&mut self) -> Option<String> {
+        use arboard::{GetExtLinux, LinuxClipboardKind};
+        self.inner
+            .clipboard
+            .get()
+            .clipboard(LinuxClipboardKind::Primary)
+            .text()
+            .ok()
+            .filter(|t| !t.is_empty())
+    }
+    fn source_app(&mut self) -> Option<String> {
+        crate::clipboard_linux::source_app()
+    }
+
+    /// KeePassXC and KDE apps offer `x-kde-passwordManagerHint` = "secret"
+    fn is_concealed(&mut self) -> bool {
+        crate::clipboard_linux::read_first(&["x-kde-passwordManagerHint"])
+            .is_some_and(|hint| decode_text(&hint).trim() == "secret")
+    }
+}
+
+// ─── Windows ──────────────────────────────────────────────────────────────────
+
+/// Windows backend: arboard for images and files, raw clipboard formats for
+/// HTML (CF_HTML), RTF and the full list of flavors
+#[cfg(target_os = "windows")]
+pub struct WindowsBackend {
+    inner: ArboardBackend,
+}
+
+#[cfg(target_os = "windows")]
+impl WindowsBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            inner: ArboardBackend::new()?,
+        })
+    }
+
+    /// Read one registered format
+    fn get_registered(&mut self, name: &str) -> Option<Vec<u8>> {
+        use clipboard_win::raw;
+
+        let format = raw::register_format(name)?.get();
+        let _clip = clipboard_win::Clipboard::new_attempts(10).ok()?;
+        let mut data = Vec::new();
+        raw::get_vec(format, &mut data).ok()?;
+        Some(data).filter(|d| !d.is_empty())
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl ClipboardBackend for WindowsBackend {
+    fn get_text(&mut self) -> Option<String> {
+        self.inner.get_text()
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.inner.set_text(text)
+    }
+
+    fn get_html(&mut self) -> Option<String> {
+        self.get_registered(CF_HTML_NAME)
+            .and_then(|data| crate::cf_html::parse(&data))
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String> {
+        self.set_rich(alt_text, Some(html), None)
+    }
+
+    fn get_rtf(&mut self) -> Option<String> {
+        self.get_registered(CF_RTF_NAME)
+            .map(|data| decode_text(&data))
+            .filter(|rtf| !rtf.is_empty())
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage> {
+        self.inner.get_image()
+    }
+
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String> {
+        self.inner.set_image(image)
+    }
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>> {
+        self.inner.get_files()
+    }
+
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String> {
+        self.inner.set_files(files)
+    }
+
+    /// Password managers either register "ExcludeClipboardContentFromMonitorProcessing"
+    /// or set "CanIncludeInClipboardHistory" to zero
+    fn is_concealed(&mut self) -> bool {
+        use clipboard_win::raw;
+
+        let excluded = raw::register_format("ExcludeClipboardContentFromMonitorProcessing")
+            .is_some_and(|format| raw::is_format_avail(format.get()));
+        excluded
+            || self
+                .get_registered("CanIncludeInClipboardHistory")
+                .is_some_and(|data| data.iter().all(|&b| b == 0))
+    }
+
+    /// Process that owns the clipboard. Apps that don't keep an owner window
+    /// get the foreground window's process instead.
+    fn source_app(&mut self) -> Option<String> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::DataExchange::GetClipboardOwner;
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+        use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+        // SAFETY: plain Win32 calls; the process handle is closed before returning
+        // and the name buffer outlives the call that fills it
+        let path = unsafe {
+            let mut window = GetClipboardOwner();
+            if window.is_null() {
+                window = GetForegroundWindow();
+            }
+            if window.is_null() {
+                return None;
+            }
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(window, &mut pid);
+            if pid == 0 {
+                return None;
+            }
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return None;
+            }
+            let mut buf = [0u16; 1024];
+            let mut len = buf.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len);
+            CloseHandle(process);
+            if ok == 0 {
+                return None;
+            }
+            String::from_utf16_lossy(&buf[..len as usize])
+        };
+        std::path::Path::new(&path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    }
+
+    fn get_flavors(&mut self) -> Vec<Flavor> {
+        use clipboard_win::{formats, raw};
+
+        let _clip = match clipboard_win::Clipboard::new_attempts(10) {
+            Ok(c) => c,
+            Err(e) => {
+                log::warn!("Failed to open clipboard for flavors: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut flavors = Vec::new();
+        for format in raw::EnumFormats::new() {
+            let name = match windows_format_name(format) {
+                Some(name) => name,
+                None => continue,
+            };
+            let mut data = Vec::new();
+            if raw::get_vec(format, &mut data).is_err() || data.is_empty() {
+                continue;
+            }
+
+            // Text, HTML and RTF are stored under their MIME types so they
+            // restore on other platforms too
+            let flavor = if format == formats::CF_UNICODETEXT {
+                let units: Vec<u16> = data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                let text = String::from_utf16_lossy(&units);
+                Flavor {
+                    mime: TEXT_PLAIN.into(),
+                    data: text.trim_end_matches('\0').as_bytes().to_vec(),
+                }
+            } else if name == CF_HTML_NAME {
+                match crate::cf_html::parse(&data) {
+                    Some(html) => Flavor {
+                        mime: TEXT_HTML.into(),
+                        data: html.into_bytes(),
+                    },
+                    None => continue,
+                }
+            } else if name == CF_RTF_NAME {
+                Flavor {
+                    mime: TEXT_RTF.into(),
+                    data: decode_text(&data).into_bytes(),
+                }
+            } else {
+                Flavor { mime: name, data }
+            };
+            flavors.push(flavor);
+        }
+        flavors
+    }
+
+    fn set_flavors(&mut self, flavors: &[Flavor]) -> Result<(), String> {
+        use clipboard_win::{formats, raw};
+
+        let _clip = clipboard_win::Clipboard::new_attempts(10).map_err(|e| e.to_string())?;
+        raw::empty().map_err(|e| e.to_string())?;
+        for flavor in flavors {
+            let (format, data) = match flavor.mime.as_str() {
+                TEXT_PLAIN => (
+                    Some(formats::CF_UNICODETEXT),
+                    decode_text(&flavor.data)
+                        .encode_utf16()
+                        .chain(std::iter::once(0))
+                        .flat_map(|u| u.to_le_bytes())
+                        .collect(),
+                ),
+                TEXT_HTML => (
+                    raw::register_format(CF_HTML_NAME).map(|f| f.get()),
+                    crate::cf_html::build(&decode_text(&flavor.data)).into_bytes(),
+                ),
+                TEXT_RTF => (
+                    raw::register_format(CF_RTF_NAME).map(|f| f.get()),
+                    flavor.data.clone(),
+                ),
+                mime => (
+                    WINDOWS_FORMATS
+                        .iter()
+                        .find(|(_, m)| *m == mime)
+                        .map(|(f, _)| *f)
+                        .or_else(|| raw::register_format(mime).map(|f| f.get())),
+                    flavor.data.clone(),
+                ),
+            };
+            if let Some(format) = format {
+                raw::set_without_clear(format, &data).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+const CF_HTML_NAME: &str = "HTML Format";
+#[cfg(target_os = "windows")]
+const CF_RTF_NAME: &str = "Rich Text Format";
+
+/// Standard formats worth keeping besides CF_UNICODETEXT. The rest are GDI
+/// handles or get synthesized by Windows from these.
+#[cfg(target_os = "windows")]
+const WINDOWS_FORMATS: &[(u32, &str)] = &[
+    (clipboard_win::formats::CF_UNICODETEXT, "text/plain;charset=utf-16le"),
+    (clipboard_win::formats::CF_DIB, "image/x-win-dib"),
+    (clipboard_win::formats::CF_HDROP, "application/x-win-hdrop"),
+];
+
+/// Registered formats ("HTML Format", "PNG", ...) keep their name
+#[cfg(target_os = "windows")]
+fn windows_format_name(format: u32) -> Option<String> {
+    if let Some((_, name)) = WINDOWS_FORMATS.iter().find(|(f, _)| *f == format) {
+        return Some(name.to_string());
+    }
+    if format >= 0xC000 {
+        return clipboard_win::raw::format_name_big(format);
+    }
+    None
+}
+
+// ─── In-memory ────────────────────────────────────────────────────────────────
+
+/// Contents of a `MemoryClipboard`. Like a real clipboard, setting one kind
+/// of content replaces everything else.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryContents {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+    pub image: Option<ClipboardImage>,
+    pub files: Option<Vec<PathBuf>>,
+    pub flavors: Vec<Flavor>,
+    pub primary: Option<String>,
+    pub source_app: Option<String>,
+}
+
+/// In-memory clipboard for headless tests. Clones share the same contents,
+/// so a test can keep a handle while the watcher owns another.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    contents: Arc<Mutex<MemoryContents>>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of the current contents
+    pub fn contents(&self) -> MemoryContents {
+        self.contents.lock().unwrap().clone()
+    }
+
+    /// Replace the whole contents, as another application copying would
+    pub fn replace(&self, contents: MemoryContents) {
+        *self.contents.lock().unwrap() = contents;
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        self.contents.lock().unwrap().text.clone().filter(|t| !t.is_empty())
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        self.replace(MemoryContents {
+            text: Some(text.to_string()),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn get_html(&mut self) -> Option<String> {
+        self.contents.lock().unwrap().html.clone()
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), String> {
+        self.replace(MemoryContents {
+            text: Some(alt_text.to_string()),
+            html: Some(html.to_string()),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn get_rtf(&mut self) -> Option<String> {
+        self.contents.lock().unwrap().rtf.clone()
+    }
+
+    fn get_image(&mut self) -> Option<ClipboardImage> {
+        self.contents.lock().unwrap().image.clone()
+    }
+
+    fn set_image(&mut self, image: &ClipboardImage) -> Result<(), String> {
+        self.replace(MemoryContents {
+            image: Some(image.clone()),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn get_files(&mut self) -> Option<Vec<PathBuf>> {
+        self.contents.lock().unwrap().files.clone()
+    }
+
+    fn set_files(&mut self, files: &[PathBuf]) -> Result<(), String> {
+        self.replace(MemoryContents {
+            files: Some(files.to_vec()),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn get_flavors(&mut self) -> Vec<Flavor> {
+        self.contents.lock().unwrap().flavors.clone()
+    }
+
+    fn set_flavors(&mut self, flavors: &[Flavor]) -> Result<(), String> {
+        let find = |mime: &str| {
+            flavors
+                .iter()
+                .find(|f| f.mime == mime)
+                .map(|f| String::from_utf8_lossy(&f.data).into_owned())
+        };
+        self.replace(MemoryContents {
+            text: find(TEXT_PLAIN),
+            html: find(TEXT_HTML),
+            rtf: find(TEXT_RTF),
+            flavors: flavors.to_vec(),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn get_primary_text(&mut self) -> Option<String> {
+        self.contents.lock().unwrap().primary.clone()
+    }
+    fn source_app(&mut self) -> Option<String> {
+        self.contents.lock().unwrap().source_app.clone()
+    }
+
+    fn is_concealed(&mut self) -> bool {
+        self.contents
+            .lock()
+            .unwrap()
+            .flavors
+            .iter()
+            .any(|f| CONCEALED_MARKERS.contains(&f.mime.as_str()))
+    }
+}
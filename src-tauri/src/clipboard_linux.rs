@@ -0,0 +1,679 @@
+use crate::clipboard_backend::{Flavor, TEXT_PLAIN};
+
+/// Read every MIME flavor of the CLIPBOARD selection
+pub fn read_flavors() -> Result<Vec<Flavor>, String> {
+    read_matching(&is_mime)
+}
+
+/// Read the first of `mimes` (in order of preference) the clipboard offers
+pub fn read_first(mimes: &[&str]) -> Option<Vec<u8>> {
+    let flavors = read_matching(&|name| mimes.contains(&name)).ok()?;
+    mimes
+        .iter()
+        .find_map(|mime| flavors.iter().find(|f| f.mime == *mime))
+        .map(|f| f.data.clone())
+}
+
+fn read_matching(wanted: &dyn Fn(&str) -> bool) -> Result<Vec<Flavor>, String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match wayland::read_flavors(wanted) {
+            Ok(flavors) => return Ok(flavors),
+            Err(e) => log::info!("Wayland flavor read unavailable: {}", e),
+        }
+    }
+    x11::read_flavors(wanted)
+}
+
+/// Take ownership of the CLIPBOARD selection and serve these flavors from a
+/// background thread until another application copies something
+pub fn write_flavors(mut flavors: Vec<Flavor>) -> Result<(), String> {
+    // Older apps only ask for the legacy text targets
+    if let Some(text) = flavors.iter().find(|f| f.mime == TEXT_PLAIN).map(|f| f.data.clone()) {
+        for alias in TEXT_ALIASES {
+            if !flavors.iter().any(|f| f.mime == *alias) {
+                flavors.push(Flavor {
+                    mime: alias.to_string(),
+                    data: text.clone(),
+                });
+            }
+        }
+    }
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match wayland::write_flavors(flavors.clone()) {
+            Ok(()) => return Ok(()),
+            Err(e) => log::info!("Wayland flavor write unavailable: {}", e),
+        }
+    }
+    x11::write_flavors(flavors)
+}
+
+/// WM_CLASS of the app that owns the clipboard. Needs X11 (or XWayland for
+/// X apps); native Wayland offers no way to tell who set the selection.
+pub fn source_app() -> Option<String> {
+    // Under Wayland the active X window may be unrelated to the copy
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    match x11::source_app(!wayland) {
+        Ok(app) => app,
+        Err(e) => {
+            log::info!("Clipboard owner lookup unavailable: {}", e);
+            None
+        }
+    }
+}
+
+const TEXT_ALIASES: &[&str] = &["text/plain", "UTF8_STRING", "TEXT", "STRING"];
+
+fn err(e: impl std::fmt::Display) -> String {
+    e.to_string()
+}
+
+/// Selection targets that are X11 conventions rather than content
+/// ("TARGETS", "UTF8_STRING", ...) have no '/' in their name
+fn is_mime(name: &str) -> bool {
+    name.contains('/')
+}
+
+// ─── X11 ──────────────────────────────────────────────────────────────────────
+
+mod x11 {
+    use super::{err, Flavor};
+    use log::warn;
+    use std::time::{Duration, Instant};
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
+        SelectionNotifyEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+    /// How long a selection owner gets to answer each request
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    struct Session {
+        conn: RustConnection,
+        window: Window,
+        clipboard: Atom,
+        targets: Atom,
+        incr: Atom,
+        property: Atom,
+    }
+
+    fn open() -> Result<Session, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(err)?;
+        let screen = &conn.setup().roots[screen_num];
+        let window = conn.generate_id().map_err(err)?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            screen.root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(err)?;
+
+        let clipboard = intern(&conn, "CLIPBOARD")?;
+        let targets = intern(&conn, "TARGETS")?;
+        let incr = intern(&conn, "INCR")?;
+        let property = intern(&conn, "COPAS_SELECTION")?;
+        Ok(Session {
+            conn,
+            window,
+            clipboard,
+            targets,
+            incr,
+            property,
+        })
+    }
+
+    /// WM_CLASS of the CLIPBOARD owner window. Toolkits often own the
+    /// selection from a hidden window without one, so fall back to the active
+    /// window when allowed.
+    pub fn source_app(use_active_window: bool) -> Result<Option<String>, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(err)?;
+        let root = conn.setup().roots[screen_num].root;
+        let clipboard = intern(&conn, "CLIPBOARD")?;
+        let owner = conn
+            .get_selection_owner(clipboard)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        if owner != NONE {
+            if let Some(class) = wm_class(&conn, owner) {
+                return Ok(Some(class));
+            }
+        }
+        if !use_active_window {
+            return Ok(None);
+        }
+
+        let active_atom = intern(&conn, "_NET_ACTIVE_WINDOW")?;
+        let active = conn
+            .get_property(false, root, active_atom, AtomEnum::WINDOW, 0, 1)
+            .map_err(err)?
+            .reply()
+            .ok()
+            .and_then(|reply| reply.value32()?.next())
+            .filter(|w| *w != NONE);
+        Ok(active.and_then(|window| wm_class(&conn, window)))
+    }
+
+    /// WM_CLASS holds the instance and class names, NUL separated. The class
+    /// ("Firefox", "KeePassXC") is the readable one.
+    fn wm_class(conn: &RustConnection, window: Window) -> Option<String> {
+        let reply = conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+            .ok()?
+            .reply()
+            .ok()?;
+        let mut parts = reply.value.split(|b| *b == 0).filter(|p| !p.is_empty());
+        let instance = parts.next()?;
+        let class = parts.next().unwrap_or(instance);
+        Some(String::from_utf8_lossy(class).into_owned())
+    }
+
+    fn intern(conn: &RustConnection, name: &str) -> Result<Atom, String> {
+        Ok(conn
+            .intern_atom(false, name.as_bytes())
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .atom)
+    }
+
+    pub fn read_flavors(wanted: &dyn Fn(&str) -> bool) -> Result<Vec<Flavor>, String> {
+        let s = open()?;
+        let targets = match convert(&s, s.targets)? {
+            Some(data) => data,
+            None => return Ok(Vec::new()),
+        };
+        let atoms: Vec<Atom> = targets
+            .chunks_exact(4)
+            .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+
+        let mut flavors = Vec::new();
+        for atom in atoms {
+            let name = match s.conn.get_atom_name(atom).map_err(err)?.reply() {
+                Ok(reply) => String::from_utf8_lossy(&reply.name).into_owned(),
+                Err(_) => continue,
+            };
+            if !wanted(&name) {
+                continue;
+            }
+            match convert(&s, atom) {
+                Ok(Some(data)) if !data.is_empty() => flavors.push(Flavor { mime: name, data }),
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read clipboard flavor {}: {}", name, e),
+            }
+        }
+        Ok(flavors)
+    }
+
+    /// Ask the owner to convert the selection to `target` and read the result,
+    /// following the INCR protocol for large payloads
+    fn convert(s: &Session, target: Atom) -> Result<Option<Vec<u8>>, String> {
+        s.conn
+            .convert_selection(s.window, s.clipboard, target, s.property, CURRENT_TIME)
+            .map_err(err)?;
+        s.conn.flush().map_err(err)?;
+
+        let mut deadline = Instant::now() + TIMEOUT;
+        loop {
+            match s.conn.poll_for_event().map_err(err)? {
+                Some(Event::SelectionNotify(ev)) if ev.requestor == s.window => {
+                    if ev.property == NONE {
+                        return Ok(None);
+                    }
+                    break;
+                }
+                Some(_) => {}
+                None => wait_or_timeout(deadline)?,
+            }
+        }
+
+        let reply = s
+            .conn
+            .get_property(true, s.window, s.property, AtomEnum::ANY, 0, u32::MAX / 4)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?;
+        if reply.type_ != s.incr {
+            return Ok(Some(reply.value));
+        }
+
+        // Large payload: the owner sends chunks, each announced by a PropertyNotify,
+        // and ends with an empty one
+        s.conn.flush().map_err(err)?;
+        let mut data = Vec::new();
+        deadline = Instant::now() + TIMEOUT;
+        loop {
+            match s.conn.poll_for_event().map_err(err)? {
+                Some(Event::PropertyNotify(ev))
+                    if ev.window == s.window
+                        && ev.atom == s.property
+                        && ev.state == Property::NEW_VALUE =>
+                {
+                    let chunk = s
+                        .conn
+                        .get_property(true, s.window, s.property, AtomEnum::ANY, 0, u32::MAX / 4)
+                        .map_err(err)?
+                        .reply()
+                        .map_err(err)?;
+                    s.conn.flush().map_err(err)?;
+                    if chunk.value.is_empty() {
+                        return Ok(Some(data));
+                    }
+                    data.extend_from_slice(&chunk.value);
+                    deadline = Instant::now() + TIMEOUT;
+                }
+                Some(_) => {}
+                None => wait_or_timeout(deadline)?,
+            }
+        }
+    }
+
+    fn wait_or_timeout(deadline: Instant) -> Result<(), String> {
+        if Instant::now() > deadline {
+            return Err("clipboard owner did not respond".into());
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(())
+    }
+
+    pub fn write_flavors(flavors: Vec<Flavor>) -> Result<(), String> {
+        let s = open()?;
+
+        // Payloads are sent in a single property write, so skip anything over the request limit
+        let max_bytes = s.conn.maximum_request_bytes().saturating_sub(64);
+        let mut served = Vec::new();
+        for flavor in flavors {
+            if flavor.data.len() > max_bytes {
+                warn!("Skipping {} flavor of {} bytes", flavor.mime, flavor.data.len());
+                continue;
+            }
+            served.push((intern(&s.conn, &flavor.mime)?, flavor.data));
+        }
+
+        s.conn
+            .set_selection_owner(s.window, s.clipboard, CURRENT_TIME)
+            .map_err(err)?;
+        let owner = s
+            .conn
+            .get_selection_owner(s.clipboard)
+            .map_err(err)?
+            .reply()
+            .map_err(err)?
+            .owner;
+        if owner != s.window {
+            return Err("could not take clipboard ownership".into());
+        }
+
+        std::thread::spawn(move || serve(s, served));
+        Ok(())
+    }
+
+    /// Answer selection requests until another client takes the clipboard
+    fn serve(s: Session, served: Vec<(Atom, Vec<u8>)>) {
+        loop {
+            let event = match s.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("X11 clipboard owner stopped: {}", e);
+                    return;
+                }
+            };
+            match event {
+                Event::SelectionRequest(req) => {
+                    // Obsolete clients leave the property unset and expect the target name
+                    let property = if req.property == NONE { req.target } else { req.property };
+                    let ok = if req.target == s.targets {
+                        let mut atoms = vec![s.targets];
+                        atoms.extend(served.iter().map(|(atom, _)| *atom));
+                        s.conn
+                            .change_property32(PropMode::REPLACE, req.requestor, property, AtomEnum::ATOM, &atoms)
+                            .is_ok()
+                    } else if let Some((_, data)) = served.iter().find(|(atom, _)| *atom == req.target) {
+                        s.conn
+                            .change_property8(PropMode::REPLACE, req.requestor, property, req.target, data)
+                            .is_ok()
+                    } else {
+                        false
+                    };
+
+                    let notify = SelectionNotifyEvent {
+                        response_type: SELECTION_NOTIFY_EVENT,
+                        sequence: 0,
+                        time: req.time,
+                        requestor: req.requestor,
+                        selection: req.selection,
+                        target: req.target,
+                        property: if ok { property } else { NONE },
+                    };
+                    let _ = s.conn.send_event(false, req.requestor, EventMask::NO_EVENT, notify);
+                    let _ = s.conn.flush();
+                }
+                // Someone else copied
+                Event::SelectionClear(_) => return,
+                _ => {}
+            }
+        }
+    }
+}
+
+// ─── Wayland: ext/wlr data-control ────────────────────────────────────────────
+
+mod wayland {
+    use super::{err, Flavor};
+    use log::warn;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::os::fd::{AsFd, BorrowedFd};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+    use wayland_client::backend::ObjectId;
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::{wl_registry, wl_seat};
+    use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+    use wayland_protocols::ext::data_control::v1::client::{
+        ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+        ext_data_control_manager_v1::ExtDataControlManagerV1,
+        ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+        ext_data_control_source_v1::{self, ExtDataControlSourceV1},
+    };
+    use wayland_protocols_wlr::data_control::v1::client::{
+        zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+        zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+    };
+
+    #[derive(Default)]
+    struct State {
+        /// Offer id -> MIME types it advertised
+        mimes: HashMap<ObjectId, Vec<String>>,
+        /// Current CLIPBOARD selection
+        selection: Option<Offer>,
+        /// Payloads served while we own the selection
+        serving: Vec<Flavor>,
+        cancelled: bool,
+    }
+
+    enum Offer {
+        Ext(ExtDataControlOfferV1),
+        Wlr(ZwlrDataControlOfferV1),
+    }
+
+    impl Offer {
+        fn id(&self) -> ObjectId {
+            match self {
+                Offer::Ext(o) => o.id(),
+                Offer::Wlr(o) => o.id(),
+            }
+        }
+
+        fn receive(&self, mime: String, fd: BorrowedFd) {
+            match self {
+                Offer::Ext(o) => o.receive(mime, fd),
+                Offer::Wlr(o) => o.receive(mime, fd),
+            }
+        }
+
+        fn destroy(&self) {
+            match self {
+                Offer::Ext(o) => o.destroy(),
+                Offer::Wlr(o) => o.destroy(),
+            }
+        }
+    }
+
+    enum Control {
+        Ext(ExtDataControlManagerV1, ExtDataControlDeviceV1),
+        Wlr(ZwlrDataControlManagerV1, ZwlrDataControlDeviceV1),
+    }
+
+    struct Session {
+        conn: Connection,
+        queue: EventQueue<State>,
+        state: State,
+        control: Control,
+    }
+
+    /// Bind a data-control device on the first seat and collect the current selection
+    fn open() -> Result<Session, String> {
+        let conn = Connection::connect_to_env().map_err(err)?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(err)?;
+        let qh = queue.handle();
+
+        let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).map_err(err)?;
+        let control = if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(&qh, 1..=1, ()) {
+            let device = manager.get_data_device(&seat, &qh, ());
+            Control::Ext(manager, device)
+        } else if let Ok(manager) = globals.bind::<ZwlrDataControlManagerV1, _, _>(&qh, 1..=2, ()) {
+            let device = manager.get_data_device(&seat, &qh, ());
+            Control::Wlr(manager, device)
+        } else {
+            return Err("compositor does not support data-control".into());
+        };
+
+        // The device reports the current selection right after creation
+        let mut state = State::default();
+        queue.roundtrip(&mut state).map_err(err)?;
+        Ok(Session {
+            conn,
+            queue,
+            state,
+            control,
+        })
+    }
+
+    pub fn read_flavors(wanted: &dyn Fn(&str) -> bool) -> Result<Vec<Flavor>, String> {
+        let mut s = open()?;
+        let offer = match s.state.selection.take() {
+            Some(offer) => offer,
+            None => return Ok(Vec::new()),
+        };
+        let mimes = s.state.mimes.remove(&offer.id()).unwrap_or_default();
+
+        let mut flavors = Vec::new();
+        for mime in mimes {
+            if !wanted(&mime) {
+                continue;
+            }
+            let (mut reader, writer) = UnixStream::pair().map_err(err)?;
+            offer.receive(mime.clone(), writer.as_fd());
+            s.conn.flush().map_err(err)?;
+            // Close our end so we see EOF once the source is done writing
+            drop(writer);
+
+            reader.set_read_timeout(Some(Duration::from_secs(2))).ok();
+            let mut data = Vec::new();
+            match reader.read_to_end(&mut data) {
+                Ok(_) if !data.is_empty() => flavors.push(Flavor { mime, data }),
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read clipboard flavor {}: {}", mime, e),
+            }
+        }
+        offer.destroy();
+        Ok(flavors)
+    }
+
+    pub fn write_flavors(flavors: Vec<Flavor>) -> Result<(), String> {
+        let mut s = open()?;
+        let qh = s.queue.handle();
+        if let Some(offer) = s.state.selection.take() {
+            offer.destroy();
+        }
+
+        match &s.control {
+            Control::Ext(manager, device) => {
+                let source = manager.create_data_source(&qh, ());
+                for flavor in &flavors {
+                    source.offer(flavor.mime.clone());
+                }
+                device.set_selection(Some(&source));
+            }
+            Control::Wlr(manager, device) => {
+                let source = manager.create_data_source(&qh, ());
+                for flavor in &flavors {
+                    source.offer(flavor.mime.clone());
+                }
+                device.set_selection(Some(&source));
+            }
+        }
+        s.conn.flush().map_err(err)?;
+        s.state.serving = flavors;
+
+        // Serve paste requests until another client sets the selection
+        std::thread::spawn(move || {
+            let mut s = s;
+            while !s.state.cancelled {
+                if let Err(e) = s.queue.blocking_dispatch(&mut s.state) {
+                    warn!("Wayland clipboard source stopped: {}", e);
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn send(state: &State, mime: &str, fd: std::os::fd::OwnedFd) {
+        if let Some(flavor) = state.serving.iter().find(|f| f.mime == mime) {
+            let mut file = std::fs::File::from(fd);
+            if let Err(e) = file.write_all(&flavor.data) {
+                warn!("Failed to send clipboard flavor {}: {}", mime, e);
+            }
+        }
+    }
+
+    impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &wl_registry::WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<wl_seat::WlSeat, ()> for State {
+        fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+    }
+
+    /// Both protocols have identical shapes, so generate the handlers for each
+    macro_rules! data_control_dispatch {
+        ($variant:ident, $manager:ty, $device:ty, $device_mod:ident, $offer:ty, $offer_mod:ident, $source:ty, $source_mod:ident) => {
+            impl Dispatch<$manager, ()> for State {
+                fn event(
+                    _: &mut Self,
+                    _: &$manager,
+                    _: <$manager as Proxy>::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                }
+            }
+
+            impl Dispatch<$device, ()> for State {
+                fn event(
+                    state: &mut Self,
+                    _: &$device,
+                    event: $device_mod::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                    match event {
+                        $device_mod::Event::DataOffer { id } => {
+                            state.mimes.insert(id.id(), Vec::new());
+                        }
+                        $device_mod::Event::Selection { id } => {
+                            if let Some(old) = state.selection.take() {
+                                state.mimes.remove(&old.id());
+                                old.destroy();
+                            }
+                            state.selection = id.map(Offer::$variant);
+                        }
+                        $device_mod::Event::PrimarySelection { id: Some(offer) } => {
+                            state.mimes.remove(&offer.id());
+                            offer.destroy();
+                        }
+                        _ => {}
+                    }
+                }
+
+                event_created_child!(State, $device, [
+                    $device_mod::EVT_DATA_OFFER_OPCODE => ($offer, ()),
+                ]);
+            }
+
+            impl Dispatch<$offer, ()> for State {
+                fn event(
+                    state: &mut Self,
+                    offer: &$offer,
+                    event: $offer_mod::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                    if let $offer_mod::Event::Offer { mime_type } = event {
+                        state.mimes.entry(offer.id()).or_default().push(mime_type);
+                    }
+                }
+            }
+
+            impl Dispatch<$source, ()> for State {
+                fn event(
+                    state: &mut Self,
+                    source: &$source,
+                    event: $source_mod::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                    match event {
+                        $source_mod::Event::Send { mime_type, fd } => send(state, &mime_type, fd),
+                        $source_mod::Event::Cancelled => {
+                            source.destroy();
+                            state.cancelled = true;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        };
+    }
+
+    data_control_dispatch!(
+        Ext,
+        ExtDataControlManagerV1,
+        ExtDataControlDeviceV1,
+        ext_data_control_device_v1,
+        ExtDataControlOfferV1,
+        ext_data_control_offer_v1,
+        ExtDataControlSourceV1,
+        ext_data_control_source_v1
+    );
+    data_control_dispatch!(
+        Wlr,
+        ZwlrDataControlManagerV1,
+        ZwlrDataControlDeviceV1,
+        zwlr_data_control_device_v1,
+        ZwlrDataControlOfferV1,
+        zwlr_data_control_offer_v1,
+        ZwlrDataControlSourceV1,
+        zwlr_data_control_source_v1
+    );
+}
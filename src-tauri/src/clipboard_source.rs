@@ -0,0 +1,382 @@
+use log::{info, warn};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// Quiet time after a change event before the watcher reads the clipboard.
+/// Apps often set the clipboard several times in a row (once per format, or
+/// clear then set), and reading in between can catch it half written.
+pub const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Longest a steady stream of change events can hold back a read
+pub const MAX_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Tells the clipboard watcher when the clipboard may have changed.
+///
+/// The watcher blocks on `wait` and reads the clipboard after it returns,
+/// so event-driven sources only wake it up on real changes.
+pub trait ChangeSource: Send {
+    /// Block until the clipboard may have changed.
+    /// An error means the source is gone and the watcher should fall back to polling.
+    fn wait(&mut self) -> Result<(), String>;
+
+    /// Short name for logging
+    fn name(&self) -> &'static str;
+}
+
+/// Starts a platform listener thread that sends on every clipboard change,
+/// and returns the name of the source. Takes `watch_primary`.
+pub type Listener = fn(Sender<()>, bool) -> Result<&'static str, String>;
+
+/// Pick the best change source for this platform, falling back to polling.
+/// With `watch_primary`, Linux sources also wake on PRIMARY selection changes.
+pub fn detect(poll_ms: u64, watch_primary: bool) -> Box<dyn ChangeSource> {
+    #[allow(unused_mut)]
+    let mut listeners: Vec<(&str, Listener)> = Vec::new();
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            listeners.push(("Wayland", wayland::spawn));
+        }
+        if std::env::var_os("DISPLAY").is_some() {
+            listeners.push(("X11", x11::spawn));
+        }
+    }
+    first_available(&listeners, poll_ms, watch_primary)
+}
+
+/// An event source from the first of `listeners` that starts, or polling if
+/// none does. Either way, polling takes over if the events stop.
+pub fn first_available(listeners: &[(&str, Listener)], poll_ms: u64, watch_primary: bool) -> Box<dyn ChangeSource> {
+    for (platform, spawn) in listeners {
+        let (tx, rx) = std::sync::mpsc::channel();
+        match spawn(tx, watch_primary) {
+            Ok(name) => return Box::new(Fallback::new(Box::new(EventSource::new(rx, name)), poll_ms)),
+            Err(e) => info!("{} clipboard events unavailable: {}", platform, e),
+        }
+    }
+    Box::new(PollingSource::new(poll_ms))
+}
+
+/// Wakes the watcher every `interval`
+pub struct PollingSource {
+    interval: Duration,
+}
+
+impl PollingSource {
+    pub fn new(poll_ms: u64) -> Self {
+        Self {
+            interval: Duration::from_millis(poll_ms.max(50)),
+        }
+    }
+}
+
+impl ChangeSource for PollingSource {
+    fn wait(&mut self) -> Result<(), String> {
+        std::thread::sleep(self.interval);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+}
+
+/// Wakes the watcher when a platform listener thread reports a change
+pub struct EventSource {
+    rx: Receiver<()>,
+    name: &'static str,
+}
+
+impl EventSource {
+    pub fn new(rx: Receiver<()>, name: &'static str) -> Self {
+        Self { rx, name }
+    }
+}
+
+impl ChangeSource for EventSource {
+    fn wait(&mut self) -> Result<(), String> {
+        self.rx
+            .recv()
+            .map_err(|_| format!("{} listener stopped", self.name))?;
+        // Coalesce a burst of notifications into a single read, once the
+        // clipboard has been quiet for `DEBOUNCE`
+        let deadline = Instant::now() + MAX_DEBOUNCE;
+        loop {
+            let quiet = DEBOUNCE.min(deadline.saturating_duration_since(Instant::now()));
+            match self.rx.recv_timeout(quiet) {
+                Ok(()) if Instant::now() < deadline => continue,
+                // Quiet, waited long enough or the listener is gone: the
+                // change already seen is still worth reading
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Switches to polling every `poll_ms` once `source` fails
+pub struct Fallback {
+    source: Box<dyn ChangeSource>,
+    poll_ms: u64,
+}
+
+impl Fallback {
+    pub fn new(source: Box<dyn ChangeSource>, poll_ms: u64) -> Self {
+        Self { source, poll_ms }
+    }
+}
+
+impl ChangeSource for Fallback {
+    fn wait(&mut self) -> Result<(), String> {
+        if let Err(e) = self.source.wait() {
+            warn!("Clipboard change source failed: {}, falling back to polling every {}ms", e, self.poll_ms);
+            self.source = Box::new(PollingSource::new(self.poll_ms));
+            return self.source.wait();
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        self.source.name()
+    }
+}
+
+// ─── X11: XFixes selection events ─────────────────────────────────────────────
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use log::warn;
+    use std::sync::mpsc::Sender;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, CreateWindowAux, WindowClass};
+    use x11rb::protocol::Event;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    /// Listen for CLIPBOARD (and optionally PRIMARY) owner changes on a hidden window
+    pub fn spawn(tx: Sender<()>, watch_primary: bool) -> Result<&'static str, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        conn.xfixes_query_version(5, 0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+
+        let screen = &conn.setup().roots[screen_num];
+        let window = conn.generate_id().map_err(|e| e.to_string())?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            screen.root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new(),
+        )
+        .map_err(|e| e.to_string())?;
+
+        let clipboard = conn
+            .intern_atom(false, b"CLIPBOARD")
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?
+            .atom;
+        let mask = SelectionEventMask::SET_SELECTION_OWNER
+            | SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+        conn.xfixes_select_selection_input(window, clipboard, mask)
+            .map_err(|e| e.to_string())?;
+        if watch_primary {
+            conn.xfixes_select_selection_input(window, AtomEnum::PRIMARY.into(), mask)
+                .map_err(|e| e.to_string())?;
+        }
+        conn.flush().map_err(|e| e.to_string())?;
+
+        std::thread::spawn(move || loop {
+            match conn.wait_for_event() {
+                Ok(Event::XfixesSelectionNotify(_)) => {
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("X11 clipboard listener stopped: {}", e);
+                    break;
+                }
+            }
+        });
+
+        Ok("x11-xfixes")
+    }
+}
+
+// ─── Wayland: ext/wlr data-control selection events ───────────────────────────
+
+#[cfg(target_os = "linux")]
+mod wayland {
+    use log::warn;
+    use std::sync::mpsc::Sender;
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::{wl_registry, wl_seat};
+    use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+    use wayland_protocols::ext::data_control::v1::client::{
+        ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+        ext_data_control_manager_v1::ExtDataControlManagerV1,
+        ext_data_control_offer_v1::ExtDataControlOfferV1,
+    };
+    use wayland_protocols_wlr::data_control::v1::client::{
+        zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    };
+
+    struct State {
+        tx: Sender<()>,
+        running: bool,
+        watch_primary: bool,
+    }
+
+    impl State {
+        fn changed(&mut self) {
+            if self.tx.send(()).is_err() {
+                self.running = false;
+            }
+        }
+    }
+
+    /// Listen for selection changes through a data-control device on the first seat.
+    /// Prefers the standard ext protocol and falls back to the wlroots one.
+    pub fn spawn(tx: Sender<()>, watch_primary: bool) -> Result<&'static str, String> {
+        let conn = Connection::connect_to_env().map_err(|e| e.to_string())?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(|e| e.to_string())?;
+        let qh = queue.handle();
+
+        let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).map_err(|e| e.to_string())?;
+        let name = if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(&qh, 1..=1, ()) {
+            manager.get_data_device(&seat, &qh, ());
+            "wayland-ext-data-control"
+        } else if let Ok(manager) = globals.bind::<ZwlrDataControlManagerV1, _, _>(&qh, 1..=2, ()) {
+            manager.get_data_device(&seat, &qh, ());
+            "wayland-wlr-data-control"
+        } else {
+            return Err("compositor does not support data-control".into());
+        };
+
+        let mut state = State {
+            tx,
+            running: true,
+            watch_primary,
+        };
+        std::thread::spawn(move || {
+            let _conn = conn;
+            while state.running {
+                if let Err(e) = queue.blocking_dispatch(&mut state) {
+                    warn!("Wayland clipboard listener stopped: {}", e);
+                    break;
+                }
+            }
+        });
+
+        Ok(name)
+    }
+
+    impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &wl_registry::WlRegistry,
+            _: wl_registry::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<wl_seat::WlSeat, ()> for State {
+        fn event(_: &mut Self, _: &wl_seat::WlSeat, _: wl_seat::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+    }
+
+    /// Both protocols have identical shapes, so generate the handlers for each
+    macro_rules! data_control_dispatch {
+        ($manager:ty, $device:ty, $offer:ty, $device_mod:ident) => {
+            impl Dispatch<$manager, ()> for State {
+                fn event(
+                    _: &mut Self,
+                    _: &$manager,
+                    _: <$manager as Proxy>::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                }
+            }
+
+            impl Dispatch<$device, ()> for State {
+                fn event(
+                    state: &mut Self,
+                    _: &$device,
+                    event: $device_mod::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                    match event {
+                        $device_mod::Event::Selection { id } => {
+                            // We only need the notification; the clipboard backend reads the data itself
+                            if let Some(offer) = id {
+                                offer.destroy();
+                            }
+                            state.changed();
+                        }
+                        $device_mod::Event::PrimarySelection { id } => {
+                            if let Some(offer) = id {
+                                offer.destroy();
+                            }
+                            if state.watch_primary {
+                                state.changed();
+                            }
+                        }
+                        $device_mod::Event::Finished => state.running = false,
+                        _ => {}
+                    }
+                }
+
+                event_created_child!(State, $device, [
+                    $device_mod::EVT_DATA_OFFER_OPCODE => ($offer, ()),
+                ]);
+            }
+
+            impl Dispatch<$offer, ()> for State {
+                fn event(
+                    _: &mut Self,
+                    _: &$offer,
+                    _: <$offer as Proxy>::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<Self>,
+                ) {
+                }
+            }
+        };
+    }
+
+    data_control_dispatch!(
+        ExtDataControlManagerV1,
+        ExtDataControlDeviceV1,
+        ExtDataControlOfferV1,
+        ext_data_control_device_v1
+    );
+    data_control_dispatch!(
+        ZwlrDataControlManagerV1,
+        ZwlrDataControlDeviceV1,
+        ZwlrDataControlOfferV1,
+        zwlr_data_control_device_v1
+    );
+}
@@ -1,342 +1,647 @@
-use crate::models::{Item, ItemKind};
+use crate::classifier;
+use crate::clipboard_backend::{self, ClipboardBackend, Flavor, TEXT_HTML, TEXT_RTF};
+use crate::clipboard_source;
+use crate::files;
+use crate::image_hash;
+use crate::sensitive;
+use crate::models::{ConcealedMode, Item, ItemKind};
+use crate::rules::TabRules;
 use crate::storage::Storage;
-use arboard::Clipboard;
 use image::ImageEncoder;
 use log::{error, info, warn};
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Duration;
 use tauri::{AppHandle, Emitter};
 
-/// Read HTML content from macOS clipboard (if available)
-#[cfg(target_os = "macos")]
-fn get_clipboard_html() -> Option<String> {
-    // Use osascript to read HTML from clipboard
-    let output = std::process::Command::new("osascript")
-        .arg("-e")
-        .arg(r#"try
-            set htmlData to the clipboard as «class HTML»
-            set htmlText to do shell script "echo " & quoted form of (htmlData as text) & " | perl -pe 's/«data HTML//; s/»//; s/([0-9A-Fa-f]{2})/chr(hex($1))/ge'"
-            return htmlText
-        on error
-            return ""
-        end try"#)
-        .output()
-        .ok()?;
-    
-    let html = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if html.is_empty() || html.len() < 10 {
-        None
-    } else {
-        Some(html)
-    }
+/// Larger flavors (huge images, videos) aren't worth keeping a copy of
+const MAX_FLAVOR_BYTES: usize = 32 * 1024 * 1024;
+
+/// How often expired sensitive items are looked for
+const EXPIRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Start clipboard monitoring in a background task
+pub fn start_clipboard_watcher(app_handle: AppHandle, storage: Arc<Storage>) {
+    let (poll_ms, watch_primary) = {
+        let data = storage.data.lock().unwrap();
+        (data.settings.poll_interval, data.settings.capture_primary_selection)
+    };
+
+    std::thread::spawn(move || {
+        let backend = match clipboard_backend::system() {
+            Ok(b) => b,
+            Err(e) => {
+                error!("Failed to access clipboard: {}", e);
+                return;
+            }
+        };
+
+        let mut watcher = ClipboardWatcher::new(storage, backend, move |item| {
+            if let Err(e) = app_handle.emit("clipboard-updated", item.masked()) {
+                warn!("Failed to emit clipboard-updated: {}", e);
+            }
+        });
+
+        let mut source = clipboard_source::detect(poll_ms, watch_primary);
+        info!("Clipboard watcher started ({})", source.name());
+
+        // Event sources fall back to polling, which never stops
+        while source.wait().is_ok() {
+            watcher.check();
+        }
+    });
 }
 
-/// Fallback for non-macOS: try reading HTML via alternative method
-#[cfg(not(target_os = "macos"))]
-fn get_clipboard_html() -> Option<String> {
-    None
+/// Periodically remove expired items and tell the UI (`items-expired`)
+pub fn start_expiry_sweeper(app_handle: AppHandle, storage: Arc<Storage>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(EXPIRY_INTERVAL);
+        let expired = storage.expire_items();
+        if !expired.is_empty() {
+            info!("Removed {} expired item(s)", expired.len());
+            if let Err(e) = app_handle.emit("items-expired", &expired) {
+                warn!("Failed to emit items-expired: {}", e);
+            }
+        }
+    });
 }
 
-/// Detect content category heuristic
-pub fn detect_category(text: &str) -> &'static str {
-    let t = text.trim();
-    if t.is_empty() {
-        return "text";
+/// Reads the clipboard through a `ClipboardBackend` and records new text and
+/// images into storage. `check` does a single read; the caller decides when
+/// to call it (on a change event or a poll tick).
+pub struct ClipboardWatcher {
+    storage: Arc<Storage>,
+    backend: Box<dyn ClipboardBackend>,
+    /// Called with every new or bumped item (the app emits `clipboard-updated`)
+    on_item: Box<dyn Fn(&Item) + Send>,
+    last_text_hash: Option<Vec<u8>>,
+    last_image_hash: Option<Vec<u8>>,
+    last_files_hash: Option<Vec<u8>>,
+    last_primary_hash: Option<Vec<u8>>,
+    /// Compiled tab rules and the `Storage::tabs_version` they were built at
+    tab_rules: Option<(u64, TabRules)>,
+}
+
+impl ClipboardWatcher {
+    pub fn new(
+        storage: Arc<Storage>,
+        mut backend: Box<dyn ClipboardBackend>,
+        on_item: impl Fn(&Item) + Send + 'static,
+    ) -> Self {
+        // Get initial clipboard text hash so we don't record what was there at startup
+        let last_text_hash = backend.get_text().map(|text| hash_bytes(text.as_bytes()));
+        let last_files_hash = backend
+            .get_files()
+            .map(|paths| hash_bytes(files::paths_text(&paths).as_bytes()));
+        let last_primary_hash = backend.get_primary_text().map(|text| hash_bytes(text.as_bytes()));
+        Self {
+            storage,
+            backend,
+            on_item: Box::new(on_item),
+            last_text_hash,
+            last_image_hash: None,
+            last_files_hash,
+            last_primary_hash,
+            tab_rules: None,
+        }
+    }
+
+    /// Read the clipboard once and record anything new
+    pub fn check(&mut self) {
+        // Skip if paste is in progress — but still update our hash tracking
+        // so we don't re-detect the pasted content after the flag clears
+        if crate::paste::PASTE_IN_PROGRESS.load(std::sync::atomic::Ordering::SeqCst) {
+            if let Some(text) = self.backend.get_text() {
+                self.last_text_hash = Some(hash_bytes(text.as_bytes()));
+            }
+            if let Some(img) = self.backend.get_image() {
+                self.last_image_hash = Some(hash_bytes(&img.bytes));
+            }
+            if let Some(paths) = self.backend.get_files() {
+                self.last_files_hash = Some(hash_bytes(files::paths_text(&paths).as_bytes()));
+            }
+            return;
+        }
+        // Nothing is captured until the database is unlocked
+        if self.storage.is_locked() {
+            return;
+        }
+
+        if self.check_files() {
+            return;
+        }
+        if self.check_text() {
+            return;
+        }
+        self.check_image();
+        self.check_primary();
+    }
+
+    /// Read every clipboard flavor worth keeping
+    fn read_flavors(&mut self) -> Vec<Flavor> {
+        self.backend
+            .get_flavors()
+            .into_iter()
+            .filter(|f| !f.data.is_empty() && f.data.len() <= MAX_FLAVOR_BYTES)
+            .collect()
     }
 
-    // URL
-    if t.starts_with("http://") || t.starts_with("https://") || t.starts_with("www.") {
-        if !t.contains(char::is_whitespace) {
-            return "link";
+    /// The app new clipboard contents came from. `Err` names an app whose
+    /// copies are ignored (see `Settings::records_app`).
+    fn source_app(&mut self) -> Result<Option<String>, String> {
+        let app = self.backend.source_app();
+        if self.storage.data.lock().unwrap().settings.records_app(app.as_deref()) {
+            Ok(app)
+        } else {
+            Err(app.unwrap_or_else(|| "(unknown)".into()))
         }
     }
 
-    // Email
-    if !t.contains(char::is_whitespace) && t.contains('@') && t.contains('.') {
-        let parts: Vec<&str> = t.split('@').collect();
-        if parts.len() == 2 && !parts[0].is_empty() && parts[1].contains('.') {
-            return "email";
+    /// Record a copied file list. Returns true while files are on the
+    /// clipboard: the text form is just their paths and shouldn't be
+    /// recorded separately.
+    fn check_files(&mut self) -> bool {
+        let paths = match self.backend.get_files() {
+            Some(paths) => paths,
+            None => {
+                // Forget the last list so copying the same files again bumps them
+                self.last_files_hash = None;
+                return false;
+            }
+        };
+        let text = files::paths_text(&paths);
+        let hash = hash_bytes(text.as_bytes());
+        // The platform usually also offers the paths as text
+        if let Some(current) = self.backend.get_text() {
+            self.last_text_hash = Some(hash_bytes(current.as_bytes()));
+        }
+
+        if self.last_files_hash.as_ref() == Some(&hash) {
+            return true;
+        }
+        self.last_files_hash = Some(hash.clone());
+
+        // Skip if this matches the last-pasted content hash
+        if let Ok(lph) = crate::paste::LAST_PASTE_HASH.lock() {
+            if lph.as_ref() == Some(&hash) {
+                return true;
+            }
         }
+
+        let source_app = match self.source_app() {
+            Ok(app) => app,
+            Err(app) => {
+                info!("Ignoring files copied from excluded app {}", app);
+                return true;
+            }
+        };
+
+        // Same file list copied before — move it to top
+        {
+            let mut data = self.storage.data.lock().unwrap();
+            if let Some(item) = data
+                .items
+                .iter_mut()
+                .find(|i| i.kind == ItemKind::Files && i.content_text.as_deref() == Some(text.as_str()))
+            {
+                item.timestamp = chrono::Utc::now().to_rfc3339();
+                let updated_item = item.clone();
+                self.storage.save_item(item);
+                drop(data);
+                (self.on_item)(&updated_item);
+                return true;
+            }
+        }
+
+        let flavors = self.read_flavors();
+        let mut item = Item {
+            id: new_item_id(),
+            kind: ItemKind::Files,
+            content_text: Some(text),
+            content_html: None,
+            content_rtf: None,
+            image_path: None,
+            image_width: None,
+            image_height: None,
+            image_size: None,
+            image_dhash: None,
+            mime: None,
+            files: files::entries_from_paths(&paths),
+            flavors: self.storage.store_flavors(&flavors),
+            source_app,
+            category: "file".into(),
+            tags: Vec::new(),
+            tab_ids: Vec::new(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            pinned: false,
+            label: String::new(),
+            content: None,
+            in_vault: false,
+            sealed: None,
+            paste_count: 0,
+            sensitive: false,
+            expires_at: None,
+        };
+
+        self.add_item(&mut item);
+        (self.on_item)(&item);
+        true
     }
 
-    // Phone
-    let cleaned: String = t.chars().filter(|c| c.is_ascii_digit()).collect();
-    if (7..=15).contains(&cleaned.len()) && t.chars().all(|c| "0123456789 -+().".contains(c)) {
-        return "phone";
+    /// Record new clipboard text. Returns true when the rest of this check
+    /// should be skipped (our own paste, or a duplicate that was bumped).
+    fn check_text(&mut self) -> bool {
+        let text = match self.backend.get_text() {
+            Some(text) => text,
+            None => return false,
+        };
+        let hash = hash_bytes(text.as_bytes());
+
+        // Skip if this matches the last-pasted content hash
+        if let Ok(lph) = crate::paste::LAST_PASTE_HASH.lock() {
+            if lph.as_ref() == Some(&hash) {
+                // This clipboard content was set by our paste operation — skip
+                self.last_text_hash = Some(hash);
+                return true;
+            }
+        }
+
+        if self.last_text_hash.as_ref() == Some(&hash) {
+            return false;
+        }
+        self.last_text_hash = Some(hash);
+
+        let source_app = match self.source_app() {
+            Ok(app) => app,
+            Err(app) => {
+                info!("Ignoring text copied from excluded app {}", app);
+                return true;
+            }
+        };
+        // Text a password manager marked as concealed is skipped, or kept
+        // like any other secret, depending on settings
+        if self.backend.is_concealed() {
+            if self.storage.data.lock().unwrap().settings.concealed_clipboard == ConcealedMode::Skip {
+                info!("Ignoring concealed clipboard content");
+            } else {
+                self.record_sensitive(text, source_app, "concealed");
+            }
+            return true;
+        }
+        self.record_text(text, source_app, true)
     }
 
-    // Code detection
-    let code_indicators = ["{", "}", "[", "]", "()", ";", "=>", "->", "fn ", "def ", "class ", "const ", "let ", "var ", "function ", "import ", "pub "];
-    let indicator_count = code_indicators.iter().filter(|ind| text.contains(*ind)).count();
-    if indicator_count >= 2 {
-        return "code";
+    /// Add a masked, memory-only item that expires after `sensitive_ttl`, or
+    /// restart the countdown of the same secret. Returns true if it was a duplicate.
+    fn record_sensitive(&mut self, text: String, source_app: Option<String>, kind: &str) -> bool {
+        info!("Recording sensitive clipboard content ({})", kind);
+        let ttl = self.storage.data.lock().unwrap().settings.sensitive_ttl;
+        let now = chrono::Utc::now();
+        let expires_at = (ttl > 0).then(|| (now + chrono::Duration::seconds(ttl as i64)).to_rfc3339());
+
+        // Copying the same secret again restarts its countdown
+        {
+            let mut data = self.storage.data.lock().unwrap();
+            if let Some(item) = data
+                .items
+                .iter_mut()
+                .find(|i| i.sensitive && i.content_text.as_deref() == Some(text.as_str()))
+            {
+                item.timestamp = now.to_rfc3339();
+                item.expires_at = expires_at;
+                let updated_item = item.clone();
+                self.storage.save_item(item);
+                drop(data);
+                (self.on_item)(&updated_item);
+                return true;
+            }
+        }
+
+        let mut item = Item {
+            id: new_item_id(),
+            kind: ItemKind::Text,
+            content_text: Some(text),
+            content_html: None,
+            content_rtf: None,
+            image_path: None,
+            image_width: None,
+            image_height: None,
+            image_size: None,
+            image_dhash: None,
+            mime: None,
+            files: Vec::new(),
+            flavors: BTreeMap::new(),
+            source_app,
+            category: "sensitive".into(),
+            tags: Vec::new(),
+            tab_ids: Vec::new(),
+            timestamp: now.to_rfc3339(),
+            pinned: false,
+            label: String::new(),
+            content: None,
+            in_vault: false,
+            sealed: None,
+            paste_count: 0,
+            sensitive: true,
+            expires_at,
+        };
+        self.add_item(&mut item);
+        (self.on_item)(&item);
+        false
     }
 
-    // Contains URLs but has other content too
-    if text.contains("http://") || text.contains("https://") {
-        return "link";
+    /// Record the PRIMARY selection as a text item, when enabled
+    fn check_primary(&mut self) {
+        if !self.storage.data.lock().unwrap().settings.capture_primary_selection {
+            return;
+        }
+        let text = match self.backend.get_primary_text() {
+            Some(text) if !text.trim().is_empty() => text,
+            _ => return,
+        };
+        let hash = hash_bytes(text.as_bytes());
+        if self.last_primary_hash.as_ref() == Some(&hash) {
+            return;
+        }
+        self.last_primary_hash = Some(hash.clone());
+        // Selecting text and then copying it shows up on both; keep one
+        if self.last_text_hash.as_ref() == Some(&hash) {
+            return;
+        }
+        // The clipboard owner stands in for the selection owner here
+        match self.source_app() {
+            Ok(app) => {
+                self.record_text(text, app, false);
+            }
+            Err(app) => info!("Ignoring selection from excluded app {}", app),
+        }
     }
 
-    "text"
-}
+    /// Add a text item, or move an existing one with the same text to the top.
+    /// Flavors are only captured for CLIPBOARD text. Returns true if it was a duplicate.
+    fn record_text(&mut self, text: String, source_app: Option<String>, from_clipboard: bool) -> bool {
+        if self.storage.data.lock().unwrap().settings.detect_sensitive {
+            if let Some(kind) = sensitive::detect(&text, source_app.as_deref()) {
+                return self.record_sensitive(text, source_app, kind);
+            }
+        }
+        let classification = classifier::classify(&text);
 
-/// Start clipboard monitoring in a background task
-pub fn start_clipboard_watcher(app_handle: AppHandle, storage: Arc<Storage>) {
-    let poll_ms = {
-        let data = storage.data.lock().unwrap();
-        data.settings.poll_interval
-    };
+        // Check if this content already exists in history — move it to top instead of adding new
+        {
+            let mut data = self.storage.data.lock().unwrap();
+            if let Some(item) = data.items.iter_mut().find(|i| {
+                i.content_text.as_deref() == Some(text.as_str())
+                    || i.content.as_deref() == Some(text.as_str())
+            }) {
+                item.timestamp = chrono::Utc::now().to_rfc3339();
+                let updated_item = item.clone();
+                self.storage.save_item(item);
+                drop(data);
+                // Emit so UI updates
+                (self.on_item)(&updated_item);
+                return true;
+            }
+        }
 
-    std::thread::spawn(move || {
-        let mut clipboard = match Clipboard::new() {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Failed to access clipboard: {}", e);
+        let (html_content, rtf_content, flavors) = if from_clipboard {
+            let flavors = self.read_flavors();
+            // Capture HTML and RTF for rich text. Backends that can't list
+            // flavors are asked for them directly.
+            let rich = |mimes: &[&str]| {
+                flavors
+                    .iter()
+                    .find(|f| mimes.contains(&f.mime.as_str()))
+                    .map(|f| clipboard_backend::decode_text(&f.data))
+                    .filter(|s| !s.trim().is_empty())
+            };
+            let (html, rtf) = if flavors.is_empty() {
+                (self.backend.get_html(), self.backend.get_rtf())
+            } else {
+                (rich(&[TEXT_HTML]), rich(&[TEXT_RTF, "application/rtf"]))
+            };
+            (html, rtf, self.storage.store_flavors(&flavors))
+        } else {
+            (None, None, BTreeMap::new())
+        };
+
+        let mut item = Item {
+            id: new_item_id(),
+            kind: ItemKind::Text,
+            content_text: Some(text.clone()),
+            content_html: html_content,
+            content_rtf: rtf_content,
+            image_path: None,
+            image_width: None,
+            image_height: None,
+            image_size: None,
+            image_dhash: None,
+            mime: None,
+            files: Vec::new(),
+            flavors,
+            source_app,
+            category: classification.category.to_string(),
+            tags: classification.tags,
+            tab_ids: Vec::new(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            pinned: false,
+            label: String::new(),
+            content: Some(text),
+            in_vault: false,
+            sealed: None,
+            paste_count: 0,
+            sensitive: false,
+            expires_at: None,
+        };
+
+        // Add to storage
+        self.add_item(&mut item);
+        (self.on_item)(&item);
+        false
+    }
+
+    /// Record a new clipboard image
+    fn check_image(&mut self) {
+        // No image in clipboard is normal. Forget the last one so copying the
+        // same image again bumps it.
+        let img_data = match self.backend.get_image() {
+            Some(img) => img,
+            None => {
+                self.last_image_hash = None;
                 return;
             }
         };
+        let hash = hash_bytes(&img_data.bytes);
+        if self.last_image_hash.as_ref() == Some(&hash) {
+            return;
+        }
+        self.last_image_hash = Some(hash);
 
-        let mut last_text_hash: Option<Vec<u8>> = None;
-        let mut last_image_hash: Option<Vec<u8>> = None;
+        let (width, height) = (img_data.width as u32, img_data.height as u32);
+        let rgba = match image::RgbaImage::from_raw(width, height, img_data.bytes) {
+            Some(rgba) => rgba,
+            None => {
+                warn!("Clipboard image buffer doesn't match its size");
+                return;
+            }
+        };
 
-        // Get initial clipboard text hash
-        if let Ok(text) = clipboard.get_text() {
-            if !text.is_empty() {
-                let mut hasher = Sha256::new();
-                hasher.update(text.as_bytes());
-                last_text_hash = Some(hasher.finalize().to_vec());
+        let source_app = match self.source_app() {
+            Ok(app) => app,
+            Err(app) => {
+                info!("Ignoring image copied from excluded app {}", app);
+                return;
             }
+        };
+
+        // The same picture re-copied (rescaled, recompressed) moves the existing item to top
+        let dhash = image_hash::dhash(&rgba);
+        if self.bump_similar_image(dhash) {
+            return;
         }
 
-        info!("Clipboard watcher started, polling every {}ms", poll_ms);
-
-        loop {
-            std::thread::sleep(Duration::from_millis(poll_ms));
-
-            // Skip if paste is in progress — but still update our hash tracking
-            // so we don't re-detect the pasted content after the flag clears
-            if crate::paste::PASTE_IN_PROGRESS.load(std::sync::atomic::Ordering::SeqCst) {
-                // Update last_text_hash to current clipboard during paste
-                if let Ok(text) = clipboard.get_text() {
-                    if !text.is_empty() {
-                        let mut hasher = Sha256::new();
-                        hasher.update(text.as_bytes());
-                        last_text_hash = Some(hasher.finalize().to_vec());
-                    }
-                }
-                // Also update image hash
-                if let Ok(img_data) = clipboard.get_image() {
-                    if !img_data.bytes.is_empty() {
-                        let mut hasher = Sha256::new();
-                        hasher.update(&img_data.bytes);
-                        last_image_hash = Some(hasher.finalize().to_vec());
-                    }
+        let id = new_item_id();
+        let flavors = self.read_flavors();
+
+        // Convert RGBA to PNG and save it to the content-addressed image store
+        let stored = encode_png(rgba.as_raw(), img_data.width, img_data.height).and_then(|png| {
+            let filename = self.storage.store_image(&id, &png)?;
+            Ok((filename, png.len() as u64))
+        });
+        match stored {
+            Ok((filename, size)) => {
+                if let Err(e) = self.storage.store_thumbnail(&filename, &rgba) {
+                    warn!("Failed to save thumbnail: {}", e);
                 }
-                continue;
+
+                let mut item = Item {
+                    id,
+                    kind: ItemKind::Image,
+                    content_text: None,
+                    content_html: None,
+                    content_rtf: None,
+                    image_path: Some(filename),
+                    image_width: Some(width),
+                    image_height: Some(height),
+                    image_size: Some(size),
+                    image_dhash: Some(image_hash::to_hex(dhash)),
+                    mime: Some("image/png".into()),
+                    files: Vec::new(),
+                    flavors: self.storage.store_flavors(&flavors),
+                    source_app,
+                    category: "image".into(),
+                    tags: Vec::new(),
+                    tab_ids: Vec::new(),
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                    pinned: false,
+                    label: String::new(),
+                    content: None,
+                    in_vault: false,
+                    sealed: None,
+                    paste_count: 0,
+                    sensitive: false,
+                    expires_at: None,
+                };
+
+                self.add_item(&mut item);
+                (self.on_item)(&item);
             }
-            // Check text
-            if let Ok(text) = clipboard.get_text() {
-                if !text.is_empty() {
-                    let mut hasher = Sha256::new();
-                    hasher.update(text.as_bytes());
-                    let hash = hasher.finalize().to_vec();
-
-                    // Skip if this matches the last-pasted content hash
-                    if let Ok(lph) = crate::paste::LAST_PASTE_HASH.lock() {
-                        if lph.as_ref() == Some(&hash) {
-                            // This clipboard content was set by our paste operation — skip
-                            last_text_hash = Some(hash);
-                            continue;
-                        }
-                    }
-
-                    if last_text_hash.as_ref() != Some(&hash) {
-                        last_text_hash = Some(hash);
-                        let category = detect_category(&text);
-                        let tab_id = if category == "link" {
-                            Some("links".to_string())
-                        } else {
-                            None
-                        };
-
-                        // Check if this content already exists in history — don't re-add duplicates
-                        {
-                            let data = storage.data.lock().unwrap();
-                            let already_exists = data.items.iter().any(|existing| {
-                                existing.content_text.as_deref() == Some(text.as_str())
-                                    || existing.content.as_deref() == Some(text.as_str())
-                            });
-                            if already_exists {
-                                // Content already in history — move it to top instead of adding new
-                                drop(data);
-                                let mut data = storage.data.lock().unwrap();
-                                // Find the item and update its timestamp
-                                if let Some(item) = data.items.iter_mut().find(|i| {
-                                    i.content_text.as_deref() == Some(text.as_str())
-                                        || i.content.as_deref() == Some(text.as_str())
-                                }) {
-                                    item.timestamp = chrono::Utc::now().to_rfc3339();
-                                    let updated_item = item.clone();
-                                    drop(data);
-                                    storage.save_sync();
-                                    // Emit so UI updates
-                                    if let Err(e) = app_handle.emit("clipboard-updated", &updated_item) {
-                                        warn!("Failed to emit clipboard-updated: {}", e);
-                                    }
-                                }
-                                continue;
-                            }
-                        }
-
-                        let id = format!(
-                            "{}{}",
-                            chrono::Utc::now().timestamp_millis(),
-                            &uuid::Uuid::new_v4().to_string()[..8]
-                        );
-                        // Try to capture HTML content for rich text
-                        let html_content = get_clipboard_html();
-
-                        let item = Item {
-                            id,
-                            kind: ItemKind::Text,
-                            content_text: Some(text.clone()),
-                            content_html: html_content,
-                            image_path: None,
-                            mime: None,
-                            category: category.to_string(),
-                            tab_id,
-                            timestamp: chrono::Utc::now().to_rfc3339(),
-                            pinned: false,
-                            label: String::new(),
-                            content: Some(text),
-                            in_vault: false,
-                        };
-
-                        // Add to storage
-                        {
-                            let mut data = storage.data.lock().unwrap();
-                            data.items.insert(0, item.clone());
-                            // Trim
-                            let max = data.settings.max_history;
-                            if data.items.len() > max {
-                                let pinned: Vec<Item> =
-                                    data.items.iter().filter(|i| i.pinned).cloned().collect();
-                                let unpinned: Vec<Item> =
-                                    data.items.iter().filter(|i| !i.pinned).cloned().collect();
-                                let mut new_items = pinned;
-                                new_items.extend(unpinned.into_iter().take(max));
-                                data.items = new_items;
-                            }
-                        }
-                        storage.save_sync();
-
-                        // Emit event
-                        if let Err(e) = app_handle.emit("clipboard-updated", &item) {
-                            warn!("Failed to emit clipboard-updated: {}", e);
-                        }
-                    }
-                }
+            Err(e) => {
+                warn!("Failed to save clipboard image: {}", e);
             }
+        }
+    }
 
-            // Check image
-            match clipboard.get_image() {
-                Ok(img_data) => {
-                    if !img_data.bytes.is_empty() {
-                        let mut hasher = Sha256::new();
-                        hasher.update(&img_data.bytes);
-                        let hash = hasher.finalize().to_vec();
-
-                        if last_image_hash.as_ref() != Some(&hash) {
-                            last_image_hash = Some(hash);
-
-                            // Save image to file
-                            let id = format!(
-                                "{}{}",
-                                chrono::Utc::now().timestamp_millis(),
-                                &uuid::Uuid::new_v4().to_string()[..8]
-                            );
-                            let filename = format!("{}.png", &id);
-                            let img_path = storage.images_dir().join(&filename);
-
-                            // Convert RGBA to PNG
-                            match save_image_data(
-                                &img_data.bytes,
-                                img_data.width,
-                                img_data.height,
-                                &img_path,
-                            ) {
-                                Ok(_) => {
-                                    let item = Item {
-                                        id,
-                                        kind: ItemKind::Image,
-                                        content_text: None,
-                                        content_html: None,
-                                        image_path: Some(filename),
-                                        mime: Some("image/png".into()),
-                                        category: "image".into(),
-                                        tab_id: None,
-                                        timestamp: chrono::Utc::now().to_rfc3339(),
-                                        pinned: false,
-                                        label: String::new(),
-                                        content: None,
-                                        in_vault: false,
-                                    };
-
-                                    {
-                                        let mut data = storage.data.lock().unwrap();
-                                        data.items.insert(0, item.clone());
-                                        let max = data.settings.max_history;
-                                        if data.items.len() > max {
-                                            let pinned: Vec<Item> = data
-                                                .items
-                                                .iter()
-                                                .filter(|i| i.pinned)
-                                                .cloned()
-                                                .collect();
-                                            let unpinned: Vec<Item> = data
-                                                .items
-                                                .iter()
-                                                .filter(|i| !i.pinned)
-                                                .cloned()
-                                                .collect();
-                                            let mut new_items = pinned;
-                                            new_items.extend(unpinned.into_iter().take(max));
-                                            data.items = new_items;
-                                        }
-                                    }
-                                    storage.save_sync();
-
-                                    if let Err(e) = app_handle.emit("clipboard-updated", &item) {
-                                        warn!("Failed to emit clipboard-updated: {}", e);
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Failed to save clipboard image: {}", e);
-                                }
-                            }
-                        }
-                    }
+    /// Insert a new item at the top of history, filed into every tab whose rules
+    /// it matches, trim past `max_history` and persist
+    fn add_item(&mut self, item: &mut Item) {
+        let storage = &self.storage;
+        let mut data = storage.data.lock().unwrap();
+        // Regexes are only recompiled after the tabs changed
+        let version = storage.tabs_version();
+        if self.tab_rules.as_ref().map(|(v, _)| *v) != Some(version) {
+            self.tab_rules = Some((version, TabRules::new(&data.tabs)));
+        }
+        let (_, tab_rules) = self.tab_rules.as_ref().expect("tab rules just built");
+        let matching: Vec<String> = tab_rules.matching_tabs(item).map(str::to_string).collect();
+        for tab_id in matching {
+            item.add_tab(&tab_id);
+        }
+        data.items.insert(0, item.clone());
+
+        // Trim: keep every pinned item plus the newest `max_history` unpinned ones
+        let max = data.settings.max_history;
+        let mut removed = Vec::new();
+        if data.items.len() > max {
+            let mut unpinned = 0;
+            data.items.retain(|i| {
+                if i.pinned {
+                    return true;
                 }
-                Err(_) => {
-                    // No image in clipboard, that's normal
+                unpinned += 1;
+                if unpinned <= max {
+                    true
+                } else {
+                    removed.push(i.id.clone());
+                    false
                 }
-            }
+            });
         }
-    });
+        drop(data);
+
+        storage.save_item(item);
+        storage.delete_items(&removed);
+    }
+
+    /// Move the closest image within the similarity threshold to the top,
+    /// if that's turned on. Returns true if there was one.
+    fn bump_similar_image(&mut self, dhash: u64) -> bool {
+        let mut data = self.storage.data.lock().unwrap();
+        if !data.settings.bump_similar_images {
+            return false;
+        }
+        let threshold = data.settings.image_similarity_threshold;
+        let closest = data
+            .items
+            .iter_mut()
+            .filter_map(|i| {
+                let other = image_hash::from_hex(i.image_dhash.as_deref()?)?;
+                Some((image_hash::distance(dhash, other), i))
+            })
+            .filter(|(distance, _)| *distance <= threshold)
+            .min_by_key(|(distance, _)| *distance);
+        let item = match closest {
+            Some((_, item)) => item,
+            None => return false,
+        };
+        item.timestamp = chrono::Utc::now().to_rfc3339();
+        let updated_item = item.clone();
+        self.storage.save_item(item);
+        drop(data);
+        (self.on_item)(&updated_item);
+        true
+    }
+}
+
+fn hash_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hasher.finalize().to_vec()
+}
+
+fn new_item_id() -> String {
+    format!(
+        "{}{}",
+        chrono::Utc::now().timestamp_millis(),
+        &uuid::Uuid::new_v4().to_string()[..8]
+    )
 }
 
-fn save_image_data(
-    rgba_bytes: &[u8],
-    width: usize,
-    height: usize,
-    path: &std::path::Path,
-) -> Result<(), String> {
-    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
-    let writer = std::io::BufWriter::new(file);
-    let encoder = image::codecs::png::PngEncoder::new(writer);
+fn encode_png(rgba_bytes: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut png);
     encoder
         .write_image(rgba_bytes, width as u32, height as u32, image::ColorType::Rgba8.into())
         .map_err(|e| e.to_string())?;
-    Ok(())
+    Ok(png)
 }
@@ -1,8 +1,16 @@
-use crate::models::{HistoryResult, Item, Stats};
+use crate::clipboard_backend::{self, ClipboardImage};
+use crate::encryption;
+use crate::files;
+use crate::history::{self, tab_filter, HistoryQuery};
+use crate::image_hash;
+use crate::models::{HistoryResult, Item, SortOrder, Stats, TabMatch, TabRule};
 use crate::paste;
+use crate::rules::{self, TabRules};
 use crate::storage::Storage;
-use arboard::Clipboard;
-use log::{error, info};
+use crate::tabs;
+use crate::vault;
+use log::{error, info, warn};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tauri::{AppHandle, Manager, State, Emitter};
 
@@ -17,24 +25,33 @@ pub fn get_tabs(storage: State<StorageState>) -> Vec<crate::models::Tab> {
     data.tabs.clone()
 }
 
+/// Create a tab, last among its siblings. With a `query` it becomes a smart
+/// tab showing every item the query matches.
 #[tauri::command]
 pub fn create_tab(
     storage: State<StorageState>,
     name: String,
     icon: Option<String>,
-) -> crate::models::Tab {
+    query: Option<String>,
+    parent_id: Option<String>,
+) -> Result<crate::models::Tab, String> {
+    let query = query.map(|q| q.trim().to_string()).filter(|q| !q.is_empty());
+    if let Some(ref q) = query {
+        crate::query::parse(q).map_err(|e| e.message)?;
+    }
+    let id = tabs::new_id();
     let tab = crate::models::Tab {
-        id: format!("tab_{}", chrono::Utc::now().timestamp_millis()),
-        name,
-        icon: icon.unwrap_or_else(|| "📁".into()),
-        system: false,
+        query,
+        ..crate::models::Tab::new(&id, &name, icon.as_deref().unwrap_or("📁"), false)
     };
-    {
-        let mut data = storage.data.lock().unwrap();
-        data.tabs.push(tab.clone());
+    let mut data = storage.data.lock().unwrap();
+    data.tabs.push(tab);
+    if let Err(e) = tabs::move_tab(&mut data.tabs, &id, parent_id.as_deref(), None) {
+        data.tabs.retain(|t| t.id != id);
+        return Err(e);
     }
-    storage.save_sync();
-    tab
+    storage.save_tabs(&data.tabs);
+    Ok(data.tabs.iter().find(|t| t.id == id).cloned().expect("tab just created"))
 }
 
 #[tauri::command]
@@ -55,8 +72,7 @@ pub fn rename_tab(
         if let Some(i) = icon {
             tab.icon = i;
         }
-        drop(data);
-        storage.save_sync();
+        storage.save_tabs(&data.tabs);
         serde_json::json!({"success": true})
     } else {
         serde_json::json!({"success": false})
@@ -70,86 +86,225 @@ pub fn delete_tab(storage: State<StorageState>, id: String) -> serde_json::Value
     if is_system {
         return serde_json::json!({"success": false});
     }
+    // Tabs nested under this one move up a level
+    let parent_id = data.tabs.iter().find(|t| t.id == id).and_then(|t| t.parent_id.clone());
+    for tab in data.tabs.iter_mut().filter(|t| t.parent_id.as_deref() == Some(id.as_str())) {
+        tab.parent_id = parent_id.clone();
+    }
     data.tabs.retain(|t| t.id != id);
-    // Reset items that were in this tab
+    tabs::normalize(&mut data.tabs);
+    storage.save_tabs(&data.tabs);
+    // Take items out of this tab; they stay in any other tabs
+    let mut changed = Vec::new();
     for item in &mut data.items {
-        if item.tab_id.as_deref() == Some(&id) {
-            item.tab_id = None;
+        if item.remove_tab(&id) {
+            changed.push(&*item);
         }
     }
-    drop(data);
-    storage.save_sync();
+    storage.save_items(changed);
+    serde_json::json!({"success": true})
+}
+
+/// Nest a tab under `parent_id` (None = top level) at `position` among its
+/// new siblings (None = last)
+#[tauri::command]
+pub fn move_tab(
+    storage: State<StorageState>,
+    id: String,
+    parent_id: Option<String>,
+    position: Option<usize>,
+) -> serde_json::Value {
+    let mut data = storage.data.lock().unwrap();
+    match tabs::move_tab(&mut data.tabs, &id, parent_id.as_deref(), position) {
+        Ok(()) => {
+            storage.save_tabs(&data.tabs);
+            serde_json::json!({"success": true})
+        }
+        Err(e) => serde_json::json!({"success": false, "error": e}),
+    }
+}
+
+/// Order the tabs nested under `parent_id` (None = top level) as in `ids`
+#[tauri::command]
+pub fn reorder_tabs(storage: State<StorageState>, parent_id: Option<String>, ids: Vec<String>) -> serde_json::Value {
+    let mut data = storage.data.lock().unwrap();
+    tabs::reorder(&mut data.tabs, parent_id.as_deref(), &ids);
+    storage.save_tabs(&data.tabs);
     serde_json::json!({"success": true})
 }
 
+/// Set or clear (`None` or blank) the saved query of a tab
+#[tauri::command]
+pub fn set_tab_query(storage: State<StorageState>, id: String, query: Option<String>) -> serde_json::Value {
+    if id == "all" {
+        return serde_json::json!({"success": false});
+    }
+    let query = query.map(|q| q.trim().to_string()).filter(|q| !q.is_empty());
+    if let Some(ref q) = query {
+        if let Err(e) = crate::query::parse(q) {
+            return serde_json::json!({"success": false, "error": e.message});
+        }
+    }
+    let mut data = storage.data.lock().unwrap();
+    if let Some(tab) = data.tabs.iter_mut().find(|t| t.id == id) {
+        tab.query = query;
+        storage.save_tabs(&data.tabs);
+        serde_json::json!({"success": true})
+    } else {
+        serde_json::json!({"success": false})
+    }
+}
+
+/// Number of items each tab shows, smart tabs included
+#[tauri::command]
+pub fn get_tab_counts(storage: State<StorageState>) -> HashMap<String, usize> {
+    let data = storage.data.lock().unwrap();
+    let index = storage.search.lock().unwrap();
+    data.tabs
+        .iter()
+        .map(|tab| {
+            let count = match tab_filter(&data.tabs, &index, Some(&tab.id), data.settings.include_child_tabs) {
+                Some(filter) => data.items.iter().filter(|i| filter.matches(i)).count(),
+                None => data.items.len(),
+            };
+            (tab.id.clone(), count)
+        })
+        .collect()
+}
+
+/// Replace the rules that file new items into a tab
+#[tauri::command]
+pub fn set_tab_rules(storage: State<StorageState>, id: String, rules: Vec<TabRule>) -> serde_json::Value {
+    if id == "all" {
+        return serde_json::json!({"success": false});
+    }
+    if let Err(e) = rules::validate(&rules) {
+        return serde_json::json!({"success": false, "error": e});
+    }
+    let mut data = storage.data.lock().unwrap();
+    if let Some(tab) = data.tabs.iter_mut().find(|t| t.id == id) {
+        tab.rules = rules;
+        storage.save_tabs(&data.tabs);
+        serde_json::json!({"success": true})
+    } else {
+        serde_json::json!({"success": false})
+    }
+}
+
+/// File existing history items into every tab whose rules they match. Items
+/// are only ever added to tabs, so ones filed by hand stay where they are.
+#[tauri::command]
+pub fn apply_tab_rules(storage: State<StorageState>) -> serde_json::Value {
+    let mut data = storage.data.lock().unwrap();
+    let data = &mut *data;
+    let tab_rules = TabRules::new(&data.tabs);
+    let mut changed = Vec::new();
+    for item in data.items.iter_mut() {
+        let matching: Vec<String> = tab_rules.matching_tabs(item).map(str::to_string).collect();
+        let mut added = false;
+        for tab_id in matching {
+            added |= item.add_tab(&tab_id);
+        }
+        if added {
+            changed.push(&*item);
+        }
+    }
+    let filed = changed.len();
+    storage.save_items(changed);
+    info!("Filed {} items by tab rules", filed);
+    serde_json::json!({"success": true, "filed": filed})
+}
+
 // ============ ITEMS ============
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn get_history(
     storage: State<StorageState>,
     search: Option<String>,
     tab_id: Option<String>,
-    _page: Option<usize>,
+    page: Option<usize>,
     page_size: Option<usize>,
+    sort: Option<SortOrder>,
+    source_app: Option<String>,
+    tab_ids: Option<Vec<String>>,
+    tab_match: Option<TabMatch>,
+    include_children: Option<bool>,
 ) -> HistoryResult {
     let data = storage.data.lock().unwrap();
-    let mut items: Vec<Item> = data.items.clone();
+    let index = storage.search.lock().unwrap();
+    let query = HistoryQuery {
+        search,
+        tab_id,
+        page,
+        page_size,
+        sort,
+        source_app,
+        tab_ids,
+        tab_match,
+        include_children,
+    };
+    history::query(&data, &index, &query)
+}
 
-    // Filter by tab
-    if let Some(ref tid) = tab_id {
-        if tid != "all" {
-            if tid == "links" {
-                items.retain(|i| i.category == "link" || i.tab_id.as_deref() == Some("links"));
-            } else {
-                items.retain(|i| i.tab_id.as_deref() == Some(tid.as_str()));
-            }
-        }
-    }
+/// The real text of a sensitive item, which the UI only ever sees masked
+fn sensitive_text(storage: &Storage, item_id: Option<&str>) -> Option<String> {
+    let data = storage.data.lock().unwrap();
+    data.items
+        .iter()
+        .find(|i| i.sensitive && Some(i.id.as_str()) == item_id)
+        .map(|i| i.get_text().to_string())
+}
 
-    // Filter by search
-    if let Some(ref q) = search {
-        if !q.is_empty() {
-            let ql = q.to_lowercase();
-            items.retain(|i| {
-                i.get_text().to_lowercase().contains(&ql)
-                    || i.label.to_lowercase().contains(&ql)
-                    || i.category.to_lowercase().contains(&ql)
-            });
-        }
+/// A decrypted copy of `item_id` and its image, if it's a vault item.
+/// Fails if the vault is locked.
+fn vault_item(storage: &Storage, item_id: Option<&str>) -> Result<Option<vault::Opened>, String> {
+    match item_id {
+        Some(id) => vault::open(storage, id),
+        None => Ok(None),
     }
+}
 
-    // Sort: pinned first, then by timestamp (newest first)
-    items.sort_by(|a, b| {
-        match (a.pinned, b.pinned) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => b.timestamp.cmp(&a.timestamp),
-        }
-    });
-
-    let total = items.len();
-    let page_size = page_size.unwrap_or(500);
-    items.truncate(page_size);
-
-    HistoryResult { items, total }
+/// Bump the paste counter of the given items
+fn record_paste(storage: &Storage, ids: &[String]) {
+    let mut data = storage.data.lock().unwrap();
+    let mut changed = Vec::new();
+    for item in data.items.iter_mut().filter(|i| ids.contains(&i.id)) {
+        item.paste_count = item.paste_count.saturating_add(1);
+        changed.push(&*item);
+    }
+    storage.save_items(changed);
 }
 
+/// Delete a history item, or a vault item with `vault` (which needs the
+/// vault unlocked)
 #[tauri::command]
-pub fn delete_item(storage: State<StorageState>, id: String) -> serde_json::Value {
-    let mut data = storage.data.lock().unwrap();
-    data.items.retain(|i| i.id != id);
-    drop(data);
-    storage.save_sync();
-    serde_json::json!({"success": true})
+pub fn delete_item(storage: State<StorageState>, id: String, vault: Option<bool>) -> serde_json::Value {
+    delete_items(&storage, &[id], vault.unwrap_or(false))
 }
 
+/// Delete history items, or vault items with `vault` (which needs the vault
+/// unlocked)
 #[tauri::command]
-pub fn delete_multiple(storage: State<StorageState>, ids: Vec<String>) -> serde_json::Value {
+pub fn delete_multiple(storage: State<StorageState>, ids: Vec<String>, vault: Option<bool>) -> serde_json::Value {
+    delete_items(&storage, &ids, vault.unwrap_or(false))
+}
+
+/// Delete items from the history, or from the vault when `in_vault` is set.
+/// Ids from the other partition are ignored.
+fn delete_items(storage: &Storage, ids: &[String], in_vault: bool) -> serde_json::Value {
+    if in_vault {
+        return match vault::delete(storage, ids) {
+            Ok(deleted) => serde_json::json!({"success": true, "deleted": deleted}),
+            Err(e) => serde_json::json!({"success": false, "error": e}),
+        };
+    }
     let mut data = storage.data.lock().unwrap();
-    data.items.retain(|i| !ids.contains(&i.id));
+    let removed: Vec<String> = data.items.iter().filter(|i| ids.contains(&i.id)).map(|i| i.id.clone()).collect();
+    data.items.retain(|i| !removed.contains(&i.id));
     drop(data);
-    storage.save_sync();
-    serde_json::json!({"success": true})
+    storage.delete_items(&removed);
+    serde_json::json!({"success": true, "deleted": removed.len()})
 }
 
 #[tauri::command]
@@ -158,31 +313,80 @@ pub fn pin_item(storage: State<StorageState>, id: String) -> serde_json::Value {
     if let Some(item) = data.items.iter_mut().find(|i| i.id == id) {
         item.pinned = !item.pinned;
         let pinned = item.pinned;
-        drop(data);
-        storage.save_sync();
+        storage.save_item(item);
         serde_json::json!({"success": true, "pinned": pinned})
     } else {
         serde_json::json!({"success": false})
     }
 }
 
+/// Move an item into `tab_id`, taking it out of `from_tab_id`, or out of
+/// every other tab when that isn't given
 #[tauri::command]
 pub fn move_to_tab(
     storage: State<StorageState>,
     item_id: String,
     tab_id: String,
+    from_tab_id: Option<String>,
 ) -> serde_json::Value {
     let mut data = storage.data.lock().unwrap();
     if let Some(item) = data.items.iter_mut().find(|i| i.id == item_id) {
-        item.tab_id = Some(tab_id);
-        drop(data);
-        storage.save_sync();
+        match from_tab_id {
+            Some(from) => {
+                item.remove_tab(&from);
+            }
+            None => item.tab_ids.clear(),
+        }
+        item.add_tab(&tab_id);
+        storage.save_item(item);
         serde_json::json!({"success": true})
     } else {
         serde_json::json!({"success": false})
     }
 }
 
+/// Add items to a tab, keeping the tabs they are already in
+#[tauri::command]
+pub fn add_to_tab(storage: State<StorageState>, ids: Vec<String>, tab_id: String) -> serde_json::Value {
+    update_tabs(&storage, &ids, |item| item.add_tab(&tab_id))
+}
+
+/// Take items out of a tab
+#[tauri::command]
+pub fn remove_from_tab(storage: State<StorageState>, ids: Vec<String>, tab_id: String) -> serde_json::Value {
+    update_tabs(&storage, &ids, |item| item.remove_tab(&tab_id))
+}
+
+/// Put items in exactly `tab_ids`
+#[tauri::command]
+pub fn set_item_tabs(storage: State<StorageState>, ids: Vec<String>, tab_ids: Vec<String>) -> serde_json::Value {
+    let mut seen = std::collections::HashSet::new();
+    let mut tab_ids = tab_ids;
+    tab_ids.retain(|t| seen.insert(t.clone()));
+    update_tabs(&storage, &ids, |item| {
+        if item.tab_ids == tab_ids {
+            return false;
+        }
+        item.tab_ids = tab_ids.clone();
+        true
+    })
+}
+
+/// Apply `update` to the items in `ids` and save the ones it changed
+fn update_tabs(storage: &Storage, ids: &[String], mut update: impl FnMut(&mut Item) -> bool) -> serde_json::Value {
+    let ids: std::collections::HashSet<&str> = ids.iter().map(String::as_str).collect();
+    let mut data = storage.data.lock().unwrap();
+    let mut changed = Vec::new();
+    for item in data.items.iter_mut().filter(|i| ids.contains(i.id.as_str())) {
+        if update(item) {
+            changed.push(&*item);
+        }
+    }
+    let count = changed.len();
+    storage.save_items(changed);
+    serde_json::json!({"success": true, "changed": count})
+}
+
 #[tauri::command]
 pub fn label_item(
     storage: State<StorageState>,
@@ -192,8 +396,7 @@ pub fn label_item(
     let mut data = storage.data.lock().unwrap();
     if let Some(item) = data.items.iter_mut().find(|i| i.id == id) {
         item.label = label;
-        drop(data);
-        storage.save_sync();
+        storage.save_item(item);
         serde_json::json!({"success": true})
     } else {
         serde_json::json!({"success": false})
@@ -201,8 +404,17 @@ pub fn label_item(
 }
 
 #[tauri::command]
-pub fn copy_to_clipboard(_storage: State<StorageState>, content: String) -> serde_json::Value {
-    match Clipboard::new() {
+pub fn copy_to_clipboard(
+    storage: State<StorageState>,
+    content: String,
+    item_id: Option<String>,
+) -> serde_json::Value {
+    let content = match vault_item(&storage, item_id.as_deref()) {
+        Ok(Some((item, _))) => item.get_text().to_string(),
+        Ok(None) => sensitive_text(&storage, item_id.as_deref()).unwrap_or(content),
+        Err(e) => return serde_json::json!({"success": false, "error": e}),
+    };
+    match clipboard_backend::system() {
         Ok(mut clipboard) => {
             if let Err(e) = clipboard.set_text(&content) {
                 error!("Failed to set clipboard text: {}", e);
@@ -220,7 +432,6 @@ pub fn copy_to_clipboard(_storage: State<StorageState>, content: String) -> serd
 #[tauri::command]
 pub fn copy_image_to_clipboard(base64: String) -> serde_json::Value {
     use base64::{Engine as _, engine::general_purpose::STANDARD};
-    use arboard::ImageData;
     
     // Remove "data:image/png;base64," if present
     let b64 = if let Some(stripped) = base64.strip_prefix("data:image/png;base64,") {
@@ -234,14 +445,14 @@ pub fn copy_image_to_clipboard(base64: String) -> serde_json::Value {
             if let Ok(img) = image::load_from_memory(&buf) {
                 let rgba = img.to_rgba8();
                 let (width, height) = rgba.dimensions();
-                let img_data = ImageData {
+                let img_data = ClipboardImage {
                     width: width as usize,
                     height: height as usize,
-                    bytes: rgba.into_raw().into(),
+                    bytes: rgba.into_raw(),
                 };
                 
-                if let Ok(mut clipboard) = Clipboard::new() {
-                    if let Err(e) = clipboard.set_image(img_data) {
+                if let Ok(mut clipboard) = clipboard_backend::system() {
+                    if let Err(e) = clipboard.set_image(&img_data) {
                         error!("Failed to copy image to clipboard: {}", e);
                         return serde_json::json!({"success": false});
                     }
@@ -267,7 +478,7 @@ pub fn bulk_copy(storage: State<StorageState>, contents: Vec<String>) -> serde_j
     drop(data);
 
     let combined = contents.join(&delim);
-    match Clipboard::new() {
+    match clipboard_backend::system() {
         Ok(mut clipboard) => {
             if let Err(e) = clipboard.set_text(&combined) {
                 error!("Failed to bulk copy: {}", e);
@@ -289,8 +500,42 @@ pub fn paste_and_hide(
     content: String,
     image_path: Option<String>,
     content_html: Option<String>,
+    item_id: Option<String>,
 ) -> serde_json::Value {
     info!("paste_and_hide: content_len={}, image={:?}, has_html={}", content.len(), image_path, content_html.is_some());
+    let content = sensitive_text(&storage, item_id.as_deref()).unwrap_or(content);
+    // Vault items are decrypted here; the UI only has their masked copy
+    let (content, content_html, vault_rtf, vault_image, vault_files) = match vault_item(&storage, item_id.as_deref()) {
+        Ok(Some((item, image))) => {
+            let paths = Some(files::entry_paths(&item.files)).filter(|paths| !paths.is_empty());
+            (item.get_text().to_string(), item.content_html, item.content_rtf, image, paths)
+        }
+        Ok(None) => (content, content_html, None, None, None),
+        Err(e) => return serde_json::json!({"success": false, "error": e}),
+    };
+
+    // Items restore their captured flavors, and file items paste the file
+    // list itself rather than its text form
+    let (flavor_blobs, file_paths, content_rtf) = match item_id.as_ref() {
+        Some(id) => {
+            let data = storage.data.lock().unwrap();
+            match data.items.iter().find(|i| &i.id == id) {
+                Some(item) => (
+                    item.flavors.clone(),
+                    Some(files::entry_paths(&item.files)).filter(|paths| !paths.is_empty()),
+                    item.content_rtf.clone(),
+                ),
+                None => (Default::default(), None, None),
+            }
+        }
+        None => (Default::default(), None, None),
+    };
+    let content_rtf = content_rtf.or(vault_rtf);
+    let file_paths = file_paths.or(vault_files);
+
+    if let Some(id) = item_id {
+        record_paste(&storage, &[id]);
+    }
 
     // Hide popup first
     if let Some(window) = app_handle.get_webview_window("main") {
@@ -298,18 +543,24 @@ pub fn paste_and_hide(
     }
 
     // Paste in a separate thread to not block
-    let images_dir = storage.images_dir().to_path_buf();
+    let storage = storage.inner().clone();
     std::thread::spawn(move || {
         std::thread::sleep(std::time::Duration::from_millis(300));
-        if let Some(ref img_path) = image_path {
-            let full_path = images_dir.join(img_path);
-            paste::paste_image_and_simulate(&full_path);
-        } else if let Some(ref html) = content_html {
-            if !html.is_empty() {
-                paste::paste_rich_text_and_simulate(&content, html);
-            } else {
-                paste::paste_text_and_simulate(&content);
+        let flavors = storage.load_flavors(&flavor_blobs);
+        if !flavors.is_empty() && paste::paste_flavors_and_simulate(&content, &flavors) {
+            return;
+        }
+        if let Some(ref paths) = file_paths {
+            paste::paste_files_and_simulate(paths);
+        } else if let Some(ref png) = vault_image {
+            paste::paste_image_data_and_simulate(png);
+        } else if let Some(ref img_path) = image_path {
+            match storage.read_image(img_path) {
+                Ok(png) => paste::paste_image_data_and_simulate(&png),
+                Err(e) => error!("paste: failed to read image {}: {}", img_path, e),
             }
+        } else if content_html.as_deref().is_some_and(|h| !h.is_empty()) || content_rtf.is_some() {
+            paste::paste_rich_text_and_simulate(&content, content_html.as_deref().unwrap_or(""), content_rtf.as_deref());
         } else {
             paste::paste_text_and_simulate(&content);
         }
@@ -325,16 +576,20 @@ pub fn bulk_paste_and_hide(
     contents: Vec<String>,
     html_contents: Option<Vec<String>>,
     image_paths: Option<Vec<String>>,
+    item_ids: Option<Vec<String>>,
 ) -> serde_json::Value {
     info!("[bulk_paste] {} text, {} html, {} images",
         contents.len(),
         html_contents.as_ref().map(|v| v.len()).unwrap_or(0),
         image_paths.as_ref().map(|v| v.len()).unwrap_or(0)
     );
+    if let Some(ids) = item_ids {
+        record_paste(&storage, &ids);
+    }
     let data = storage.data.lock().unwrap();
     let delim = data.settings.paste_delimiter.clone();
-    let images_dir = storage.images_dir().to_path_buf();
     drop(data);
+    let storage = storage.inner().clone();
 
     // Hide popup
     if let Some(window) = app_handle.get_webview_window("main") {
@@ -349,7 +604,7 @@ pub fn bulk_paste_and_hide(
 
         // If we have images, do sequential mixed paste
         if !img_vec.is_empty() {
-            let mut items: Vec<(Option<String>, Option<String>, Option<String>)> = Vec::new();
+            let mut items: Vec<paste::MixedItem> = Vec::new();
             
             // Add text items
             for (i, text) in contents.iter().enumerate() {
@@ -359,8 +614,10 @@ pub fn bulk_paste_and_hide(
             
             // Add image items
             for img in &img_vec {
-                let full_path = images_dir.join(img);
-                items.push((None, None, Some(full_path.to_string_lossy().to_string())));
+                match storage.read_image(img) {
+                    Ok(png) => items.push((None, None, Some(png))),
+                    Err(e) => error!("[bulk_paste] failed to read image {}: {}", img, e),
+                }
             }
             
             paste::bulk_paste_mixed(&items);
@@ -370,7 +627,7 @@ pub fn bulk_paste_and_hide(
             if has_html && contents.len() == 1 {
                 // Single rich text item
                 let html = html_vec.first().map(|s| s.as_str()).unwrap_or("");
-                paste::paste_rich_text_and_simulate(&contents[0], html);
+                paste::paste_rich_text_and_simulate(&contents[0], html, None);
             } else {
                 // Multiple text items — combine with delimiter
                 info!("[bulk_paste] starting paste with delimiter '{}'", delim);
@@ -390,24 +647,33 @@ pub fn hide_popup(app_handle: AppHandle) {
     }
 }
 
+/// Delete unpinned history items, in `tab_id` if given. With `vault`, empty
+/// the vault instead (which needs it unlocked).
 #[tauri::command]
 pub fn clear_history(
     storage: State<StorageState>,
     tab_id: Option<String>,
+    vault: Option<bool>,
 ) -> serde_json::Value {
-    let mut data = storage.data.lock().unwrap();
-    if let Some(ref tid) = tab_id {
-        if tid != "all" {
-            data.items.retain(|i| i.tab_id.as_deref() != Some(tid.as_str()) || i.pinned);
-        } else {
-            data.items.retain(|i| i.pinned);
-        }
-    } else {
-        data.items.retain(|i| i.pinned);
+    if vault.unwrap_or(false) {
+        return match vault::clear(&storage) {
+            Ok(deleted) => serde_json::json!({"success": true, "deleted": deleted}),
+            Err(e) => serde_json::json!({"success": false, "error": e}),
+        };
     }
+    let mut data = storage.data.lock().unwrap();
+    let filter = tab_filter(
+        &data.tabs,
+        &storage.search.lock().unwrap(),
+        tab_id.as_deref(),
+        data.settings.include_child_tabs,
+    );
+    let keep = |i: &Item| i.pinned || filter.as_ref().is_some_and(|f| !f.matches(i));
+    let removed: Vec<String> = data.items.iter().filter(|i| !keep(i)).map(|i| i.id.clone()).collect();
+    data.items.retain(keep);
     drop(data);
-    storage.save_sync();
-    serde_json::json!({"success": true})
+    storage.delete_items(&removed);
+    serde_json::json!({"success": true, "deleted": removed.len()})
 }
 
 #[tauri::command]
@@ -419,7 +685,10 @@ pub fn get_stats(storage: State<StorageState>) -> Stats {
     Stats {
         total_items: data.items.len(),
         pinned_items: data.items.iter().filter(|i| i.pinned).count(),
+        vault_items: data.vault.len(),
         storage_size,
+        images_size: storage.images_size(),
+        reclaimed_bytes: storage.reclaimed_bytes(),
     }
 }
 
@@ -454,12 +723,51 @@ pub fn set_settings(
     if let Some(poll) = settings.get("pollInterval").and_then(|v| v.as_u64()) {
         data.settings.poll_interval = poll;
     }
+    if let Some(primary) = settings.get("capturePrimarySelection").and_then(|v| v.as_bool()) {
+        data.settings.capture_primary_selection = primary;
+    }
+    if let Some(bump) = settings.get("bumpSimilarImages").and_then(|v| v.as_bool()) {
+        data.settings.bump_similar_images = bump;
+    }
+    if let Some(threshold) = settings.get("imageSimilarityThreshold").and_then(|v| v.as_u64()) {
+        data.settings.image_similarity_threshold = threshold.min(64) as u32;
+    }
+    if let Some(apps) = settings.get("excludedApps").and_then(|v| v.as_array()) {
+        data.settings.excluded_apps = app_list(apps);
+    }
+    if let Some(apps) = settings.get("allowedApps").and_then(|v| v.as_array()) {
+        data.settings.allowed_apps = app_list(apps);
+    }
+    if let Some(mode) = settings.get("concealedClipboard").and_then(|v| serde_json::from_value(v.clone()).ok()) {
+        data.settings.concealed_clipboard = mode;
+    }
+    if let Some(detect) = settings.get("detectSensitive").and_then(|v| v.as_bool()) {
+        data.settings.detect_sensitive = detect;
+    }
+    if let Some(ttl) = settings.get("sensitiveTtl").and_then(|v| v.as_u64()) {
+        data.settings.sensitive_ttl = ttl;
+    }
+    if let Some(include) = settings.get("includeChildTabs").and_then(|v| v.as_bool()) {
+        data.settings.include_child_tabs = include;
+    }
+    if let Some(timeout) = settings.get("vaultTimeout").and_then(|v| v.as_u64()) {
+        data.settings.vault_timeout = timeout;
+    }
 
-    drop(data);
-    storage.save_sync();
+    storage.save_settings(&data.settings);
     serde_json::json!({"success": true})
 }
 
+/// App names from a settings array, trimmed, without blanks
+fn app_list(values: &[serde_json::Value]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
 // ============ WINDOW CONTROLS ============
 
 #[tauri::command]
@@ -518,13 +826,91 @@ pub fn install_update() -> serde_json::Value {
     serde_json::json!({"status": "no-update"})
 }
 
-/// Get the asset protocol URL for an image path
+/// Get the URL of a stored image
 #[tauri::command]
 pub fn get_image_url(storage: State<StorageState>, filename: String) -> String {
-    let full_path = storage.images_dir().join(&filename);
-    if full_path.exists() {
-        // Return file:// URL for the image
-        format!("asset://localhost/{}", full_path.to_string_lossy().replace('\\', "/"))
+    // Anything else would let the webview read files outside the image store
+    if !storage.is_stored_image(&filename) {
+        warn!("Refusing to serve image {:?}", filename);
+        return String::new();
+    }
+    image_url(&storage, &storage.images_dir().join(&filename))
+}
+
+/// Get the URL of an image item's thumbnail. Items saved
+/// before thumbnails existed get their thumbnail, size metadata and
+/// perceptual hash on first request.
+#[tauri::command]
+pub fn get_thumbnail_url(storage: State<StorageState>, id: String) -> String {
+    let (filename, has_info, has_dhash) = {
+        let data = storage.data.lock().unwrap();
+        // Vault items aren't in the history; their images are only shown
+        // decrypted, through `get_vault_items`
+        let found = data.items.iter().find(|i| i.id == id).and_then(|i| {
+            Some((i.image_path.clone()?, i.image_width.is_some(), i.image_dhash.is_some()))
+        });
+        match found {
+            Some(found) => found,
+            None => return String::new(),
+        }
+    };
+
+    let thumb = match storage.ensure_thumbnail(&filename) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("Failed to create thumbnail for {}: {}", filename, e);
+            return image_url(&storage, &storage.images_dir().join(&filename));
+        }
+    };
+
+    let info = if has_info { None } else { storage.image_info(&filename) };
+    // dHash is scale-invariant, so hashing the thumbnail matches hashing the full image
+    let dhash = if has_dhash {
+        None
+    } else {
+        storage
+            .read_blob(&thumb)
+            .ok()
+            .and_then(|png| image::load_from_memory(&png).ok())
+            .map(|i| image_hash::dhash(&i.to_rgba8()))
+    };
+    if info.is_some() || dhash.is_some() {
+        let mut data = storage.data.lock().unwrap();
+        if let Some(item) = data.items.iter_mut().find(|i| i.id == id) {
+            if let Some((width, height, size)) = info {
+                item.image_width = Some(width);
+                item.image_height = Some(height);
+                item.image_size = Some(size);
+            }
+            if let Some(dhash) = dhash {
+                item.image_dhash = Some(image_hash::to_hex(dhash));
+            }
+            storage.save_item(item);
+        }
+    }
+
+    image_url(&storage, &thumb)
+}
+
+/// URL of a stored image file. Encrypted files can't be served by the asset
+/// protocol, so they're decrypted into a data URL.
+fn image_url(storage: &Storage, path: &std::path::Path) -> String {
+    if !storage.is_encrypted() {
+        return asset_url(path);
+    }
+    match storage.read_blob(path) {
+        Ok(png) => {
+            use base64::{engine::general_purpose::STANDARD, Engine as _};
+            format!("data:image/png;base64,{}", STANDARD.encode(png))
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// `asset://` URL for a file, or empty if it doesn't exist
+fn asset_url(path: &std::path::Path) -> String {
+    if path.exists() {
+        format!("asset://localhost/{}", path.to_string_lossy().replace('\\', "/"))
     } else {
         String::new()
     }
@@ -600,68 +986,146 @@ pub fn save_screenshot_to_file(base64_data: String, file_path: String) -> Result
 
 // ============ VAULT ============
 
-/// Simple hash for vault PIN (not crypto-grade, but sufficient for local app lock)
-fn simple_hash(pin: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    let mut hasher = DefaultHasher::new();
-    pin.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+/// Set up the vault with a first PIN. The vault is left unlocked. With
+/// `recovery`, `recoveryKey` holds a one-time recovery key to show the user.
+#[tauri::command]
+pub fn set_vault_pin(storage: State<StorageState>, pin: String, recovery: Option<bool>) -> serde_json::Value {
+    match vault::set_pin(&storage, &pin, recovery.unwrap_or(false)) {
+        Ok(recovery_key) => serde_json::json!({"success": true, "recoveryKey": recovery_key}),
+        Err(e) => serde_json::json!({"success": false, "error": e}),
+    }
 }
 
+/// Replace the vault PIN after checking the current one
 #[tauri::command]
-pub fn set_vault_pin(storage: State<StorageState>, pin: String) -> serde_json::Value {
-    let mut data = storage.data.lock().unwrap();
-    data.settings.vault_pin_hash = simple_hash(&pin);
-    drop(data);
-    storage.save_sync();
-    serde_json::json!({"success": true})
+pub fn change_vault_pin(storage: State<StorageState>, old_pin: String, new_pin: String) -> serde_json::Value {
+    attempt_result(&storage, vault::change_pin(&storage, &old_pin, &new_pin))
+}
+
+/// Set a new vault PIN with the recovery key, which is used up. The vault is
+/// left unlocked.
+#[tauri::command]
+pub fn reset_vault_pin(storage: State<StorageState>, recovery_key: String, new_pin: String) -> serde_json::Value {
+    attempt_result(&storage, vault::reset_pin(&storage, &recovery_key, &new_pin))
+}
+
+/// Replace the recovery key with a new one. Needs the vault unlocked.
+#[tauri::command]
+pub fn new_vault_recovery_key(storage: State<StorageState>) -> serde_json::Value {
+    match vault::new_recovery_key(&storage) {
+        Ok(recovery_key) => serde_json::json!({"success": true, "recoveryKey": recovery_key}),
+        Err(e) => serde_json::json!({"success": false, "error": e}),
+    }
 }
 
+/// Open a vault session with the PIN. After repeated wrong PINs,
+/// `retryAfter` says how many seconds to wait before the next try.
 #[tauri::command]
 pub fn verify_vault_pin(storage: State<StorageState>, pin: String) -> serde_json::Value {
-    let data = storage.data.lock().unwrap();
-    let valid = data.settings.vault_pin_hash == simple_hash(&pin);
-    serde_json::json!({"valid": valid})
+    match vault::unlock(&storage, &pin) {
+        Ok(()) => serde_json::json!({"valid": true}),
+        Err(e) => serde_json::json!({"valid": false, "error": e, "retryAfter": vault::retry_after(&storage)}),
+    }
+}
+
+/// Close the vault session
+#[tauri::command]
+pub fn lock_vault(storage: State<StorageState>) -> serde_json::Value {
+    vault::lock(&storage);
+    serde_json::json!({"success": true})
 }
 
 #[tauri::command]
 pub fn has_vault_pin(storage: State<StorageState>) -> serde_json::Value {
-    let data = storage.data.lock().unwrap();
-    serde_json::json!({"hasPin": !data.settings.vault_pin_hash.is_empty()})
+    serde_json::json!({
+        "hasPin": vault::has_pin(&storage),
+        "unlocked": vault::is_unlocked(&storage),
+        "hasRecovery": vault::has_recovery_key(&storage),
+        "retryAfter": vault::retry_after(&storage),
+    })
 }
 
+/// Encrypt an item into the vault. Needs the vault unlocked.
 #[tauri::command]
 pub fn move_to_vault(storage: State<StorageState>, id: String) -> serde_json::Value {
-    let mut data = storage.data.lock().unwrap();
-    if let Some(item) = data.items.iter_mut().find(|i| i.id == id) {
-        item.in_vault = true;
-        drop(data);
-        storage.save_sync();
-        serde_json::json!({"success": true})
-    } else {
-        serde_json::json!({"success": false, "error": "Item not found"})
-    }
+    vault_result(vault::move_to_vault(&storage, &id))
 }
 
+/// Decrypt an item out of the vault. Needs the vault unlocked.
 #[tauri::command]
 pub fn remove_from_vault(storage: State<StorageState>, id: String) -> serde_json::Value {
-    let mut data = storage.data.lock().unwrap();
-    if let Some(item) = data.items.iter_mut().find(|i| i.id == id) {
-        item.in_vault = false;
-        drop(data);
-        storage.save_sync();
-        serde_json::json!({"success": true})
-    } else {
-        serde_json::json!({"success": false, "error": "Item not found"})
+    vault_result(vault::remove_from_vault(&storage, &id))
+}
+
+/// Decrypted vault items, or those matching `search`, with their images as
+/// data URLs keyed by item id
+#[tauri::command]
+pub fn get_vault_items(storage: State<StorageState>, search: Option<String>) -> serde_json::Value {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    let items = match search.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        Some(q) => vault::search(&storage, q),
+        None => vault::open_items(&storage),
+    };
+    let items = match items {
+        Ok(items) => items,
+        Err(e) => return serde_json::json!({"items": [], "error": e}),
+    };
+    let mut images = HashMap::new();
+    for item in items.iter().filter(|i| i.image_path.is_some()) {
+        match vault::open(&storage, &item.id) {
+            Ok(Some((_, Some(png)))) => {
+                images.insert(item.id.clone(), format!("data:image/png;base64,{}", STANDARD.encode(png)));
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to open vault image {}: {}", item.id, e),
+        }
     }
+    serde_json::json!({"items": items, "images": images})
 }
 
+fn vault_result(result: Result<(), String>) -> serde_json::Value {
+    match result {
+        Ok(()) => serde_json::json!({"success": true}),
+        Err(e) => serde_json::json!({"success": false, "error": e}),
+    }
+}
+
+/// Like `vault_result`, with `retryAfter` for checks under the PIN backoff
+fn attempt_result(storage: &Storage, result: Result<(), String>) -> serde_json::Value {
+    match result {
+        Ok(()) => serde_json::json!({"success": true}),
+        Err(e) => serde_json::json!({"success": false, "error": e, "retryAfter": vault::retry_after(storage)}),
+    }
+}
+
+// ============ ENCRYPTION AT REST ============
+
+/// Whether the database is encrypted, where its key is kept and whether it
+/// still has to be unlocked
 #[tauri::command]
-pub fn get_vault_items(storage: State<StorageState>) -> serde_json::Value {
-    let data = storage.data.lock().unwrap();
-    let vault_items: Vec<&crate::models::Item> = data.items.iter().filter(|i| i.in_vault).collect();
-    serde_json::json!({"items": vault_items})
+pub fn get_encryption_status(storage: State<StorageState>) -> serde_json::Value {
+    serde_json::to_value(encryption::status(&storage)).unwrap_or_default()
+}
+
+/// Encrypt the database in place. Without `passphrase`, the key is kept in
+/// the OS keyring.
+#[tauri::command]
+pub fn enable_encryption(storage: State<StorageState>, passphrase: Option<String>) -> serde_json::Value {
+    vault_result(encryption::enable(&storage, passphrase.as_deref()))
+}
+
+/// Decrypt the database in place. `passphrase` is required when the key is
+/// derived from one.
+#[tauri::command]
+pub fn disable_encryption(storage: State<StorageState>, passphrase: Option<String>) -> serde_json::Value {
+    vault_result(encryption::disable(&storage, passphrase.as_deref()))
+}
+
+/// Load the database with the master passphrase given at startup
+#[tauri::command]
+pub fn unlock_database(storage: State<StorageState>, passphrase: String) -> serde_json::Value {
+    vault_result(encryption::unlock(&storage, &passphrase))
 }
 
 // ============ LICENSE ============
@@ -678,8 +1142,8 @@ pub fn activate_license(storage: State<StorageState>, key: String) -> serde_json
     if valid {
         let mut data = storage.data.lock().unwrap();
         data.settings.license_key = key.trim().to_uppercase();
+        storage.save_settings(&data.settings);
         drop(data);
-        storage.save_sync();
         info!("License activated successfully");
         serde_json::json!({"success": true})
     } else {
@@ -0,0 +1,320 @@
+//! Optional encryption at rest for the whole store.
+//!
+//! When it's on, item and tab rows, image files, thumbnails and clipboard
+//! flavor blobs are sealed with XChaCha20-Poly1305 under a random database
+//! key, bound to the row id or file name. Files are named by an HMAC of their
+//! content under the same key rather than its SHA-256, which would let anyone
+//! check whether a known image was copied. The key is kept either in the OS
+//! keyring (Keychain, Credential Manager, Secret Service) or wrapped with a
+//! master passphrase by Argon2id, which has to be entered at startup. Until
+//! then `Storage` is locked: nothing is loaded, captured or written.
+//!
+//! Sealed values are self-describing (`TEXT_PREFIX`, `BLOB_MAGIC`), so plain
+//! and sealed data can be read side by side. That makes the in-place
+//! migration restartable: `Storage` records its direction in the meta table
+//! before touching anything and finishes it on the next start if it was
+//! interrupted.
+//!
+//! Settings stay in the clear, so the app starts with the user's shortcuts
+//! and preferences before it's unlocked. So do the item columns that say
+//! nothing about the content: kind, timestamp and the pinned and vault
+//! flags. The category column is left empty; it's only in the sealed row.
+
+use crate::storage::Storage;
+use crate::vault::{Key, WrappedKey};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Meta row holding the `Header`. Encryption is on while it exists.
+pub(crate) const META_KEY: &str = "encryption";
+
+/// Meta row holding the direction ("encrypt" or "decrypt") of an unfinished
+/// migration
+pub(crate) const MIGRATION_KEY: &str = "encryption_migration";
+
+/// Error returned while the key isn't available yet
+pub const LOCKED: &str = "The database is locked";
+
+/// Error returned for a wrong master passphrase
+pub const WRONG_PASSPHRASE: &str = "Wrong passphrase";
+
+/// Shortest master passphrase accepted
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Where the database key is kept in the OS keyring
+const KEYRING_SERVICE: &str = "copas";
+const KEYRING_USER: &str = "database-key";
+
+/// Sealed row values are this prefix followed by base64. Plain values are
+/// JSON, which never starts with it.
+const TEXT_PREFIX: &str = "enc1:";
+
+/// Sealed files start with this, followed by the sealed content
+const BLOB_MAGIC: &[u8] = b"\0COPAS-ENC1\0";
+
+/// Sealed into the header, to tell a wrong key from a damaged database
+const CHECK: &[u8] = b"copas-database";
+
+/// Goes in front of file content when it's hashed into a name, so those
+/// hashes can't be mistaken for any other use of the key
+const BLOB_NAME_CONTEXT: &[u8] = b"copas-blob-name\0";
+
+/// Where the database key is kept
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeySource {
+    Keyring,
+    Passphrase,
+}
+
+/// What the `encryption` meta row holds
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    source: KeySource,
+    /// The database key wrapped with the passphrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapped: Option<WrappedKey>,
+    /// `CHECK` sealed with the database key (base64)
+    check: String,
+}
+
+impl Header {
+    fn new(key: &Key, source: KeySource, wrapped: Option<WrappedKey>) -> Result<Self, String> {
+        Ok(Self {
+            source,
+            wrapped,
+            check: STANDARD.encode(key.seal(CHECK, META_KEY.as_bytes())?),
+        })
+    }
+
+    /// Whether `key` is the database key
+    fn verify(&self, key: &Key) -> Result<(), String> {
+        let check = STANDARD.decode(&self.check).map_err(|e| e.to_string())?;
+        match key.open(&check, META_KEY.as_bytes()) {
+            Ok(value) if value == CHECK => Ok(()),
+            _ => Err("The key doesn't match the database".into()),
+        }
+    }
+
+    /// The database key, from the keyring or unwrapped with `passphrase`
+    fn key(&self, passphrase: Option<&str>) -> Result<Key, String> {
+        let key = match self.source {
+            KeySource::Keyring => keyring_key()?,
+            KeySource::Passphrase => self
+                .wrapped
+                .as_ref()
+                .ok_or("The wrapped database key is missing")?
+                .unwrap(passphrase.unwrap_or_default())
+                .map_err(|e| if e == crate::vault::WRONG_PIN { WRONG_PASSPHRASE.into() } else { e })?,
+        };
+        self.verify(&key)?;
+        Ok(key)
+    }
+}
+
+/// How `Storage` reads and writes rows and blobs
+pub enum Cipher {
+    /// Encryption is off
+    Off,
+    /// Encryption is on, but the key isn't available yet
+    Locked,
+    On(Key),
+}
+
+impl Cipher {
+    pub fn is_on(&self) -> bool {
+        matches!(self, Cipher::On(_))
+    }
+
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Cipher::Locked)
+    }
+
+    /// Seal a row value bound to `aad`. Returned as is when encryption is off.
+    pub fn seal_text(&self, text: String, aad: &str) -> Result<String, String> {
+        match self {
+            Cipher::Off => Ok(text),
+            Cipher::Locked => Err(LOCKED.into()),
+            Cipher::On(key) => Ok(format!("{}{}", TEXT_PREFIX, STANDARD.encode(key.seal(text.as_bytes(), aad.as_bytes())?))),
+        }
+    }
+
+    /// Open a row value, sealed or not
+    pub fn open_text(&self, stored: String, aad: &str) -> Result<String, String> {
+        let sealed = match stored.strip_prefix(TEXT_PREFIX) {
+            Some(sealed) => STANDARD.decode(sealed).map_err(|e| e.to_string())?,
+            None => return Ok(stored),
+        };
+        match self {
+            Cipher::On(key) => String::from_utf8(key.open(&sealed, aad.as_bytes())?).map_err(|e| e.to_string()),
+            _ => Err(LOCKED.into()),
+        }
+    }
+
+    /// Seal file content bound to its file name. Returned as is when
+    /// encryption is off.
+    pub fn seal_blob(&self, data: &[u8], name: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Cipher::Off => Ok(data.to_vec()),
+            Cipher::Locked => Err(LOCKED.into()),
+            Cipher::On(key) => {
+                let mut out = BLOB_MAGIC.to_vec();
+                out.extend(key.seal(data, name.as_bytes())?);
+                Ok(out)
+            }
+        }
+    }
+
+    /// Name of the file holding `data`, so equal content shares one file:
+    /// the SHA-256 of the content, or an HMAC of it while encryption is on
+    pub fn blob_name(&self, data: &[u8]) -> String {
+        match self {
+            Cipher::On(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC can take key of any size");
+                mac.update(BLOB_NAME_CONTEXT);
+                mac.update(data);
+                hex::encode(mac.finalize().into_bytes())
+            }
+            _ => hex::encode(Sha256::digest(data)),
+        }
+    }
+
+    /// Open file content, sealed or not
+    pub fn open_blob(&self, data: Vec<u8>, name: &str) -> Result<Vec<u8>, String> {
+        if !is_sealed_blob(&data) {
+            return Ok(data);
+        }
+        match self {
+            Cipher::On(key) => key.open(&data[BLOB_MAGIC.len()..], name.as_bytes()),
+            _ => Err(LOCKED.into()),
+        }
+    }
+}
+
+/// Whether file content was written by `Cipher::seal_blob`
+pub fn is_sealed_blob(data: &[u8]) -> bool {
+    data.starts_with(BLOB_MAGIC)
+}
+
+/// Encryption state for the UI
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub enabled: bool,
+    pub source: Option<KeySource>,
+    /// Waiting for the passphrase (or the keyring) before anything is loaded
+    pub locked: bool,
+}
+
+fn header(storage: &Storage) -> Option<Header> {
+    let json = storage.meta(META_KEY)?;
+    serde_json::from_str(&json)
+        .map_err(|e| warn!("Failed to read the encryption header: {}", e))
+        .ok()
+}
+
+pub fn status(storage: &Storage) -> Status {
+    let source = header(storage).map(|h| h.source);
+    Status {
+        enabled: source.is_some(),
+        source,
+        locked: storage.is_locked(),
+    }
+}
+
+/// The cipher `Storage` opens the database with, given its header row. With
+/// a passphrase, or when the keyring can't be read, it starts locked.
+pub(crate) fn startup_cipher(header_json: Option<&str>) -> Cipher {
+    let header: Header = match header_json.map(serde_json::from_str) {
+        None => return Cipher::Off,
+        Some(Ok(header)) => header,
+        Some(Err(e)) => {
+            warn!("Failed to read the encryption header: {}", e);
+            return Cipher::Locked;
+        }
+    };
+    if header.source == KeySource::Passphrase {
+        return Cipher::Locked;
+    }
+    match header.key(None) {
+        Ok(key) => Cipher::On(key),
+        Err(e) => {
+            warn!("Failed to get the database key from the keyring: {}", e);
+            Cipher::Locked
+        }
+    }
+}
+
+/// Load the database with the master passphrase. With the key in the
+/// keyring, the keyring is tried again instead.
+pub fn unlock(storage: &Storage, passphrase: &str) -> Result<(), String> {
+    if !storage.is_locked() {
+        return Ok(());
+    }
+    let header = header(storage).ok_or("Encryption is off")?;
+    storage.unlock_with(header.key(Some(passphrase))?)
+}
+
+/// Turn encryption on, keeping the key in the keyring, or wrapped with
+/// `passphrase` if given, and encrypt everything in place
+pub fn enable(storage: &Storage, passphrase: Option<&str>) -> Result<(), String> {
+    if header(storage).is_some() {
+        return Err("Encryption is already on".into());
+    }
+    let key = Key::generate();
+    let header = match passphrase {
+        Some(passphrase) => {
+            if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+                return Err(format!("The passphrase needs at least {} characters", MIN_PASSPHRASE_LEN));
+            }
+            Header::new(&key, KeySource::Passphrase, Some(WrappedKey::new(&key, passphrase)?))?
+        }
+        None => {
+            save_keyring_key(&key)?;
+            Header::new(&key, KeySource::Keyring, None)?
+        }
+    };
+    let json = serde_json::to_string(&header).map_err(|e| e.to_string())?;
+    storage.encrypt_in_place(key, &json)?;
+    info!("Encryption at rest enabled ({:?})", header.source);
+    Ok(())
+}
+
+/// Decrypt everything in place and turn encryption off. With a master
+/// passphrase, it has to be given again.
+pub fn disable(storage: &Storage, passphrase: Option<&str>) -> Result<(), String> {
+    let header = header(storage).ok_or("Encryption is off")?;
+    if storage.is_locked() {
+        return Err(LOCKED.into());
+    }
+    if header.source == KeySource::Passphrase {
+        header.key(passphrase)?;
+    }
+    storage.decrypt_in_place()?;
+    if header.source == KeySource::Keyring {
+        if let Err(e) = keyring_entry().and_then(|entry| entry.delete_credential().map_err(|e| e.to_string())) {
+            warn!("Failed to remove the database key from the keyring: {}", e);
+        }
+    }
+    info!("Encryption at rest disabled");
+    Ok(())
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| e.to_string())
+}
+
+fn keyring_key() -> Result<Key, String> {
+    let secret = zeroize::Zeroizing::new(keyring_entry()?.get_secret().map_err(|e| e.to_string())?);
+    Key::from_bytes(&secret)
+}
+
+fn save_keyring_key(key: &Key) -> Result<(), String> {
+    keyring_entry()?
+        .set_secret(key.as_bytes())
+        .map_err(|e| format!("Failed to save the key in the keyring: {}", e))
+}
@@ -0,0 +1,127 @@
+use crate::models::FileEntry;
+use std::path::{Path, PathBuf};
+
+/// Build file entries (URI, size, MIME type) for copied paths
+pub fn entries_from_paths(paths: &[PathBuf]) -> Vec<FileEntry> {
+    paths
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(path).ok();
+            let is_dir = meta.as_ref().map(|m| m.is_dir()).unwrap_or(false);
+            FileEntry {
+                uri: path_to_uri(path),
+                size: meta.filter(|m| m.is_file()).map(|m| m.len()),
+                mime: Some(if is_dir {
+                    "inode/directory".to_string()
+                } else {
+                    guess_mime(path).to_string()
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Local paths of a files item, skipping anything that isn't a `file://` URI
+pub fn entry_paths(entries: &[FileEntry]) -> Vec<PathBuf> {
+    entries.iter().filter_map(|f| uri_to_path(&f.uri)).collect()
+}
+
+/// Text form of a file list: one path per line.
+/// Stored as the item's text so search and dedup work on it.
+pub fn paths_text(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Convert a local path to a `file://` URI, percent-encoding as `text/uri-list` expects
+pub fn path_to_uri(path: &Path) -> String {
+    let raw = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    // Windows drive paths ("C:/...") need a leading slash
+    if !raw.starts_with('/') {
+        uri.push('/');
+    }
+    for b in raw.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Convert a `file://` URI back to a local path
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Drop an optional host ("file://localhost/...")
+    let rest = &rest[rest.find('/')?..];
+
+    let bytes = rest.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = rest.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    let path = String::from_utf8(decoded).ok()?;
+
+    // "/C:/dir" -> "C:/dir" on Windows
+    #[cfg(target_os = "windows")]
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] => path[1..].to_string(),
+        _ => path,
+    };
+
+    Some(PathBuf::from(path))
+}
+
+/// Best-effort MIME type from the file extension
+fn guess_mime(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "txt" | "log" | "md" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "rtf" => "application/rtf",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
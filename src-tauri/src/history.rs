@@ -0,0 +1,180 @@
+//! History queries: filtering by app, tab and search query, then sorting and
+//! paging. Kept apart from the `get_history` command so it only needs the data
+//! and the search index, not the app state.
+
+use crate::models::{normalize_app, AppData, HistoryResult, Item, SortOrder, Tab, TabMatch};
+use crate::query::{Matcher, TabFilter};
+use crate::search::SearchIndex;
+use crate::tabs;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Parameters of a history query, all optional
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub search: Option<String>,
+    pub tab_id: Option<String>,
+    /// Zero-based page number
+    pub page: Option<usize>,
+    /// Items per page, 500 by default
+    pub page_size: Option<usize>,
+    /// Newest first, or by relevance when searching, by default
+    pub sort: Option<SortOrder>,
+    pub source_app: Option<String>,
+    pub tab_ids: Option<Vec<String>>,
+    pub tab_match: Option<TabMatch>,
+    /// Defaults to the `include_child_tabs` setting
+    pub include_children: Option<bool>,
+}
+
+/// One page of the history items matching `query`
+pub fn query(data: &AppData, index: &SearchIndex, query: &HistoryQuery) -> HistoryResult {
+    let mut items: Vec<&Item> = data.items.iter().collect();
+
+    // Filter by the app items were copied from
+    if let Some(app) = query.source_app.as_deref().map(normalize_app).filter(|a| !a.is_empty()) {
+        items.retain(|i| i.source_app.as_deref().map(normalize_app).as_deref() == Some(app.as_str()));
+    }
+
+    // Filter by tab, including the saved query of smart tabs
+    // and, unless turned off, of the tabs nested under it
+    let include_children = query.include_children.unwrap_or(data.settings.include_child_tabs);
+    if let Some(filter) = tab_filter(&data.tabs, index, query.tab_id.as_deref(), include_children) {
+        items.retain(|i| filter.matches(i));
+    }
+    // Filter by several tabs at once: items in any (default) or all of them
+    let filters: Vec<TabFilter> = query
+        .tab_ids
+        .iter()
+        .flatten()
+        .filter_map(|id| tab_filter(&data.tabs, index, Some(id), include_children))
+        .collect();
+    if !filters.is_empty() {
+        match query.tab_match.unwrap_or_default() {
+            TabMatch::Any => items.retain(|i| filters.iter().any(|f| f.matches(i))),
+            TabMatch::All => items.retain(|i| filters.iter().all(|f| f.matches(i))),
+        }
+    }
+
+    // Filter by search query: field filters, boolean operators and ranked
+    // full-text terms (see `query.rs`)
+    let matcher = match query.search.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        Some(q) => match crate::query::parse(q) {
+            Ok(expr) => Some(Matcher::new(expr, index)),
+            Err(error) => {
+                return HistoryResult {
+                    items: vec![],
+                    total: 0,
+                    has_more: false,
+                    highlights: HashMap::new(),
+                    error: Some(error),
+                }
+            }
+        },
+        None => None,
+    };
+    let mut scores = HashMap::new();
+    if let Some(ref m) = matcher {
+        items.retain(|i| m.matches(i));
+        if m.has_text() {
+            scores = items.iter().map(|i| (i.id.clone(), m.score(i))).collect();
+        }
+    }
+
+    let sort = query.sort.unwrap_or(if scores.is_empty() {
+        SortOrder::Newest
+    } else {
+        SortOrder::Relevance
+    });
+    sort_items(&mut items, sort, &scores);
+
+    let total = items.len();
+    let page_size = query.page_size.unwrap_or(500).max(1);
+    let start = query.page.unwrap_or(0).saturating_mul(page_size).min(total);
+    let end = start.saturating_add(page_size).min(total);
+    let items: Vec<Item> = items[start..end].iter().map(|i| i.masked()).collect();
+
+    let highlights = match matcher {
+        Some(ref m) if m.has_text() => {
+            let terms = m.positive_terms();
+            items
+                .iter()
+                .map(|i| (i.id.clone(), crate::search::highlights(i, &terms)))
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .collect()
+        }
+        _ => HashMap::new(),
+    };
+
+    HistoryResult {
+        items,
+        total,
+        has_more: end < total,
+        highlights,
+        error: None,
+    }
+}
+
+/// Membership test for `tab_id`, optionally with its child tabs, or None for
+/// the "all" tab
+pub fn tab_filter(tabs: &[Tab], index: &SearchIndex, tab_id: Option<&str>, include_children: bool) -> Option<TabFilter> {
+    let tid = tab_id.filter(|t| *t != "all")?;
+    let ids = if include_children {
+        tabs::with_descendants(tabs, tid)
+    } else {
+        vec![tid.to_string()]
+    };
+    Some(TabFilter::any(ids.iter().map(|id| match tabs.iter().find(|t| t.id == *id) {
+        Some(tab) => TabFilter::new(tab, index),
+        None => TabFilter::by_id(id),
+    })))
+}
+
+/// Sort: pinned first, then by the requested order. Ties fall back to the id
+/// so that pages stay stable between calls.
+fn sort_items(items: &mut [&Item], sort: SortOrder, scores: &HashMap<String, f32>) {
+    items.sort_by(|a, b| {
+        let pinned = if sort == SortOrder::Relevance {
+            Ordering::Equal
+        } else {
+            b.pinned.cmp(&a.pinned)
+        };
+        pinned
+            .then_with(|| compare_items(a, b, sort, scores))
+            .then_with(|| b.id.cmp(&a.id))
+    });
+}
+
+fn compare_items(a: &Item, b: &Item, sort: SortOrder, scores: &HashMap<String, f32>) -> Ordering {
+    match sort {
+        SortOrder::Newest => b.timestamp.cmp(&a.timestamp),
+        SortOrder::Relevance => {
+            let score = |i: &Item| scores.get(&i.id).copied().unwrap_or(0.0);
+            score(b)
+                .total_cmp(&score(a))
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+        }
+        SortOrder::Oldest => a.timestamp.cmp(&b.timestamp),
+        SortOrder::MostPasted => b
+            .paste_count
+            .cmp(&a.paste_count)
+            .then_with(|| b.timestamp.cmp(&a.timestamp)),
+        SortOrder::Alphabetical => a
+            .get_text()
+            .to_lowercase()
+            .cmp(&b.get_text().to_lowercase())
+            .then_with(|| b.timestamp.cmp(&a.timestamp)),
+        SortOrder::Label => {
+            // Labelled items first, alphabetically; the rest newest first
+            match (a.label.is_empty(), b.label.is_empty()) {
+                (false, true) => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                _ => a
+                    .label
+                    .to_lowercase()
+                    .cmp(&b.label.to_lowercase())
+                    .then_with(|| b.timestamp.cmp(&a.timestamp)),
+            }
+        }
+    }
+}
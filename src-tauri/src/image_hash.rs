@@ -0,0 +1,39 @@
+//! Perceptual hashing for spotting near-duplicate images.
+//!
+//! dHash shrinks the image to 9x8 grayscale and records, for each row, whether
+//! each pixel is brighter than its right neighbour. Rescaling and recompression
+//! barely move those gradients, so copies of the same picture end up a few bits
+//! apart, while unrelated images differ in about half of the 64 bits.
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+/// 64-bit difference hash of an image
+pub fn dhash(image: &RgbaImage) -> u64 {
+    let gray = imageops::grayscale(image);
+    let small = imageops::resize(&gray, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Number of differing bits between two hashes (0 = perceptually identical)
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Hashes are stored as 16 hex digits, since JSON numbers can't hold all 64 bits
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn from_hex(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex, 16).ok()
+}
@@ -1,9 +1,26 @@
-mod clipboard_watcher;
+pub mod cf_html;
+pub mod classifier;
+pub mod clipboard_backend;
+#[cfg(target_os = "linux")]
+mod clipboard_linux;
+pub mod clipboard_source;
+pub mod clipboard_watcher;
 mod commands;
+pub mod encryption;
+pub mod files;
+pub mod history;
+pub mod image_hash;
 pub mod license;
-mod models;
+pub mod models;
 mod paste;
-mod storage;
+pub mod query;
+pub mod rules;
+mod screen_lock;
+pub mod search;
+pub mod sensitive;
+pub mod storage;
+pub mod tabs;
+pub mod vault;
 
 use log::info;
 use std::sync::{Arc, Mutex};
@@ -56,6 +73,15 @@ pub fn run() {
                 storage.clone(),
             );
 
+            // Remove image files no item uses any more
+            storage::start_garbage_collector(storage.clone());
+
+            // Drop short-lived sensitive items once they expire
+            clipboard_watcher::start_expiry_sweeper(app.handle().clone(), storage.clone());
+
+            // Close the vault session on inactivity, sleep and screen lock
+            vault::start_auto_lock(app.handle().clone(), storage.clone());
+
             // Setup tray icon
             setup_tray(app)?;
 
@@ -74,11 +100,20 @@ pub fn run() {
             commands::create_tab,
             commands::rename_tab,
             commands::delete_tab,
+            commands::move_tab,
+            commands::reorder_tabs,
+            commands::set_tab_query,
+            commands::get_tab_counts,
+            commands::set_tab_rules,
+            commands::apply_tab_rules,
             commands::get_history,
             commands::delete_item,
             commands::delete_multiple,
             commands::pin_item,
             commands::move_to_tab,
+            commands::add_to_tab,
+            commands::remove_from_tab,
+            commands::set_item_tabs,
             commands::label_item,
             commands::copy_to_clipboard,
             commands::bulk_copy,
@@ -97,16 +132,25 @@ pub fn run() {
             commands::check_for_update,
             commands::install_update,
             commands::get_image_url,
+            commands::get_thumbnail_url,
             commands::capture_screen,
             commands::copy_image_to_clipboard,
             commands::window_fullscreen,
             commands::save_screenshot_to_file,
             commands::set_vault_pin,
             commands::verify_vault_pin,
+            commands::change_vault_pin,
+            commands::reset_vault_pin,
+            commands::new_vault_recovery_key,
+            commands::lock_vault,
             commands::has_vault_pin,
             commands::move_to_vault,
             commands::remove_from_vault,
             commands::get_vault_items,
+            commands::get_encryption_status,
+            commands::enable_encryption,
+            commands::disable_encryption,
+            commands::unlock_database,
             commands::get_machine_id,
             commands::activate_license,
             commands::check_license,
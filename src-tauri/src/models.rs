@@ -1,12 +1,65 @@
+use crate::query::QueryError;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 /// A tab/category for organizing clipboard items
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Tab {
     pub id: String,
     pub name: String,
     pub icon: String,
     pub system: bool,
+    /// New items matching any of these are filed into the tab
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<TabRule>,
+    /// Saved search (see `query.rs`) making this a smart tab: it also shows
+    /// every item the query matches, whatever tab it was filed into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Tab this one is nested under (None = top level)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Order among the tabs with the same parent
+    #[serde(default)]
+    pub position: usize,
+}
+
+/// Saved query of the built-in links tab
+pub const LINKS_QUERY: &str = "category:link";
+
+impl Tab {
+    /// A plain tab without rules or query
+    pub fn new(id: &str, name: &str, icon: &str, system: bool) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            icon: icon.into(),
+            system,
+            rules: Vec::new(),
+            query: None,
+            parent_id: None,
+            position: 0,
+        }
+    }
+}
+
+/// A condition for filing captured items into a tab
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TabRule {
+    /// The text matches a regular expression
+    TextMatches { pattern: String },
+    /// Copied from this app (compared like `normalize_app`)
+    SourceApp { app: String },
+    /// The classifier put the item in this category
+    Category { category: String },
+    /// The classifier gave the item this tag
+    Tag { tag: String },
+    /// The text is longer than this many characters
+    LongerThan { chars: usize },
+    /// The text is shorter than this many characters
+    ShorterThan { chars: usize },
 }
 
 /// The kind of clipboard item
@@ -15,6 +68,21 @@ pub struct Tab {
 pub enum ItemKind {
     Text,
     Image,
+    /// A list of files copied from a file manager
+    Files,
+}
+
+/// One file of a `Files` item
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+    /// `file://` URI, as in `text/uri-list`
+    pub uri: String,
+    /// Size in bytes at copy time (None for directories or missing files)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
 }
 
 /// A clipboard history item
@@ -29,17 +97,45 @@ pub struct Item {
     /// HTML content for rich text formatting (bold, italic, colors)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub content_html: Option<String>,
+    /// RTF content, kept for apps that prefer it over HTML (Word, WordPad, TextEdit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_rtf: Option<String>,
     /// Relative path to saved image (for image items)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub image_path: Option<String>,
+    /// Image dimensions in pixels and file size in bytes (for image items)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<u64>,
+    /// Perceptual hash (dHash, hex) used to spot re-copies of the same image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_dhash: Option<String>,
     /// MIME type
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime: Option<String>,
-    /// Category: text, link, email, phone, code, image
-    pub category: String,
-    /// Which tab this item belongs to (null = no specific tab)
+    /// Copied files (for files items)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileEntry>,
+    /// Every clipboard flavor captured with the item: MIME type -> blob name in the flavors dir
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub flavors: BTreeMap<String, String>,
+    /// Application the content was copied from, where the platform can tell
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub tab_id: Option<String>,
+    pub source_app: Option<String>,
+    /// Primary category: text, link, email, phone, color, json, yaml, sql,
+    /// path, ip, uuid, date, number, markdown, code, image, file, sensitive
+    pub category: String,
+    /// Secondary tags from the classifier: other categories the content also
+    /// fits, plus details like the programming language or currency
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Tabs this item was filed into, by hand or by tab rules. Older
+    /// versions stored a single `tabId`, which is read as a one-tab list.
+    #[serde(default, alias = "tabId", deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub tab_ids: Vec<String>,
     /// ISO 8601 timestamp
     pub timestamp: String,
     pub pinned: bool,
@@ -51,9 +147,58 @@ pub struct Item {
     /// Whether this item is in the vault
     #[serde(default)]
     pub in_vault: bool,
+    /// Text, HTML and RTF of a vault item, encrypted with the vault key
+    /// (base64, see `vault.rs`). The plain fields are empty while this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed: Option<String>,
+    /// How many times this item has been pasted from CoPas
+    #[serde(default)]
+    pub paste_count: u32,
+    /// Secret content (e.g. copied from a password manager): kept in memory
+    /// only and shown masked
+    #[serde(default)]
+    pub sensitive: bool,
+    /// ISO 8601 time after which the item is dropped from history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::One(id)) => vec![id],
+        Some(OneOrMany::Many(ids)) => ids,
+    })
 }
 
 impl Item {
+    /// Whether the item was filed into `tab_id`
+    pub fn in_tab(&self, tab_id: &str) -> bool {
+        self.tab_ids.iter().any(|t| t == tab_id)
+    }
+
+    /// File the item into `tab_id`. Returns false if it already was.
+    pub fn add_tab(&mut self, tab_id: &str) -> bool {
+        if self.in_tab(tab_id) {
+            return false;
+        }
+        self.tab_ids.push(tab_id.to_string());
+        true
+    }
+
+    /// Take the item out of `tab_id`. Returns false if it wasn't in it.
+    pub fn remove_tab(&mut self, tab_id: &str) -> bool {
+        let before = self.tab_ids.len();
+        self.tab_ids.retain(|t| t != tab_id);
+        self.tab_ids.len() != before
+    }
+
     /// Get displayable text content (for legacy compat)
     pub fn get_text(&self) -> &str {
         self.content_text
@@ -61,6 +206,41 @@ impl Item {
             .or(self.content.as_deref())
             .unwrap_or("")
     }
+
+    /// Copy of the item that is safe to show: sensitive and vault text is
+    /// replaced with a mask
+    pub fn masked(&self) -> Item {
+        let mut item = self.clone();
+        if item.sensitive || item.sealed.take().is_some() {
+            item.content_text = Some(MASK.into());
+            item.content_html = None;
+            item.content_rtf = None;
+            item.content = None;
+        }
+        item
+    }
+
+    /// Whether `expires_at` has passed
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at
+            .as_deref()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .is_some_and(|t| t <= now)
+    }
+}
+
+/// Shown in place of sensitive text
+pub const MASK: &str = "••••••••";
+
+/// What to do with copies a password manager marks as concealed or transient
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConcealedMode {
+    /// Don't record them at all
+    #[default]
+    Skip,
+    /// Record a masked, memory-only item that expires after `sensitive_ttl`
+    Mask,
 }
 
 /// Application settings
@@ -79,13 +259,45 @@ pub struct Settings {
     pub shortcut_screenshot: String,
     #[serde(default = "default_poll_interval")]
     pub poll_interval: u64,
+    /// Also record the Linux PRIMARY selection (highlighted text)
+    #[serde(default)]
+    pub capture_primary_selection: bool,
+    /// Bump an existing image to the top instead of recording a copied image
+    /// that looks the same. Off by default: distinct screenshots of one
+    /// window can hash alike.
+    #[serde(default)]
+    pub bump_similar_images: bool,
+    /// How many of the 64 perceptual hash bits may differ for a copied image
+    /// to count as a re-copy of an existing one (0 = identical hashes only)
+    #[serde(default = "default_image_similarity_threshold")]
+    pub image_similarity_threshold: u32,
+    /// Apps whose copies are never recorded (window class or process name)
+    #[serde(default = "default_excluded_apps")]
+    pub excluded_apps: Vec<String>,
+    /// If not empty, only copies from these apps are recorded
+    #[serde(default)]
+    pub allowed_apps: Vec<String>,
+    /// Handling of copies marked concealed by password managers
+    #[serde(default)]
+    pub concealed_clipboard: ConcealedMode,
+    /// Flag copied card numbers, IBANs, API keys, private keys and one-time
+    /// codes as sensitive (see `sensitive.rs`)
+    #[serde(default = "default_true")]
+    pub detect_sensitive: bool,
+    /// Seconds a masked sensitive item stays in history (0 = until restart)
+    #[serde(default = "default_sensitive_ttl")]
+    pub sensitive_ttl: u64,
+    /// Opening a tab also shows the items of the tabs nested under it
+    #[serde(default = "default_true")]
+    pub include_child_tabs: bool,
     #[serde(default = "default_true")]
     pub show_notifications: bool,
     #[serde(default)]
     pub auto_start: bool,
     #[serde(default = "default_paste_delimiter")]
     pub paste_delimiter: String,
-    /// Vault PIN hash (empty = no vault set up yet)
+    /// Unsalted PIN hash from before the vault was encrypted. Replaced by an
+    /// Argon2id-wrapped vault key the first time the vault is unlocked.
     #[serde(default)]
     pub vault_pin_hash: String,
     /// Vault auto-lock timeout in seconds (0 = never)
@@ -105,6 +317,15 @@ impl Default for Settings {
             shortcut_paste: default_shortcut_paste(),
             shortcut_screenshot: default_shortcut_screenshot(),
             poll_interval: default_poll_interval(),
+            capture_primary_selection: false,
+            bump_similar_images: false,
+            image_similarity_threshold: default_image_similarity_threshold(),
+            excluded_apps: default_excluded_apps(),
+            allowed_apps: Vec::new(),
+            concealed_clipboard: ConcealedMode::default(),
+            detect_sensitive: true,
+            sensitive_ttl: default_sensitive_ttl(),
+            include_child_tabs: true,
             show_notifications: true,
             auto_start: false,
             paste_delimiter: default_paste_delimiter(),
@@ -115,8 +336,38 @@ impl Default for Settings {
     }
 }
 
+impl Settings {
+    /// Whether copies from `app` should be recorded. When an allow list is set,
+    /// copies from unknown apps are skipped too.
+    pub fn records_app(&self, app: Option<&str>) -> bool {
+        let app = app.map(normalize_app);
+        let listed = |list: &[String]| {
+            app.as_ref()
+                .is_some_and(|app| list.iter().any(|entry| normalize_app(entry) == *app))
+        };
+        if listed(&self.excluded_apps) {
+            return false;
+        }
+        self.allowed_apps.is_empty() || listed(&self.allowed_apps)
+    }
+}
+
+/// App names compare case-insensitively, with or without ".exe"
+pub fn normalize_app(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    name.strip_suffix(".exe").map(str::to_string).unwrap_or(name)
+}
+
 fn default_theme() -> String { "light".into() }
 fn default_max_history() -> usize { 1000 }
+fn default_image_similarity_threshold() -> u32 { 2 }
+fn default_excluded_apps() -> Vec<String> {
+    ["1Password", "Bitwarden", "KeePassXC", "KeePass", "Keychain Access", "Dashlane", "LastPass"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+fn default_sensitive_ttl() -> u64 { 60 }
 fn default_shortcut_toggle() -> String {
     if cfg!(target_os = "macos") {
         "Cmd+Shift+V".into()
@@ -148,21 +399,37 @@ fn default_vault_timeout() -> u64 { 300 }
 #[serde(rename_all = "camelCase")]
 pub struct AppData {
     pub tabs: Vec<Tab>,
+    /// History items. Vault items are never in here, so history queries,
+    /// tab counts and bulk commands can't reach them.
     pub items: Vec<Item>,
+    /// Vault items, only handled through `vault.rs`
+    #[serde(default)]
+    pub vault: Vec<Item>,
     pub settings: Settings,
     #[serde(default)]
     pub migrated_from_electron: bool,
 }
 
+impl AppData {
+    /// Move vault items found among `items` into `vault`
+    pub fn partition_vault(&mut self) {
+        let (vault, items): (Vec<Item>, Vec<Item>) =
+            std::mem::take(&mut self.items).into_iter().partition(|i| i.in_vault);
+        self.items = items;
+        self.vault.extend(vault);
+    }
+}
+
 impl Default for AppData {
     fn default() -> Self {
         Self {
             tabs: vec![
-                Tab { id: "all".into(), name: "Tất cả".into(), icon: "📋".into(), system: true },
-                Tab { id: "links".into(), name: "Liên kết".into(), icon: "🔗".into(), system: true },
-                Tab { id: "important".into(), name: "Quan trọng".into(), icon: "⭐".into(), system: false },
+                Tab::new("all", "Tất cả", "📋", true),
+                Tab { query: Some(LINKS_QUERY.into()), ..Tab::new("links", "Liên kết", "🔗", true) },
+                Tab::new("important", "Quan trọng", "⭐", false),
             ],
             items: vec![],
+            vault: vec![],
             settings: Settings::default(),
             migrated_from_electron: false,
         }
@@ -175,12 +442,62 @@ impl Default for AppData {
 pub struct Stats {
     pub total_items: usize,
     pub pinned_items: usize,
+    /// Items in the vault, which `total_items` doesn't count
+    pub vault_items: usize,
     pub storage_size: u64,
+    /// Size of the image files on disk
+    pub images_size: u64,
+    /// Bytes freed by garbage collection of images and flavors so far
+    pub reclaimed_bytes: u64,
+}
+
+/// How history queries combine several tabs
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TabMatch {
+    /// Items in at least one of the tabs
+    #[default]
+    Any,
+    /// Items in every one of the tabs
+    All,
+}
+
+/// Sort order for history queries (pinned items come first, except when
+/// sorting search results by relevance)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    #[default]
+    Newest,
+    Relevance,
+    Oldest,
+    MostPasted,
+    Alphabetical,
+    Label,
 }
 
 /// History query result
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct HistoryResult {
     pub items: Vec<Item>,
     pub total: usize,
+    /// Whether there are more items after this page
+    pub has_more: bool,
+    /// Search match ranges for the returned items, keyed by item id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub highlights: HashMap<String, Vec<MatchRange>>,
+    /// Set when the search query could not be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<QueryError>,
+}
+
+/// A highlighted search match inside an item field.
+/// Offsets are UTF-16 code units so the frontend can slice JS strings directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRange {
+    /// "text" or "label"
+    pub field: String,
+    pub start: usize,
+    pub end: usize,
 }
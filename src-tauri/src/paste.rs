@@ -1,7 +1,7 @@
-use arboard::Clipboard;
+use crate::clipboard_backend::{self, ClipboardImage, Flavor};
 use log::{error, info, warn};
 use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
@@ -17,7 +17,7 @@ pub static LAST_PASTE_HASH: once_cell::sync::Lazy<Mutex<Option<Vec<u8>>>> =
 // ─── Text paste ───────────────────────────────────────────────────────────────
 
 /// Write text to clipboard and simulate paste via 3 reliable steps:
-///   1. pbcopy (macOS) or arboard (other) to set clipboard
+///   1. the platform clipboard backend to set clipboard
 ///   2. osascript to activate target app
 ///   3. osascript to send Cmd+V
 pub fn paste_text_and_simulate(text: &str) {
@@ -28,7 +28,7 @@ pub fn paste_text_and_simulate(text: &str) {
     #[cfg(target_os = "macos")]
     {
         let prev_app = get_previous_app();
-        macos_set_clipboard_text(text);
+        set_clipboard_text(text);
         macos_activate_app(&prev_app);
         thread::sleep(Duration::from_millis(300));
         macos_send_paste();
@@ -36,7 +36,7 @@ pub fn paste_text_and_simulate(text: &str) {
 
     #[cfg(not(target_os = "macos"))]
     {
-        set_clipboard_arboard(text);
+        set_clipboard_text(text);
         activate_and_paste();
     }
 
@@ -46,74 +46,33 @@ pub fn paste_text_and_simulate(text: &str) {
 
 // ─── Rich text paste ──────────────────────────────────────────────────────────
 
-/// Paste text WITH HTML formatting (bold, italic, colors)
-/// Sets both plain text and HTML on macOS clipboard via NSPasteboard
-pub fn paste_rich_text_and_simulate(text: &str, html: &str) {
-    info!("paste_rich: text_len={}, html_len={}", text.len(), html.len());
+/// Paste text WITH formatting (bold, italic, colors).
+/// Sets plain text, HTML and RTF together through the platform backend:
+/// NSPasteboard on macOS, CF_HTML / Rich Text Format on Windows and
+/// `text/html` / `text/rtf` targets on Linux.
+pub fn paste_rich_text_and_simulate(text: &str, html: &str, rtf: Option<&str>) {
+    info!("paste_rich: text_len={}, html_len={}, has_rtf={}", text.len(), html.len(), rtf.is_some());
     PASTE_IN_PROGRESS.store(true, Ordering::SeqCst);
     save_paste_hash(text);
 
-    #[cfg(target_os = "macos")]
-    {
-        let prev_app = get_previous_app();
-
-        // Try NSPasteboard to set both HTML + text
-        let html_tmp = "/tmp/copas_paste_html.html";
-        let text_tmp = "/tmp/copas_paste_text.txt";
-
-        let rich_ok = if std::fs::write(html_tmp, html).is_ok()
-            && std::fs::write(text_tmp, text).is_ok()
-        {
-            let script = r#"
-                use framework "AppKit"
-                set htmlContent to read (POSIX file "/tmp/copas_paste_html.html") as «class utf8»
-                set textContent to read (POSIX file "/tmp/copas_paste_text.txt") as «class utf8»
-                set pb to current application's NSPasteboard's generalPasteboard()
-                pb's clearContents()
-                pb's setString:textContent forType:(current application's NSPasteboardTypeString)
-                pb's setString:htmlContent forType:(current application's NSPasteboardTypeHTML)
-                return "OK"
-            "#;
-            let result = std::process::Command::new("osascript")
-                .arg("-l").arg("AppleScript")
-                .arg("-e").arg(script)
-                .output();
-            match result {
-                Ok(out) if out.status.success() => {
-                    info!("paste_rich: NSPasteboard HTML+text OK");
-                    true
-                }
-                Ok(out) => {
-                    let e = String::from_utf8_lossy(&out.stderr);
-                    warn!("paste_rich: NSPasteboard failed: {}", e);
-                    false
-                }
-                Err(e) => { warn!("paste_rich: osascript error: {}", e); false }
-            }
-        } else {
+    // Fall back to plain text if the rich formats can't be set
+    let html = Some(html).filter(|h| !h.is_empty());
+    let rich_ok = match clipboard_backend::system().and_then(|mut cb| cb.set_rich(text, html, rtf)) {
+        Ok(()) => {
+            info!("paste_rich: rich text set OK");
+            true
+        }
+        Err(e) => {
+            warn!("paste_rich: {}", e);
             false
-        };
-
-        // Cleanup temp files
-        let _ = std::fs::remove_file(html_tmp);
-        let _ = std::fs::remove_file(text_tmp);
-
-        // If rich clipboard failed, fallback to plain text via pbcopy
-        if !rich_ok {
-            info!("paste_rich: falling back to plain text");
-            macos_set_clipboard_text(text);
         }
-
-        macos_activate_app(&prev_app);
-        thread::sleep(Duration::from_millis(300));
-        macos_send_paste();
+    };
+    if !rich_ok {
+        info!("paste_rich: falling back to plain text");
+        set_clipboard_text(text);
     }
 
-    #[cfg(not(target_os = "macos"))]
-    {
-        set_clipboard_arboard(text);
-        activate_and_paste();
-    }
+    activate_and_paste();
 
     thread::sleep(Duration::from_millis(2000));
     PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
@@ -121,12 +80,13 @@ pub fn paste_rich_text_and_simulate(text: &str, html: &str) {
 
 // ─── Image paste ──────────────────────────────────────────────────────────────
 
-/// Write image to clipboard and simulate paste
-pub fn paste_image_and_simulate(image_path: &Path) {
-    info!("paste_image: {:?}", image_path);
+/// Write an encoded image to the clipboard and simulate paste. Images are
+/// read through `Storage`, which may have to decrypt them.
+pub fn paste_image_data_and_simulate(data: &[u8]) {
+    info!("paste_image: {} bytes", data.len());
     PASTE_IN_PROGRESS.store(true, Ordering::SeqCst);
 
-    let img = match image::open(image_path) {
+    let img = match image::load_from_memory(data) {
         Ok(img) => img,
         Err(e) => {
             error!("paste_image: open failed: {}", e);
@@ -139,7 +99,7 @@ pub fn paste_image_and_simulate(image_path: &Path) {
     let (width, height) = rgba.dimensions();
     let bytes = rgba.into_raw();
 
-    let mut clipboard = match Clipboard::new() {
+    let mut clipboard = match clipboard_backend::system() {
         Ok(c) => c,
         Err(e) => {
             error!("paste_image: clipboard open failed: {}", e);
@@ -148,13 +108,13 @@ pub fn paste_image_and_simulate(image_path: &Path) {
         }
     };
 
-    let img_data = arboard::ImageData {
+    let img_data = ClipboardImage {
         width: width as usize,
         height: height as usize,
-        bytes: std::borrow::Cow::Owned(bytes),
+        bytes,
     };
 
-    if let Err(e) = clipboard.set_image(img_data) {
+    if let Err(e) = clipboard.set_image(&img_data) {
         error!("paste_image: set_image failed: {}", e);
         PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
         return;
@@ -168,6 +128,60 @@ pub fn paste_image_and_simulate(image_path: &Path) {
     PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
 }
 
+// ─── Flavor paste ─────────────────────────────────────────────────────────────
+
+/// Restore every captured flavor of an item and simulate paste.
+/// Returns false without pasting if the flavors couldn't be put on the
+/// clipboard, so the caller can fall back to a single format.
+pub fn paste_flavors_and_simulate(text: &str, flavors: &[Flavor]) -> bool {
+    info!("paste_flavors: {} flavors", flavors.len());
+    PASTE_IN_PROGRESS.store(true, Ordering::SeqCst);
+    save_paste_hash(text);
+
+    let result = clipboard_backend::system().and_then(|mut cb| cb.set_flavors(flavors));
+    if let Err(e) = result {
+        warn!("paste_flavors: {}", e);
+        PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
+        return false;
+    }
+
+    activate_and_paste();
+
+    thread::sleep(Duration::from_millis(2000));
+    PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
+    true
+}
+
+// ─── Files paste ──────────────────────────────────────────────────────────────
+
+/// Put a file list on the clipboard and simulate paste, so a file manager
+/// copies the files again
+pub fn paste_files_and_simulate(paths: &[PathBuf]) {
+    info!("paste_files: {} files", paths.len());
+    PASTE_IN_PROGRESS.store(true, Ordering::SeqCst);
+    save_paste_hash(&crate::files::paths_text(paths));
+
+    let mut clipboard = match clipboard_backend::system() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("paste_files: clipboard open failed: {}", e);
+            PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    if let Err(e) = clipboard.set_files(paths) {
+        error!("paste_files: set_files failed: {}", e);
+        PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
+        return;
+    }
+    drop(clipboard);
+
+    activate_and_paste();
+
+    thread::sleep(Duration::from_millis(2000));
+    PASTE_IN_PROGRESS.store(false, Ordering::SeqCst);
+}
+
 // ─── Bulk paste ───────────────────────────────────────────────────────────────
 
 /// Bulk paste text items combined with a delimiter
@@ -177,19 +191,22 @@ pub fn bulk_paste_text_and_simulate(contents: &[String], delimiter: &str) {
     paste_text_and_simulate(&combined);
 }
 
+/// Text, HTML and encoded image of one item in a mixed bulk paste
+pub type MixedItem = (Option<String>, Option<String>, Option<Vec<u8>>);
+
 /// Bulk paste mixed content (text + images) sequentially
-pub fn bulk_paste_mixed(items: &[(Option<String>, Option<String>, Option<String>)]) {
+pub fn bulk_paste_mixed(items: &[MixedItem]) {
     info!("bulk_paste_mixed: {} items", items.len());
 
-    for (i, (text, html, image_path)) in items.iter().enumerate() {
+    for (i, (text, html, image)) in items.iter().enumerate() {
         info!("bulk_paste_mixed: item {}/{}", i + 1, items.len());
 
-        if let Some(img_path) = image_path {
-            paste_image_and_simulate(Path::new(img_path));
+        if let Some(png) = image {
+            paste_image_data_and_simulate(png);
         } else if let Some(text) = text {
             if let Some(html) = html {
                 if !html.is_empty() {
-                    paste_rich_text_and_simulate(text, html);
+                    paste_rich_text_and_simulate(text, html, None);
                 } else {
                     paste_text_and_simulate(text);
                 }
@@ -223,31 +240,6 @@ fn get_previous_app() -> String {
         .unwrap_or_default()
 }
 
-/// Set clipboard text via pbcopy (macOS only, proven reliable)
-#[cfg(target_os = "macos")]
-fn macos_set_clipboard_text(text: &str) {
-    use std::io::Write;
-    let mut child = match std::process::Command::new("pbcopy")
-        .stdin(std::process::Stdio::piped())
-        .spawn()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            error!("pbcopy spawn failed: {}", e);
-            return;
-        }
-    };
-    if let Some(ref mut stdin) = child.stdin {
-        if let Err(e) = stdin.write_all(text.as_bytes()) {
-            error!("pbcopy write failed: {}", e);
-            return;
-        }
-    }
-    drop(child.stdin.take());
-    let _ = child.wait();
-    info!("pbcopy OK");
-}
-
 /// Activate app by name via osascript
 #[cfg(target_os = "macos")]
 fn macos_activate_app(app_name: &str) {
@@ -301,21 +293,18 @@ fn macos_send_paste() {
     }
 }
 
-/// Set clipboard via arboard (cross-platform fallback)
-#[allow(dead_code)]
-fn set_clipboard_arboard(text: &str) {
-    let mut clipboard = match Clipboard::new() {
+/// Set clipboard text through the platform backend
+fn set_clipboard_text(text: &str) {
+    let mut clipboard = match clipboard_backend::system() {
         Ok(c) => c,
         Err(e) => { error!("clipboard open failed: {}", e); return; }
     };
     if let Err(e) = clipboard.set_text(text) {
         error!("set_text failed: {}", e);
-        return;
     }
-    drop(clipboard);
 }
 
-/// Activate previous app and paste (image path, non-macOS text)
+/// Activate previous app and paste (image and rich text paths, non-macOS text)
 fn activate_and_paste() {
     #[cfg(target_os = "macos")]
     {
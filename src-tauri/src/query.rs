@@ -0,0 +1,564 @@
+//! Search box query language.
+//!
+//! ```text
+//! kind:image tab:important pinned:true after:2026-09-01 "exact phrase" -draft
+//! (category:link OR category:email) NOT label:old app:firefox tag:rust
+//! kind:image after:this-week on:yesterday after:7d
+//! ```
+//!
+//! Terms separated by spaces are AND-ed, `OR` (uppercase) joins alternatives,
+//! `-term` / `NOT term` negates, parentheses group. Bare words go through the
+//! full-text index (prefix, fuzzy and accent-insensitive), quoted phrases must
+//! appear verbatim (ignoring case and accents).
+
+use crate::models::{Item, ItemKind, Tab};
+use crate::search::{fold, SearchIndex};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use log::warn;
+use std::collections::HashMap;
+
+/// A query parse error. Offsets are UTF-16 code units into the query string.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parsed query expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// Folded word looked up in the search index
+    Word(String),
+    /// Folded phrase matched as a substring of text or label
+    Phrase(String),
+    Field(Filter),
+}
+
+/// A field filter over `Item`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Kind(ItemKind),
+    Category(String),
+    /// A classifier tag, e.g. `tag:rust`
+    Tag(String),
+    Tab(String),
+    Pinned(bool),
+    /// Folded substring of the label
+    Label(String),
+    /// Folded substring of the source app
+    App(String),
+    /// Half-open time range `[start, end)`
+    Time {
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    },
+}
+
+/// Parse a query string
+pub fn parse(input: &str) -> Result<Expr, QueryError> {
+    let tokens = lex(input)?;
+    let mut parser = Parser { tokens, pos: 0, input_len: utf16_len(input) };
+    let expr = parser.parse_or()?;
+    if let Some(tok) = parser.peek() {
+        return Err(QueryError {
+            message: "Unexpected ')'".into(),
+            start: tok.start,
+            end: tok.end,
+        });
+    }
+    Ok(expr)
+}
+
+/// A parsed query bound to the search index, ready to test items
+pub struct Matcher {
+    expr: Expr,
+    /// Words and phrases with whether they sit under a negation
+    words: Vec<(String, bool)>,
+    phrases: Vec<(String, bool)>,
+    /// word -> item id -> relevance score
+    word_hits: HashMap<String, HashMap<String, f32>>,
+}
+
+impl Matcher {
+    pub fn new(expr: Expr, index: &SearchIndex) -> Self {
+        let (mut words, mut phrases) = (Vec::new(), Vec::new());
+        collect_terms(&expr, false, &mut words, &mut phrases);
+        words.dedup();
+        phrases.dedup();
+        let word_hits = words
+            .iter()
+            .map(|(w, _)| (w.clone(), index.search(w)))
+            .collect();
+        Self { expr, words, phrases, word_hits }
+    }
+
+    /// Whether the item satisfies the query
+    pub fn matches(&self, item: &Item) -> bool {
+        self.eval(&self.expr, item)
+    }
+
+    /// Whether the query contains positive text terms (and so can be ranked)
+    pub fn has_text(&self) -> bool {
+        self.words.iter().chain(&self.phrases).any(|(_, negated)| !negated)
+    }
+
+    /// Relevance of a matching item: summed scores of its positive words,
+    /// with a bonus per matched phrase
+    pub fn score(&self, item: &Item) -> f32 {
+        let words: f32 = self
+            .words
+            .iter()
+            .filter(|(_, negated)| !negated)
+            .filter_map(|(w, _)| self.word_hits.get(w).and_then(|h| h.get(&item.id)))
+            .sum();
+        let phrases = self
+            .phrases
+            .iter()
+            .filter(|(p, negated)| !negated && phrase_matches(p, item))
+            .count();
+        words + phrases as f32 * 2.0
+    }
+
+    /// Positive words and phrases, joined for highlighting
+    pub fn positive_terms(&self) -> String {
+        self.words
+            .iter()
+            .chain(&self.phrases)
+            .filter(|(_, negated)| !negated)
+            .map(|(t, _)| t.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn eval(&self, expr: &Expr, item: &Item) -> bool {
+        match expr {
+            Expr::And(parts) => parts.iter().all(|e| self.eval(e, item)),
+            Expr::Or(parts) => parts.iter().any(|e| self.eval(e, item)),
+            Expr::Not(inner) => !self.eval(inner, item),
+            Expr::Word(w) => self
+                .word_hits
+                .get(w)
+                .map(|hits| hits.contains_key(&item.id))
+                .unwrap_or(false),
+            Expr::Phrase(p) => phrase_matches(p, item),
+            Expr::Field(filter) => filter.matches(item),
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, item: &Item) -> bool {
+        match self {
+            Filter::Kind(kind) => &item.kind == kind,
+            Filter::Category(c) => item.category.eq_ignore_ascii_case(c),
+            Filter::Tag(t) => item.tags.iter().any(|tag| tag.eq_ignore_ascii_case(t)),
+            Filter::Tab(t) => item.in_tab(t),
+            Filter::Pinned(p) => item.pinned == *p,
+            Filter::Label(l) => fold(&item.label).contains(l.as_str()),
+            Filter::App(a) => item.source_app.as_deref().is_some_and(|app| fold(app).contains(a.as_str())),
+            Filter::Time { start, end } => match DateTime::parse_from_rfc3339(&item.timestamp) {
+                Ok(ts) => {
+                    let ts = ts.with_timezone(&Utc);
+                    start.map_or(true, |s| ts >= s) && end.map_or(true, |e| ts < e)
+                }
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+/// Which items a tab shows: the ones filed into it and, for smart tabs, the
+/// ones matching its saved query
+pub struct TabFilter {
+    ids: Vec<String>,
+    matchers: Vec<Matcher>,
+}
+
+impl TabFilter {
+    pub fn new(tab: &Tab, index: &SearchIndex) -> Self {
+        let matchers = tab
+            .query
+            .as_deref()
+            .and_then(|q| match parse(q) {
+                Ok(expr) => Some(Matcher::new(expr, index)),
+                Err(e) => {
+                    warn!("Ignoring invalid query of tab {}: {}", tab.id, e.message);
+                    None
+                }
+            })
+            .into_iter()
+            .collect();
+        Self { ids: vec![tab.id.clone()], matchers }
+    }
+
+    /// A tab that no longer exists still shows what was filed into it
+    pub fn by_id(id: &str) -> Self {
+        Self { ids: vec![id.to_string()], matchers: Vec::new() }
+    }
+
+    /// Items shown by any of `filters`, e.g. a tab and its child tabs
+    pub fn any(filters: impl IntoIterator<Item = TabFilter>) -> Self {
+        let (mut ids, mut matchers) = (Vec::new(), Vec::new());
+        for filter in filters {
+            ids.extend(filter.ids);
+            matchers.extend(filter.matchers);
+        }
+        Self { ids, matchers }
+    }
+
+    pub fn matches(&self, item: &Item) -> bool {
+        self.ids.iter().any(|id| item.in_tab(id)) || self.matchers.iter().any(|m| m.matches(item))
+    }
+}
+
+fn phrase_matches(phrase: &str, item: &Item) -> bool {
+    (!item.sensitive && fold(item.get_text()).contains(phrase)) || fold(&item.label).contains(phrase)
+}
+
+/// Collect words and phrases along with whether they are negated
+fn collect_terms(
+    expr: &Expr,
+    negated: bool,
+    words: &mut Vec<(String, bool)>,
+    phrases: &mut Vec<(String, bool)>,
+) {
+    match expr {
+        Expr::And(parts) | Expr::Or(parts) => {
+            for e in parts {
+                collect_terms(e, negated, words, phrases);
+            }
+        }
+        Expr::Not(inner) => collect_terms(inner, !negated, words, phrases),
+        Expr::Word(w) => words.push((w.clone(), negated)),
+        Expr::Phrase(p) => phrases.push((p.clone(), negated)),
+        Expr::Field(_) => {}
+    }
+}
+
+// ─── Lexer ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Minus,
+    Or,
+    And,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field(String, String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+const FIELDS: &[&str] = &[
+    "kind", "category", "cat", "tag", "tab", "tab_id", "pinned", "label", "timestamp", "date", "after",
+    "before", "on", "vault", "in_vault", "app", "source", "source_app",
+];
+
+fn lex(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    // UTF-16 offset of each char index
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut acc = 0;
+    for c in &chars {
+        offsets.push(acc);
+        acc += c.len_utf16();
+    }
+    offsets.push(acc);
+
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' | ')' => {
+                i += 1;
+                tokens.push(Token {
+                    kind: if c == '(' { TokenKind::LParen } else { TokenKind::RParen },
+                    start: offsets[start],
+                    end: offsets[i],
+                });
+            }
+            '-' if chars.get(i + 1).is_some_and(|n| !n.is_whitespace() && *n != ')') => {
+                i += 1;
+                tokens.push(Token { kind: TokenKind::Minus, start: offsets[start], end: offsets[i] });
+            }
+            '"' => {
+                let (phrase, next) = read_phrase(&chars, i, &offsets)?;
+                i = next;
+                tokens.push(Token {
+                    kind: TokenKind::Phrase(phrase),
+                    start: offsets[start],
+                    end: offsets[i],
+                });
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+
+                // field:value or field:"quoted value"
+                if let Some((name, value)) = word.split_once(':') {
+                    let name = name.to_lowercase();
+                    if FIELDS.contains(&name.as_str()) {
+                        let value = if value.is_empty() && chars.get(i) == Some(&'"') {
+                            let (phrase, next) = read_phrase(&chars, i, &offsets)?;
+                            i = next;
+                            phrase
+                        } else {
+                            value.to_string()
+                        };
+                        tokens.push(Token {
+                            kind: TokenKind::Field(name, value),
+                            start: offsets[start],
+                            end: offsets[i],
+                        });
+                        continue;
+                    }
+                }
+
+                let kind = match word.as_str() {
+                    "OR" | "||" => TokenKind::Or,
+                    "AND" | "&&" => TokenKind::And,
+                    "NOT" => TokenKind::Not,
+                    _ => TokenKind::Word(word),
+                };
+                tokens.push(Token { kind, start: offsets[start], end: offsets[i] });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Read a quoted phrase starting at `chars[i] == '"'`; returns the phrase and
+/// the index after the closing quote
+fn read_phrase(chars: &[char], i: usize, offsets: &[usize]) -> Result<(String, usize), QueryError> {
+    match chars[i + 1..].iter().position(|c| *c == '"') {
+        Some(len) => Ok((chars[i + 1..i + 1 + len].iter().collect(), i + len + 2)),
+        None => Err(QueryError {
+            message: "Unterminated quote".into(),
+            start: offsets[i],
+            end: offsets[chars.len()],
+        }),
+    }
+}
+
+// ─── Parser ───────────────────────────────────────────────────────────────────
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    input_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut parts = vec![self.parse_and()?];
+        while matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Or)) {
+            self.next();
+            parts.push(self.parse_and()?);
+        }
+        Ok(if parts.len() == 1 { parts.pop().unwrap() } else { Expr::Or(parts) })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut parts = vec![self.parse_unary()?];
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                None | Some(TokenKind::Or) | Some(TokenKind::RParen) => break,
+                Some(TokenKind::And) => {
+                    self.next();
+                }
+                _ => {}
+            }
+            parts.push(self.parse_unary()?);
+        }
+        Ok(if parts.len() == 1 { parts.pop().unwrap() } else { Expr::And(parts) })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        let tok = match self.next() {
+            Some(tok) => tok,
+            None => {
+                return Err(QueryError {
+                    message: "Expected a search term".into(),
+                    start: self.input_len,
+                    end: self.input_len,
+                })
+            }
+        };
+        match tok.kind {
+            TokenKind::Minus | TokenKind::Not => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            TokenKind::LParen => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some(Token { kind: TokenKind::RParen, .. }) => Ok(inner),
+                    _ => Err(QueryError {
+                        message: "Missing ')'".into(),
+                        start: tok.start,
+                        end: self.input_len,
+                    }),
+                }
+            }
+            TokenKind::RParen | TokenKind::Or | TokenKind::And => Err(QueryError {
+                message: "Expected a search term".into(),
+                start: tok.start,
+                end: tok.end,
+            }),
+            // Punctuation-only words ("@", "#") have nothing to index, so match them literally
+            TokenKind::Word(w) if !w.chars().any(char::is_alphanumeric) => Ok(Expr::Phrase(fold(&w))),
+            TokenKind::Word(w) => Ok(Expr::Word(fold(&w))),
+            TokenKind::Phrase(p) => Ok(Expr::Phrase(fold(&p))),
+            TokenKind::Field(name, value) => {
+                parse_field(&name, &value).map(Expr::Field).map_err(|message| QueryError {
+                    message,
+                    start: tok.start,
+                    end: tok.end,
+                })
+            }
+        }
+    }
+}
+
+fn parse_field(name: &str, value: &str) -> Result<Filter, String> {
+    if value.is_empty() {
+        return Err(format!("Missing value for '{}:'", name));
+    }
+    match name {
+        "kind" => match value.to_lowercase().as_str() {
+            "text" => Ok(Filter::Kind(ItemKind::Text)),
+            "image" => Ok(Filter::Kind(ItemKind::Image)),
+            "files" | "file" => Ok(Filter::Kind(ItemKind::Files)),
+            other => Err(format!("Unknown kind '{}'", other)),
+        },
+        "category" | "cat" => Ok(Filter::Category(value.to_lowercase())),
+        "tag" => Ok(Filter::Tag(value.to_lowercase())),
+        "tab" | "tab_id" => Ok(Filter::Tab(value.to_string())),
+        "pinned" => parse_bool(value).map(Filter::Pinned),
+        // Vault items are kept out of the history, so this would never match
+        "vault" | "in_vault" => Err("Vault items aren't searched, open the vault to see them".into()),
+        "label" => Ok(Filter::Label(fold(value))),
+        "app" | "source" | "source_app" => Ok(Filter::App(fold(value))),
+        "after" => parse_time(value).map(|(start, _)| Filter::Time { start: Some(start), end: None }),
+        "before" => parse_time(value).map(|(start, _)| Filter::Time { start: None, end: Some(start) }),
+        "on" | "date" => parse_time(value).map(|(start, end)| Filter::Time { start: Some(start), end: Some(end) }),
+        "timestamp" => {
+            let (op, rest) = ["<=", ">=", "<", ">", "="]
+                .iter()
+                .find_map(|op| value.strip_prefix(op).map(|r| (*op, r)))
+                .unwrap_or(("=", value));
+            let (start, end) = parse_time(rest)?;
+            Ok(match op {
+                ">=" => Filter::Time { start: Some(start), end: None },
+                ">" => Filter::Time { start: Some(end), end: None },
+                "<" => Filter::Time { start: None, end: Some(start) },
+                "<=" => Filter::Time { start: None, end: Some(end) },
+                _ => Filter::Time { start: Some(start), end: Some(end) },
+            })
+        }
+        _ => Err(format!("Unknown field '{}'", name)),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        other => Err(format!("Expected true or false, got '{}'", other)),
+    }
+}
+
+/// Parse a date (`2026-09-01`, a whole local day), an RFC 3339 instant or a
+/// period relative to today (`today`, `yesterday`, `this-week`, `this-month`,
+/// `7d` or `2w` for the day that many days or weeks ago).
+/// Returns the start and the exclusive end of the period.
+fn parse_time(value: &str) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        let ts = ts.with_timezone(&Utc);
+        return Ok((ts, ts + chrono::Duration::seconds(1)));
+    }
+    let (first, last) = match relative_days(value, Local::now().date_naive()) {
+        Some(days) => days,
+        None => {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                format!("Invalid date '{}', expected YYYY-MM-DD, today, this-week or 7d", value)
+            })?;
+            (date, date)
+        }
+    };
+    let start = local_midnight(first)?;
+    let end = local_midnight(last.succ_opt().ok_or("Date out of range")?)?;
+    Ok((start, end))
+}
+
+/// First and last day of a period relative to `today`
+fn relative_days(value: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let value = value.to_lowercase();
+    let days_ago = |n: i64| today.checked_sub_signed(chrono::Duration::days(n));
+    match value.as_str() {
+        "today" => Some((today, today)),
+        "yesterday" => days_ago(1).map(|d| (d, d)),
+        "this-week" | "week" => {
+            let monday = days_ago(today.weekday().num_days_from_monday() as i64)?;
+            Some((monday, monday + chrono::Duration::days(6)))
+        }
+        "this-month" | "month" => {
+            let first = today.with_day(1)?;
+            let next = if first.month() == 12 {
+                NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)?
+            };
+            Some((first, next.pred_opt()?))
+        }
+        _ => {
+            let (count, per) = match value.strip_suffix('d') {
+                Some(count) => (count, 1),
+                None => (value.strip_suffix('w')?, 7),
+            };
+            let count: u32 = count.parse().ok()?;
+            days_ago(count as i64 * per).map(|d| (d, d))
+        }
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> Result<DateTime<Utc>, String> {
+    let naive = date.and_hms_opt(0, 0, 0).ok_or("Invalid date")?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| "Invalid local date".to_string())
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
@@ -0,0 +1,103 @@
+//! User-defined rules that file captured items into tabs.
+//!
+//! Each tab carries a list of `TabRule`s; an item goes into every tab with a
+//! rule it matches. Regexes are compiled once per
+//! `TabRules`, so build one and reuse it when filing many items.
+
+use crate::models::{normalize_app, Item, Tab, TabRule};
+use log::warn;
+use regex::Regex;
+
+/// Longest pattern accepted for a `TextMatches` rule
+const MAX_PATTERN_LEN: usize = 1000;
+
+/// Compiled size limit, so a pathological pattern can't eat memory
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+enum Condition {
+    Text(Regex),
+    App(String),
+    Category(String),
+    Tag(String),
+    LongerThan(usize),
+    ShorterThan(usize),
+}
+
+impl Condition {
+    fn matches(&self, item: &Item) -> bool {
+        let text = item.content_text.as_deref();
+        match self {
+            Condition::Text(re) => text.is_some_and(|t| re.is_match(t)),
+            Condition::App(app) => item.source_app.as_deref().is_some_and(|a| normalize_app(a) == *app),
+            Condition::Category(category) => item.category == *category,
+            Condition::Tag(tag) => item.tags.iter().any(|t| t == tag),
+            Condition::LongerThan(n) => text.is_some_and(|t| t.chars().count() > *n),
+            Condition::ShorterThan(n) => text.is_some_and(|t| t.chars().count() < *n),
+        }
+    }
+}
+
+/// Compile a `TextMatches` pattern, with a readable error for the settings UI
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("Pattern is longer than {} characters", MAX_PATTERN_LEN));
+    }
+    regex::RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Check a rule list before saving it
+pub fn validate(rules: &[TabRule]) -> Result<(), String> {
+    for rule in rules {
+        if let TabRule::TextMatches { pattern } = rule {
+            compile(pattern)?;
+        }
+    }
+    Ok(())
+}
+
+/// The rules of every tab, ready to match items against
+pub struct TabRules {
+    tabs: Vec<(String, Vec<Condition>)>,
+}
+
+impl TabRules {
+    pub fn new(tabs: &[Tab]) -> Self {
+        let tabs = tabs
+            .iter()
+            .filter(|tab| tab.id != "all" && !tab.rules.is_empty())
+            .map(|tab| {
+                let conditions = tab
+                    .rules
+                    .iter()
+                    .filter_map(|rule| match rule {
+                        TabRule::TextMatches { pattern } => match compile(pattern) {
+                            Ok(re) => Some(Condition::Text(re)),
+                            Err(e) => {
+                                warn!("Ignoring invalid rule pattern on tab {}: {}", tab.id, e);
+                                None
+                            }
+                        },
+                        TabRule::SourceApp { app } => Some(Condition::App(normalize_app(app))),
+                        TabRule::Category { category } => Some(Condition::Category(category.clone())),
+                        TabRule::Tag { tag } => Some(Condition::Tag(tag.clone())),
+                        TabRule::LongerThan { chars } => Some(Condition::LongerThan(*chars)),
+                        TabRule::ShorterThan { chars } => Some(Condition::ShorterThan(*chars)),
+                    })
+                    .collect();
+                (tab.id.clone(), conditions)
+            })
+            .collect();
+        Self { tabs }
+    }
+
+    /// Every tab with a rule `item` matches, in tab order
+    pub fn matching_tabs<'a>(&'a self, item: &'a Item) -> impl Iterator<Item = &'a str> + 'a {
+        self.tabs
+            .iter()
+            .filter(|(_, conditions)| conditions.iter().any(|c| c.matches(item)))
+            .map(|(id, _)| id.as_str())
+    }
+}
@@ -0,0 +1,79 @@
+//! Whether the user's screen is locked, polled by the vault auto-lock.
+//!
+//! - macOS: `CGSSessionScreenIsLocked` in the CoreGraphics session dictionary
+//! - Windows: the input desktop can't be opened while Winlogon's secure
+//!   desktop is showing
+//! - Linux: logind's `LockedHint`, which desktop lock screens set
+
+/// Whether the screen is currently locked. False where it can't be told.
+pub fn is_locked() -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        macos_is_locked()
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_is_locked()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        linux_is_locked()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        false
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn macos_is_locked() -> bool {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::string::CFString;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+    }
+
+    // SAFETY: the dictionary is returned retained (a "Copy" function), so it's
+    // wrapped under the create rule and released when dropped
+    let dict: CFDictionary<CFString, CFType> = unsafe {
+        let dict = CGSessionCopyCurrentDictionary();
+        if dict.is_null() {
+            return false;
+        }
+        CFDictionary::wrap_under_create_rule(dict)
+    };
+    dict.find(CFString::from_static_string("CGSSessionScreenIsLocked"))
+        .and_then(|value| value.downcast::<CFBoolean>())
+        .is_some_and(bool::from)
+}
+
+#[cfg(target_os = "windows")]
+fn windows_is_locked() -> bool {
+    use windows_sys::Win32::System::StationsAndDesktops::{CloseDesktop, OpenInputDesktop, DESKTOP_SWITCHDESKTOP};
+
+    // SAFETY: plain Win32 calls; the desktop handle is closed before returning
+    unsafe {
+        let desktop = OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP);
+        if desktop.is_null() {
+            return true;
+        }
+        CloseDesktop(desktop);
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn linux_is_locked() -> bool {
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".into());
+    std::process::Command::new("loginctl")
+        .args(["show-session", &session, "--property=LockedHint", "--value"])
+        .output()
+        .is_ok_and(|out| out.status.success() && String::from_utf8_lossy(&out.stdout).trim() == "yes")
+}
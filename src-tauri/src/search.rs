@@ -0,0 +1,325 @@
+use crate::models::{Item, MatchRange};
+use std::collections::{BTreeMap, HashMap};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Only the first part of very long items is indexed
+const MAX_INDEXED_CHARS: usize = 20_000;
+
+/// Field weights: a hit in the label counts more than one in the body
+const LABEL_WEIGHT: f32 = 3.0;
+const TEXT_WEIGHT: f32 = 1.0;
+const CATEGORY_WEIGHT: f32 = 0.5;
+
+/// Score multipliers for non-exact term matches
+const PREFIX_FACTOR: f32 = 0.7;
+const FUZZY_FACTOR: f32 = 0.4;
+
+/// How a query term matched an indexed term
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TermMatch {
+    Exact,
+    Prefix,
+    Fuzzy,
+}
+
+impl TermMatch {
+    fn factor(self) -> f32 {
+        match self {
+            TermMatch::Exact => 1.0,
+            TermMatch::Prefix => PREFIX_FACTOR,
+            TermMatch::Fuzzy => FUZZY_FACTOR,
+        }
+    }
+}
+
+/// In-process inverted index over item text, label, category and tags.
+///
+/// Terms are folded (lowercased, accents stripped) so "tieng viet" finds
+/// "Tiếng Việt". Storage keeps the index in sync on every item write.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// term -> item id -> weighted term frequency
+    postings: BTreeMap<String, HashMap<String, f32>>,
+    /// item id -> terms it was indexed under, for removal
+    docs: HashMap<String, Vec<String>>,
+}
+
+impl SearchIndex {
+    /// Build an index over the given items
+    pub fn build<'a>(items: impl IntoIterator<Item = &'a Item>) -> Self {
+        let mut index = Self::default();
+        for item in items {
+            index.insert(item);
+        }
+        index
+    }
+
+    /// Add or re-index an item
+    pub fn insert(&mut self, item: &Item) {
+        self.remove(&item.id);
+
+        let mut weights: HashMap<String, f32> = HashMap::new();
+        // Sensitive text is never searchable; its label and category still are
+        let text: String = if item.sensitive {
+            String::new()
+        } else {
+            item.get_text().chars().take(MAX_INDEXED_CHARS).collect()
+        };
+        for (field, weight) in [
+            (text.as_str(), TEXT_WEIGHT),
+            (item.label.as_str(), LABEL_WEIGHT),
+            (item.category.as_str(), CATEGORY_WEIGHT),
+        ] {
+            for token in tokenize(field) {
+                *weights.entry(token.term).or_insert(0.0) += weight;
+            }
+        }
+        for tag in &item.tags {
+            for token in tokenize(tag) {
+                *weights.entry(token.term).or_insert(0.0) += CATEGORY_WEIGHT;
+            }
+        }
+
+        let terms: Vec<String> = weights.keys().cloned().collect();
+        for (term, weight) in weights {
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(item.id.clone(), weight);
+        }
+        self.docs.insert(item.id.clone(), terms);
+    }
+
+    /// Drop an item from the index
+    pub fn remove(&mut self, id: &str) {
+        if let Some(terms) = self.docs.remove(id) {
+            for term in terms {
+                if let Some(posting) = self.postings.get_mut(&term) {
+                    posting.remove(id);
+                    if posting.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rebuild from scratch
+    pub fn rebuild<'a>(&mut self, items: impl IntoIterator<Item = &'a Item>) {
+        *self = Self::build(items);
+    }
+
+    /// Search for items matching every term of `query`.
+    ///
+    /// Each query term may match an indexed term exactly, as a prefix, or
+    /// within a small edit distance. Returns item id -> relevance score.
+    pub fn search(&self, query: &str) -> HashMap<String, f32> {
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return HashMap::new();
+        }
+
+        let doc_count = self.docs.len().max(1) as f32;
+        let mut result: Option<HashMap<String, f32>> = None;
+
+        for qterm in &terms {
+            // Best score per item for this query term
+            let mut term_scores: HashMap<String, f32> = HashMap::new();
+            for (term, kind) in self.expand(qterm) {
+                let posting = &self.postings[term];
+                let idf = (1.0 + doc_count / posting.len() as f32).ln();
+                for (id, weight) in posting {
+                    let score = weight * idf * kind.factor();
+                    let best = term_scores.entry(id.clone()).or_insert(0.0);
+                    if score > *best {
+                        *best = score;
+                    }
+                }
+            }
+
+            result = Some(match result {
+                None => term_scores,
+                Some(prev) => prev
+                    .into_iter()
+                    .filter_map(|(id, score)| term_scores.get(&id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+            if result.as_ref().map(|r| r.is_empty()).unwrap_or(true) {
+                break;
+            }
+        }
+
+        result.unwrap_or_default()
+    }
+
+    /// Indexed terms that a query term matches, and how
+    fn expand<'a>(&'a self, qterm: &str) -> Vec<(&'a String, TermMatch)> {
+        let mut out = Vec::new();
+        let qlen = qterm.chars().count();
+
+        for (term, _) in self.postings.range::<str, _>((
+            std::ops::Bound::Included(qterm),
+            std::ops::Bound::Unbounded,
+        )) {
+            if !term.starts_with(qterm) {
+                break;
+            }
+            out.push((term, if term == qterm { TermMatch::Exact } else { TermMatch::Prefix }));
+        }
+
+        let max_edits = max_edits(qlen);
+        if max_edits > 0 {
+            for term in self.postings.keys() {
+                if term.starts_with(qterm) {
+                    continue;
+                }
+                let tlen = term.chars().count();
+                if tlen.abs_diff(qlen) > max_edits {
+                    continue;
+                }
+                if levenshtein(qterm, term, max_edits).is_some() {
+                    out.push((term, TermMatch::Fuzzy));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Compute highlight ranges for `query` in an item's text and label
+pub fn highlights(item: &Item, query: &str) -> Vec<MatchRange> {
+    let terms = query_terms(query);
+    if terms.is_empty() {
+        return vec![];
+    }
+
+    let mut ranges = Vec::new();
+    for (field, value) in [("text", item.get_text()), ("label", item.label.as_str())] {
+        for token in tokenize(value) {
+            if token.start >= MAX_INDEXED_CHARS {
+                break;
+            }
+            if terms.iter().any(|q| term_matches(q, &token.term)) {
+                ranges.push(MatchRange {
+                    field: field.to_string(),
+                    start: token.start_utf16,
+                    end: token.end_utf16,
+                });
+            }
+        }
+    }
+    ranges
+}
+
+/// Fold text for accent- and case-insensitive comparison
+pub fn fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.nfd() {
+        if is_combining_mark(c) {
+            continue;
+        }
+        match c {
+            // đ has no decomposition, so strip it by hand
+            'đ' | 'Đ' => out.push('d'),
+            _ => out.extend(c.to_lowercase()),
+        }
+    }
+    out
+}
+
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = tokenize(query).into_iter().map(|t| t.term).collect();
+    terms.dedup();
+    terms
+}
+
+fn term_matches(qterm: &str, term: &str) -> bool {
+    if term.starts_with(qterm) {
+        return true;
+    }
+    let qlen = qterm.chars().count();
+    let max_edits = max_edits(qlen);
+    max_edits > 0
+        && term.chars().count().abs_diff(qlen) <= max_edits
+        && levenshtein(qterm, term, max_edits).is_some()
+}
+
+/// Typo tolerance grows with term length
+fn max_edits(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// A folded token with its position in the original string
+struct Token {
+    term: String,
+    /// Char offset, used to cap indexing of long texts
+    start: usize,
+    /// UTF-16 offsets, which is what the frontend slices strings with
+    start_utf16: usize,
+    end_utf16: usize,
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let (mut start, mut start_utf16) = (0, 0);
+    let mut utf16 = 0;
+
+    for (i, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() || (is_combining_mark(c) && !current.is_empty()) {
+            if current.is_empty() {
+                start = i;
+                start_utf16 = utf16;
+            }
+            current.push(c);
+        } else if !current.is_empty() {
+            tokens.push(Token {
+                term: fold(&current),
+                start,
+                start_utf16,
+                end_utf16: utf16,
+            });
+            current.clear();
+        }
+        utf16 += c.len_utf16();
+    }
+    if !current.is_empty() {
+        tokens.push(Token {
+            term: fold(&current),
+            start,
+            start_utf16,
+            end_utf16: utf16,
+        });
+    }
+    tokens
+}
+
+/// Levenshtein distance between `a` and `b`, or None if it exceeds `max`
+fn levenshtein(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        let mut row_min = cur[0];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+            row_min = row_min.min(cur[j + 1]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    let dist = prev[b.len()];
+    (dist <= max).then_some(dist)
+}
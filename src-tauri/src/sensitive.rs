@@ -0,0 +1,255 @@
+//! Spotting secrets in copied text: payment cards, IBANs, JWTs, API keys,
+//! private keys and one-time codes.
+//!
+//! Items that match are flagged `sensitive`: they are kept in memory only,
+//! shown masked and dropped after `Settings::sensitive_ttl`.
+
+/// Known API key prefixes and the minimum number of characters after them
+const KEY_PREFIXES: &[(&str, usize)] = &[
+    // AWS access key ids
+    ("AKIA", 16),
+    ("ASIA", 16),
+    // GitHub tokens
+    ("ghp_", 36),
+    ("gho_", 36),
+    ("ghu_", 36),
+    ("ghs_", 36),
+    ("ghr_", 36),
+    ("github_pat_", 22),
+    // Stripe secret and restricted keys
+    ("sk_live_", 16),
+    ("sk_test_", 16),
+    ("rk_live_", 16),
+    ("rk_test_", 16),
+    // Slack tokens
+    ("xoxb-", 20),
+    ("xoxp-", 20),
+];
+
+/// Words that put a number in the context of a one-time code, lowercase
+const OTP_WORDS: &[&str] = &[
+    "otp",
+    "one-time",
+    "one time",
+    "passcode",
+    "verification",
+    "verify",
+    "2fa",
+    "security code",
+    "login code",
+    "sign-in code",
+    "confirmation code",
+    "authentication code",
+    "your code",
+    "code is",
+    "code:",
+    "mã xác",
+    "mã otp",
+];
+
+/// Apps whose copied numbers are one-time codes (matched case-insensitively
+/// against part of the source app)
+const OTP_APPS: &[&str] = &[
+    "authenticator",
+    "authy",
+    "otp",
+    "2fa",
+    "messages",
+    "sms",
+    "1password",
+    "bitwarden",
+    "keepass",
+];
+
+/// Longest text searched for a one-time code next to `OTP_WORDS`, about an SMS
+const OTP_MESSAGE_MAX_CHARS: usize = 200;
+
+/// What kind of secret `text`, copied from `source_app`, contains, if any
+pub fn detect(text: &str, source_app: Option<&str>) -> Option<&'static str> {
+    if is_private_key(text) {
+        return Some("private-key");
+    }
+    let tokens = || {
+        text.split(|c: char| c.is_whitespace() || "\"'`,;()[]{}<>=".contains(c))
+            .filter(|t| !t.is_empty())
+    };
+    if tokens().any(is_jwt) {
+        return Some("jwt");
+    }
+    if tokens().any(is_api_key) {
+        return Some("api-key");
+    }
+    if has_card_number(text) {
+        return Some("card");
+    }
+    if has_iban(text) {
+        return Some("iban");
+    }
+    if is_otp(text, source_app) {
+        return Some("otp");
+    }
+    None
+}
+
+fn is_private_key(text: &str) -> bool {
+    text.find("-----BEGIN ")
+        .is_some_and(|start| text[start..].lines().next().is_some_and(|l| l.contains("PRIVATE KEY-----")))
+}
+
+/// header.payload.signature, base64url, with a JSON header (`eyJ` = `{"`)
+fn is_jwt(token: &str) -> bool {
+    let parts: Vec<&str> = token.split('.').collect();
+    parts.len() == 3
+        && parts[0].starts_with("eyJ")
+        && parts[1].starts_with("eyJ")
+        && parts[0].len() >= 10
+        && parts[1].len() >= 10
+        && parts[2].len() >= 16
+        && parts
+            .iter()
+            .all(|p| p.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+}
+
+fn is_api_key(token: &str) -> bool {
+    KEY_PREFIXES.iter().any(|(prefix, min_len)| {
+        token.strip_prefix(prefix).is_some_and(|rest| {
+            rest.len() >= *min_len && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+    })
+}
+
+/// 13–19 digits, optionally grouped by single spaces or dashes, with a card
+/// network's leading digit and a valid Luhn checksum
+fn has_card_number(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() || (i > 0 && chars[i - 1].is_ascii_alphanumeric()) {
+            i += 1;
+            continue;
+        }
+        let mut digits = Vec::new();
+        let mut j = i;
+        while j < chars.len() {
+            if let Some(d) = chars[j].to_digit(10) {
+                digits.push(d);
+                j += 1;
+            } else if (chars[j] == ' ' || chars[j] == '-') && chars.get(j + 1).is_some_and(|c| c.is_ascii_digit()) {
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        let bounded = !chars.get(j).is_some_and(|c| c.is_ascii_alphanumeric());
+        if bounded
+            && (13..=19).contains(&digits.len())
+            && matches!(digits[0], 2..=6)
+            && luhn(&digits)
+        {
+            return true;
+        }
+        i = j.max(i + 1);
+    }
+    false
+}
+
+fn luhn(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 0 {
+                d
+            } else if d > 4 {
+                d * 2 - 9
+            } else {
+                d * 2
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Country code, check digits and 11–30 alphanumerics (spaces allowed
+/// between groups), passing the ISO 13616 mod-97 check
+fn has_iban(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    for start in 0..chars.len() {
+        if start > 0 && chars[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+        let head = &chars[start..];
+        if head.len() < 15
+            || !head[0].is_ascii_uppercase()
+            || !head[1].is_ascii_uppercase()
+            || !head[2].is_ascii_digit()
+            || !head[3].is_ascii_digit()
+        {
+            continue;
+        }
+        let mut compact = String::new();
+        for (k, &c) in head.iter().enumerate() {
+            if c.is_ascii_digit() || c.is_ascii_uppercase() {
+                compact.push(c);
+            } else if c == ' ' && head.get(k + 1).is_some_and(|n| n.is_ascii_alphanumeric()) {
+                continue;
+            } else {
+                break;
+            }
+            if compact.len() == 34 {
+                break;
+            }
+        }
+        if (15..=34).contains(&compact.len()) && iban_checksum_ok(&compact) {
+            return true;
+        }
+    }
+    false
+}
+
+fn iban_checksum_ok(iban: &str) -> bool {
+    let rearranged = iban[4..].chars().chain(iban[..4].chars());
+    let mut remainder = 0u32;
+    for c in rearranged {
+        let value = match c.to_digit(36) {
+            Some(v) => v,
+            None => return false,
+        };
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+/// A copied one-time code. Lots of numbers are 6–8 digits long, so it takes
+/// context: the whole clipboard is a code copied from an authenticator or
+/// messaging app, or behind Google's "G-" prefix, or a short message says
+/// it's a code ("Your verification code is 482913").
+fn is_otp(text: &str, source_app: Option<&str>) -> bool {
+    let t = text.trim();
+    if let Some(code) = t.strip_prefix("G-") {
+        return is_otp_code(code);
+    }
+    if is_otp_code(t) {
+        let app = source_app.unwrap_or_default().to_lowercase();
+        return OTP_APPS.iter().any(|a| app.contains(a));
+    }
+    if t.chars().count() > OTP_MESSAGE_MAX_CHARS {
+        return false;
+    }
+    let lower = t.to_lowercase();
+    OTP_WORDS.iter().any(|w| lower.contains(w))
+        && t.split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|token| (6..=8).contains(&token.len()) && token.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 6–8 digits, possibly split in two halves ("123 456")
+fn is_otp_code(code: &str) -> bool {
+    let digits: String = code.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+    let separators = code.chars().filter(|c| matches!(c, ' ' | '-')).count();
+    (6..=8).contains(&digits.len()) && separators <= 1 && digits.chars().all(|c| c.is_ascii_digit())
+}
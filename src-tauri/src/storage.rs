@@ -1,46 +1,116 @@
-use crate::models::{AppData, Item, ItemKind};
+use crate::clipboard_backend::Flavor;
+use crate::encryption::{self, Cipher};
+use crate::models::{AppData, Item, ItemKind, Settings, Tab, LINKS_QUERY};
+use crate::search::SearchIndex;
 use log::{error, info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 
-/// Storage manages the persisted JSON data file
+/// Current schema version of the SQLite database
+const SCHEMA_VERSION: i64 = 3;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS meta (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS items (
+    id        TEXT PRIMARY KEY,
+    kind      TEXT NOT NULL,
+    category  TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    pinned    INTEGER NOT NULL DEFAULT 0,
+    in_vault  INTEGER NOT NULL DEFAULT 0,
+    data      TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_items_timestamp ON items(timestamp);
+CREATE TABLE IF NOT EXISTS tabs (
+    id       TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    data     TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+"#;
+
+/// Storage manages the persisted SQLite database.
+///
+/// `data` is an in-memory mirror of the database that commands read from.
+/// Mutations are written back row by row with `save_item`, `delete_items`,
+/// `save_tabs` and `save_settings`; `save_sync` rewrites everything and is
+/// kept for callers that have not moved to row-level updates yet.
+/// Every item write also updates the full-text `search` index and the
+/// reference counts of the image files items point at.
+///
+/// With encryption at rest on (see `encryption.rs`), rows and blobs go
+/// through `cipher`. While it's locked, nothing but settings is loaded and
+/// item writes fail.
 pub struct Storage {
     pub data: Mutex<AppData>,
+    pub search: Mutex<SearchIndex>,
+    /// The open vault session, if the vault is unlocked (see `vault.rs`).
+    /// Lock `data` first when holding both.
+    pub vault: Mutex<Option<crate::vault::Session>>,
+    conn: Mutex<Connection>,
+    images: Mutex<ImageRefs>,
+    /// Lock order: `data`, `images`, `cipher`, `conn`
+    cipher: RwLock<Cipher>,
+    /// Bumped whenever the tab list is saved, so compiled tab rules know
+    /// when to rebuild
+    tabs_version: AtomicU64,
     db_path: PathBuf,
     images_dir: PathBuf,
+    thumbs_dir: PathBuf,
+    flavors_dir: PathBuf,
 }
 
 impl Storage {
     /// Create storage, loading existing data or defaults
     pub fn new(app_data_dir: &Path) -> Self {
-        let db_path = app_data_dir.join("copas-db.json");
+        let db_path = app_data_dir.join("copas.db");
+        let legacy_json_path = app_data_dir.join("copas-db.json");
         let images_dir = app_data_dir.join("images");
+        let thumbs_dir = app_data_dir.join("thumbs");
+        let flavors_dir = app_data_dir.join("flavors");
 
         // Ensure dirs exist
         fs::create_dir_all(app_data_dir).ok();
         fs::create_dir_all(&images_dir).ok();
+        fs::create_dir_all(&thumbs_dir).ok();
+        fs::create_dir_all(&flavors_dir).ok();
 
-        let data = if db_path.exists() {
-            match fs::read_to_string(&db_path) {
-                Ok(content) => match serde_json::from_str::<AppData>(&content) {
-                    Ok(data) => {
-                        info!("Loaded existing database from {:?}", db_path);
-                        data
-                    }
-                    Err(e) => {
-                        error!("Failed to parse database: {}, using defaults", e);
-                        // Backup corrupted file
-                        let backup = db_path.with_extension("json.bak");
-                        fs::copy(&db_path, &backup).ok();
-                        AppData::default()
-                    }
-                },
-                Err(e) => {
-                    error!("Failed to read database: {}, using defaults", e);
-                    AppData::default()
+        let conn = match Self::open_connection(&db_path) {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Failed to open database {:?}: {}, using in-memory db", db_path, e);
+                let conn = Connection::open_in_memory().expect("in-memory sqlite");
+                conn.execute_batch(SCHEMA).ok();
+                conn
+            }
+        };
+
+        let cipher = encryption::startup_cipher(Self::read_meta(&conn, encryption::META_KEY).as_deref());
+        let version = Self::read_meta(&conn, "schema_version").and_then(|v| v.parse::<i64>().ok());
+        let (mut data, fresh) = if let Some(version) = version {
+            info!("Loaded existing database from {:?}", db_path);
+            if version < SCHEMA_VERSION {
+                // Items are read through serde, which understands the old
+                // layout; rewriting them all below fills in the new tables
+                info!("Migrating database from schema {} to {}", version, SCHEMA_VERSION);
+                if let Err(e) = Self::migrate_schema(&conn, version) {
+                    error!("Failed to migrate database schema: {}", e);
                 }
             }
+            (Self::load_all(&conn, &cipher), version < SCHEMA_VERSION)
+        } else if legacy_json_path.exists() {
+            (Self::load_legacy_json(&legacy_json_path), true)
         } else {
             info!("No database found, creating with defaults");
             let mut data = AppData::default();
@@ -50,15 +120,45 @@ impl Storage {
                 data = migrated;
                 data.migrated_from_electron = true;
             }
-            data
+            (data, true)
         };
+        crate::tabs::normalize(&mut data.tabs);
+        data.partition_vault();
+        if Self::read_meta(&conn, "links_tab").is_none() && !cipher.is_locked() {
+            Self::seed_links_tab(&conn, &cipher, &mut data.tabs);
+        }
 
+        let search = SearchIndex::build(&data.items);
+        let images = ImageRefs::build(data.items.iter().chain(&data.vault));
         let storage = Self {
             data: Mutex::new(data),
+            search: Mutex::new(search),
+            vault: Mutex::new(None),
+            conn: Mutex::new(conn),
+            images: Mutex::new(images),
+            cipher: RwLock::new(cipher),
+            tabs_version: AtomicU64::new(0),
             db_path,
             images_dir,
+            thumbs_dir,
+            flavors_dir,
         };
-        storage.save_sync();
+
+        if fresh {
+            storage.save_sync();
+            // Keep the old JSON around but out of the way, so the import only runs once
+            if legacy_json_path.exists() {
+                let migrated_path = legacy_json_path.with_extension("json.migrated");
+                match fs::rename(&legacy_json_path, &migrated_path) {
+                    Ok(_) => info!("Migrated JSON database to SQLite, old file kept at {:?}", migrated_path),
+                    Err(e) => warn!("Failed to rename migrated JSON database: {}", e),
+                }
+            }
+        }
+        if let Err(e) = storage.finish_migration() {
+            error!("Failed to finish the encryption migration: {}", e);
+        }
+        storage.sweep_flavor_blobs(Duration::ZERO);
         storage
     }
 
@@ -67,30 +167,865 @@ impl Storage {
         &self.images_dir
     }
 
+    /// Get the thumbnails directory path
+    pub fn thumbs_dir(&self) -> &Path {
+        &self.thumbs_dir
+    }
+
+    /// Get the clipboard flavor blob directory path
+    pub fn flavors_dir(&self) -> &Path {
+        &self.flavors_dir
+    }
+
     /// Get the db file path
     pub fn db_path(&self) -> &Path {
         &self.db_path
     }
 
-    /// Save data to disk (atomic write: write to tmp then rename)
+    // ─── Flavor blobs ─────────────────────────────────────────────────────────
+
+    /// Write flavor payloads to the flavors dir, named by content hash so
+    /// repeated copies share one file. Returns MIME type -> blob name.
+    pub fn store_flavors(&self, flavors: &[Flavor]) -> BTreeMap<String, String> {
+        let mut stored = BTreeMap::new();
+        for flavor in flavors {
+            match self.store_blob(&self.flavors_dir, &flavor.data, "") {
+                Ok(name) => {
+                    // A blob that was already there may be unused, so make it
+                    // look new to the GC pass until the item is saved
+                    fs::File::options()
+                        .append(true)
+                        .open(self.flavors_dir.join(&name))
+                        .and_then(|f| f.set_modified(SystemTime::now()))
+                        .ok();
+                    stored.insert(flavor.mime.clone(), name);
+                }
+                Err(e) => warn!("Failed to store {} flavor: {}", flavor.mime, e),
+            }
+        }
+        stored
+    }
+
+    /// Read an item's flavor blobs back. Missing blobs are skipped.
+    pub fn load_flavors(&self, flavors: &BTreeMap<String, String>) -> Vec<Flavor> {
+        flavors
+            .iter()
+            .filter_map(|(mime, name)| {
+                self.read_blob(&self.flavors_dir.join(name))
+                    .map(|data| Flavor {
+                        mime: mime.clone(),
+                        data,
+                    })
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Delete the named flavor blobs unless an item in `data` still refers to
+    /// them, e.g. those an item dropped when it was sealed into the vault.
+    /// Call with `data` locked.
+    pub(crate) fn delete_unused_flavors(&self, data: &AppData, names: &[String]) {
+        let mut removed = 0;
+        for name in names {
+            let used = data.items.iter().chain(&data.vault).any(|i| i.flavors.values().any(|n| n == name));
+            if !used && fs::remove_file(self.flavors_dir.join(name)).is_ok() {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            info!("Removed {} unused flavor blobs", removed);
+        }
+    }
+
+    /// Delete flavor blobs no item refers to any more and that are at least
+    /// `min_age` old, which spares the blobs of an item still being captured.
+    /// Returns the bytes freed.
+    fn sweep_flavor_blobs(&self, min_age: Duration) -> u64 {
+        let referenced: HashSet<String> = {
+            let data = self.data.lock().unwrap();
+            if self.is_locked() {
+                return 0;
+            }
+            data.items
+                .iter()
+                .chain(&data.vault)
+                .flat_map(|i| i.flavors.values().cloned())
+                .collect()
+        };
+        let entries = match fs::read_dir(&self.flavors_dir) {
+            Ok(entries) => entries,
+            Err(_) => return 0,
+        };
+        let now = SystemTime::now();
+        let mut removed = 0;
+        let mut freed = 0;
+        for entry in entries.flatten() {
+            let meta = match entry.metadata() {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let age = meta.modified().ok().and_then(|m| now.duration_since(m).ok()).unwrap_or_default();
+            if referenced.contains(&name) || age < min_age {
+                continue;
+            }
+            if fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+                freed += meta.len();
+            }
+        }
+        if removed > 0 {
+            info!("Removed {} unused flavor blobs, {} bytes", removed, freed);
+        }
+        freed
+    }
+
+    // ─── Images ───────────────────────────────────────────────────────────────
+
+    /// Write an encoded image to the images dir under its content hash, so
+    /// identical images share one file, and count it as used by `item_id`.
+    /// Returns the file name to store as the item's `image_path`.
+    pub fn store_image(&self, item_id: &str, png: &[u8]) -> Result<String, String> {
+        // Hold the refs lock so a GC pass can't remove the file before it's counted
+        let mut images = self.images.lock().unwrap();
+        let name = self.store_blob(&self.images_dir, png, ".png")?;
+        images.acquire(item_id, &name);
+        Ok(name)
+    }
+
+    /// Write a downscaled copy of an image to the thumbs dir. Thumbnails are
+    /// named after their image, so items sharing an image share its thumbnail.
+    pub fn store_thumbnail(&self, name: &str, image: &image::RgbaImage) -> Result<(), String> {
+        let (width, height) = thumbnail_size(image.width(), image.height());
+        let thumb = image::imageops::thumbnail(image, width, height);
+        let mut png = Vec::new();
+        thumb
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?;
+        self.write_blob(&self.thumbs_dir.join(name), &png)
+    }
+
+    /// Path of an image's thumbnail, generating it from the full image if it's
+    /// missing (items saved before thumbnails existed)
+    pub fn ensure_thumbnail(&self, name: &str) -> Result<PathBuf, String> {
+        let path = self.thumbs_dir.join(name);
+        if !path.exists() {
+            let image = image::load_from_memory(&self.read_image(name)?).map_err(|e| e.to_string())?;
+            self.store_thumbnail(name, &image.to_rgba8())?;
+        }
+        Ok(path)
+    }
+
+    /// Width, height and byte size of a stored image, read from its header
+    pub fn image_info(&self, name: &str) -> Option<(u32, u32, u64)> {
+        let png = self.read_image(name).ok()?;
+        let (width, height) = image::ImageReader::new(std::io::Cursor::new(&png))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+        Some((width, height, png.len() as u64))
+    }
+
+    /// Whether `name` is a bare file name of an image some item uses. Names
+    /// that come from the frontend are checked with this before being read.
+    pub fn is_stored_image(&self, name: &str) -> bool {
+        Path::new(name).file_name().is_some_and(|n| n == name) && self.images.lock().unwrap().is_referenced(name)
+    }
+
+    /// Read a stored image, decrypting it if needed
+    pub fn read_image(&self, name: &str) -> Result<Vec<u8>, String> {
+        self.read_blob(&self.images_dir.join(name))
+    }
+
+    /// Read a file written by `write_blob`
+    pub fn read_blob(&self, path: &Path) -> Result<Vec<u8>, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        self.cipher.read().unwrap().open_blob(data, &blob_aad(path))
+    }
+
+    /// Write a file to the images, thumbs or flavors dir, sealed when
+    /// encryption is on
+    fn write_blob(&self, path: &Path, data: &[u8]) -> Result<(), String> {
+        let sealed = self.cipher.read().unwrap().seal_blob(data, &blob_aad(path))?;
+        fs::write(path, sealed).map_err(|e| e.to_string())
+    }
+
+    /// Write `data` to `dir`, named by `Cipher::blob_name` plus `ext`, unless
+    /// it's there already. Returns the file name.
+    fn store_blob(&self, dir: &Path, data: &[u8], ext: &str) -> Result<String, String> {
+        // One read lock for both, so the name and the content agree
+        let cipher = self.cipher.read().unwrap();
+        let name = format!("{}{}", cipher.blob_name(data), ext);
+        let path = dir.join(&name);
+        if !path.exists() {
+            fs::write(&path, cipher.seal_blob(data, &blob_aad(&path))?).map_err(|e| e.to_string())?;
+        }
+        Ok(name)
+    }
+
+    /// Delete image files, thumbnails and flavor blobs no item refers to any
+    /// more. Returns the bytes freed, which are also added to the running
+    /// total shown in stats. Must not be called with the data lock held.
+    pub fn collect_garbage(&self) -> u64 {
+        // Flavors are counted under the data lock, which comes before the image refs
+        let flavors_freed = self.sweep_flavor_blobs(FLAVOR_GRACE);
+        let images = self.images.lock().unwrap();
+        // Nothing is counted until the database is unlocked
+        if self.is_locked() {
+            return 0;
+        }
+        let entries = fs::read_dir(&self.images_dir)
+            .into_iter()
+            .chain(fs::read_dir(&self.thumbs_dir))
+            .flatten();
+        let mut removed = 0;
+        let mut freed = 0;
+        for entry in entries.flatten() {
+            let meta = match entry.metadata() {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !images.is_referenced(&name) && fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+                freed += meta.len();
+            }
+        }
+        drop(images);
+
+        if removed > 0 {
+            info!("Image GC removed {} files, {} bytes", removed, freed);
+        }
+        let freed = freed + flavors_freed;
+        if freed > 0 {
+            let conn = self.conn.lock().unwrap();
+            let total = Self::read_meta(&conn, "reclaimed_bytes")
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0)
+                + freed;
+            if let Err(e) = Self::write_meta(&conn, "reclaimed_bytes", &total.to_string()) {
+                error!("Failed to record reclaimed space: {}", e);
+            }
+        }
+        freed
+    }
+
+    /// Total bytes freed by GC over the life of this database
+    pub fn reclaimed_bytes(&self) -> u64 {
+        let conn = self.conn.lock().unwrap();
+        Self::read_meta(&conn, "reclaimed_bytes")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Size of the images and thumbs dirs
+    pub fn images_size(&self) -> u64 {
+        fs::read_dir(&self.images_dir)
+            .into_iter()
+            .chain(fs::read_dir(&self.thumbs_dir))
+            .flatten()
+            .flatten()
+            .filter_map(|e| e.metadata().ok())
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    // ─── Meta ─────────────────────────────────────────────────────────────────
+
+    /// Read a value from the meta table
+    pub fn meta(&self, key: &str) -> Option<String> {
+        Self::read_meta(&self.conn.lock().unwrap(), key)
+    }
+
+    /// Write a value to the meta table
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<(), String> {
+        Self::write_meta(&self.conn.lock().unwrap(), key, value).map_err(|e| e.to_string())
+    }
+
+    /// Fold the write-ahead log back into the database and truncate it, so
+    /// old versions of rewritten rows don't linger in it
+    pub fn checkpoint(&self) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(e) = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())) {
+            warn!("Failed to checkpoint the database: {}", e);
+        }
+    }
+
+    // ─── Encryption at rest ───────────────────────────────────────────────────
+
+    /// Whether encryption is on but the key hasn't been given yet
+    pub fn is_locked(&self) -> bool {
+        self.cipher.read().unwrap().is_locked()
+    }
+
+    /// Whether rows and blobs are stored encrypted
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(*self.cipher.read().unwrap(), Cipher::Off)
+    }
+
+    /// Load the database with its key, then finish an interrupted migration
+    pub(crate) fn unlock_with(&self, key: crate::vault::Key) -> Result<(), String> {
+        let mut data = self.data.lock().unwrap();
+        let mut cipher = self.cipher.write().unwrap();
+        if !cipher.is_locked() {
+            return Ok(());
+        }
+        *cipher = Cipher::On(key);
+        let conn = self.conn.lock().unwrap();
+        let mut loaded = Self::load_all(&conn, &cipher);
+        crate::tabs::normalize(&mut loaded.tabs);
+        loaded.partition_vault();
+        if Self::read_meta(&conn, "links_tab").is_none() {
+            Self::seed_links_tab(&conn, &cipher, &mut loaded.tabs);
+        }
+        drop(conn);
+        drop(cipher);
+        *data = loaded;
+        self.tabs_version.fetch_add(1, Ordering::SeqCst);
+        self.search.lock().unwrap().rebuild(&data.items);
+        *self.images.lock().unwrap() = ImageRefs::build(data.items.iter().chain(&data.vault));
+        info!("Database unlocked, {} items", data.items.len());
+        drop(data);
+
+        self.finish_migration()?;
+        self.sweep_flavor_blobs(Duration::ZERO);
+        Ok(())
+    }
+
+    /// Turn encryption on with `key` and re-write everything sealed. The
+    /// header goes in first, so an interrupted run resumes on the next unlock.
+    pub(crate) fn encrypt_in_place(&self, key: crate::vault::Key, header: &str) -> Result<(), String> {
+        {
+            let _data = self.data.lock().unwrap();
+            let mut cipher = self.cipher.write().unwrap();
+            if !matches!(*cipher, Cipher::Off) {
+                return Err("Encryption is already on".into());
+            }
+            let mut conn = self.conn.lock().unwrap();
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            Self::write_meta(&tx, encryption::META_KEY, header).map_err(|e| e.to_string())?;
+            Self::write_meta(&tx, encryption::MIGRATION_KEY, "encrypt").map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            *cipher = Cipher::On(key);
+        }
+        self.finish_migration()?;
+
+        // Plain copies left over from the JSON era
+        if let Some(dir) = self.db_path.parent() {
+            for name in ["copas-db.json.migrated", "copas-db.json.bak"] {
+                if fs::remove_file(dir.join(name)).is_ok() {
+                    info!("Removed the old plain text database {}", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-write everything in plain text and turn encryption off
+    pub(crate) fn decrypt_in_place(&self) -> Result<(), String> {
+        if !self.cipher.read().unwrap().is_on() {
+            return Err(encryption::LOCKED.into());
+        }
+        self.set_meta(encryption::MIGRATION_KEY, "decrypt")?;
+        self.finish_migration()
+    }
+
+    /// Carry out the migration recorded in the meta table, if any. Blobs
+    /// already in the target form are skipped; the rows and the migration
+    /// flag are rewritten in one transaction, after the blobs.
+    fn finish_migration(&self) -> Result<(), String> {
+        let mut data = self.data.lock().unwrap();
+        // Keep GC and new images out while files are swapped
+        let mut images = self.images.lock().unwrap();
+        let mut cipher = self.cipher.write().unwrap();
+        let direction = Self::read_meta(&self.conn.lock().unwrap(), encryption::MIGRATION_KEY);
+        let encrypt = match direction.as_deref() {
+            None => return Ok(()),
+            // Picked up again once unlocked
+            Some(_) if !cipher.is_on() => return Ok(()),
+            Some(direction) => direction == "encrypt",
+        };
+        info!("{} the database in place", if encrypt { "Encrypting" } else { "Decrypting" });
+
+        let off = Cipher::Off;
+        let target = if encrypt { &*cipher } else { &off };
+        let renames = self.rewrite_blobs(&cipher, target)?;
+        let AppData { items, vault, .. } = &mut *data;
+        for item in items.iter_mut().chain(vault.iter_mut()) {
+            if let Some(name) = item.image_path.as_ref().and_then(|n| renames.images.get(n)) {
+                item.image_path = Some(name.clone());
+            }
+            for name in item.flavors.values_mut() {
+                if let Some(renamed) = renames.flavors.get(name) {
+                    *name = renamed.clone();
+                }
+            }
+        }
+        *images = ImageRefs::build(data.items.iter().chain(&data.vault));
+
+        let mut conn = self.conn.lock().unwrap();
+        let result = conn.transaction().and_then(|tx| {
+            Self::write_rows(&tx, target, &data)?;
+            Self::delete_meta(&tx, encryption::MIGRATION_KEY)?;
+            if !encrypt {
+                Self::delete_meta(&tx, encryption::META_KEY)?;
+            }
+            tx.commit()
+        });
+        result.map_err(|e| e.to_string())?;
+        if !encrypt {
+            *cipher = Cipher::Off;
+        }
+        info!("Rewrote {} items and {} files", data.items.len() + data.vault.len(), renames.files);
+
+        // Drop the old versions of rewritten rows from free pages and the WAL
+        if let Err(e) = conn.execute_batch("VACUUM") {
+            warn!("Failed to vacuum the database: {}", e);
+        }
+        drop(conn);
+        drop(cipher);
+        self.checkpoint();
+        Ok(())
+    }
+
+    /// Re-write image, thumbnail and flavor files from `from` to `to`, under
+    /// the names `to` gives their content. Vault files are sealed with the
+    /// vault key already and left alone. Each file is replaced by a rename,
+    /// so an interruption never leaves half of one.
+    fn rewrite_blobs(&self, from: &Cipher, to: &Cipher) -> Result<Renames, String> {
+        let mut renames = Renames::default();
+        // Files renamed before an interruption are found under either name
+        let other = if to.is_on() { &Cipher::Off } else { from };
+        for (dir, names) in [(&self.images_dir, &mut renames.images), (&self.flavors_dir, &mut renames.flavors)] {
+            for (path, name) in blob_files(dir) {
+                let data = fs::read(&path).map_err(|e| e.to_string())?;
+                let sealed = encryption::is_sealed_blob(&data);
+                let plain = match from.open_blob(data, &blob_aad(&path)) {
+                    Ok(plain) => plain,
+                    Err(e) => {
+                        warn!("Skipping unreadable file {}: {}", name, e);
+                        continue;
+                    }
+                };
+                let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+                let target = format!("{}{}", to.blob_name(&plain), ext);
+                names.insert(format!("{}{}", other.blob_name(&plain), ext), target.clone());
+                names.insert(name.clone(), target.clone());
+                if name == target && sealed == to.is_on() {
+                    continue;
+                }
+                replace_blob(&path, &dir.join(&target), to, &plain)?;
+                renames.files += 1;
+            }
+        }
+        // Thumbnails are named after their image
+        for (path, name) in blob_files(&self.thumbs_dir) {
+            let target = renames.images.get(&name).unwrap_or(&name).clone();
+            let data = fs::read(&path).map_err(|e| e.to_string())?;
+            if name == target && encryption::is_sealed_blob(&data) == to.is_on() {
+                continue;
+            }
+            match from.open_blob(data, &blob_aad(&path)) {
+                Ok(plain) => replace_blob(&path, &self.thumbs_dir.join(&target), to, &plain)?,
+                Err(e) => {
+                    warn!("Skipping unreadable file {}: {}", name, e);
+                    continue;
+                }
+            }
+            renames.files += 1;
+        }
+        Ok(renames)
+    }
+
+    // ─── Row-level writes ─────────────────────────────────────────────────────
+
+    /// Insert or update a single item. Sensitive items stay in memory only,
+    /// and vault items stay out of the search index.
+    pub fn save_item(&self, item: &Item) {
+        let cipher = self.cipher.read().unwrap();
+        let conn = self.conn.lock().unwrap();
+        if !item.sensitive {
+            if let Err(e) = Self::upsert_item(&conn, &cipher, item) {
+                error!("Failed to save item {}: {}", item.id, e);
+            }
+        }
+        drop(conn);
+        drop(cipher);
+        Self::index(&mut self.search.lock().unwrap(), item);
+        self.images.lock().unwrap().track(item);
+    }
+
+    /// Insert or update several items in one transaction
+    pub fn save_items<'a>(&self, items: impl IntoIterator<Item = &'a Item>) {
+        let items: Vec<&Item> = items.into_iter().collect();
+        let cipher = self.cipher.read().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+        let result = conn.transaction().and_then(|tx| {
+            for item in items.iter().filter(|i| !i.sensitive) {
+                Self::upsert_item(&tx, &cipher, item)?;
+            }
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to save items: {}", e);
+        }
+        drop(conn);
+        drop(cipher);
+        let mut search = self.search.lock().unwrap();
+        let mut images = self.images.lock().unwrap();
+        for item in items {
+            Self::index(&mut search, item);
+            images.track(item);
+        }
+    }
+
+    fn index(search: &mut SearchIndex, item: &Item) {
+        if item.in_vault {
+            search.remove(&item.id);
+        } else {
+            search.insert(item);
+        }
+    }
+
+    /// Delete items by id
+    pub fn delete_items(&self, ids: &[String]) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut conn = self.conn.lock().unwrap();
+        let result = conn.transaction().and_then(|tx| {
+            {
+                let mut stmt = tx.prepare_cached("DELETE FROM items WHERE id = ?1")?;
+                for id in ids {
+                    stmt.execute(params![id])?;
+                }
+            }
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to delete items: {}", e);
+        }
+        drop(conn);
+        let mut search = self.search.lock().unwrap();
+        let mut images = self.images.lock().unwrap();
+        for id in ids {
+            search.remove(id);
+            images.release(id);
+        }
+    }
+
+    /// Drop items whose `expires_at` has passed. Returns their ids.
+    pub fn expire_items(&self) -> Vec<String> {
+        let now = chrono::Utc::now();
+        let mut data = self.data.lock().unwrap();
+        let expired: Vec<String> = data
+            .items
+            .iter()
+            .filter(|i| i.is_expired(now))
+            .map(|i| i.id.clone())
+            .collect();
+        if !expired.is_empty() {
+            data.items.retain(|i| !expired.contains(&i.id));
+            drop(data);
+            self.delete_items(&expired);
+        }
+        expired
+    }
+
+    /// How many times the tab list was saved. Read it with `data` locked.
+    pub fn tabs_version(&self) -> u64 {
+        self.tabs_version.load(Ordering::SeqCst)
+    }
+
+    /// Replace the stored tab list (tabs are few, so the whole table is rewritten)
+    pub fn save_tabs(&self, tabs: &[Tab]) {
+        self.tabs_version.fetch_add(1, Ordering::SeqCst);
+        let cipher = self.cipher.read().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+        let result = conn.transaction().and_then(|tx| {
+            Self::write_tabs(&tx, &cipher, tabs)?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to save tabs: {}", e);
+        }
+    }
+
+    /// Persist settings
+    pub fn save_settings(&self, settings: &Settings) {
+        let mut conn = self.conn.lock().unwrap();
+        let result = conn.transaction().and_then(|tx| {
+            Self::write_settings(&tx, settings)?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to save settings: {}", e);
+        }
+    }
+
+    /// Save the whole in-memory state to disk in a single transaction
     pub fn save_sync(&self) {
         let data = self.data.lock().unwrap();
-        let tmp_path = self.db_path.with_extension("json.tmp");
+        let cipher = self.cipher.read().unwrap();
+        // Nothing but settings was loaded, so this would wipe the history
+        if cipher.is_locked() {
+            warn!("Not saving the database while it's locked");
+            return;
+        }
+        let mut conn = self.conn.lock().unwrap();
+
+        let result = conn.transaction().and_then(|tx| {
+            Self::write_rows(&tx, &cipher, &data)?;
+            Self::write_settings(&tx, &data.settings)?;
+            Self::write_meta(
+                &tx,
+                "migrated_from_electron",
+                if data.migrated_from_electron { "1" } else { "0" },
+            )?;
+            Self::write_meta(&tx, "schema_version", &SCHEMA_VERSION.to_string())?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to save database: {}", e);
+        }
+        drop(conn);
+        drop(cipher);
+        self.search.lock().unwrap().rebuild(&data.items);
+        *self.images.lock().unwrap() = ImageRefs::build(data.items.iter().chain(&data.vault));
+    }
+
+    // ─── SQLite helpers ───────────────────────────────────────────────────────
+
+    /// Schema changes that `CREATE TABLE IF NOT EXISTS` can't make
+    fn migrate_schema(conn: &Connection, from: i64) -> rusqlite::Result<()> {
+        if from < 2 {
+            // Tab membership moved from `items.tab_id` to the item's `tabIds`
+            conn.execute_batch("DROP INDEX IF EXISTS idx_items_tab; ALTER TABLE items DROP COLUMN tab_id;")?;
+        }
+        if from < 3 {
+            // Membership is only read from `tabIds`; this copy was never loaded
+            conn.execute_batch("DROP TABLE IF EXISTS item_tabs;")?;
+        }
+        Ok(())
+    }
+
+    fn open_connection(db_path: &Path) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        // Overwrite deleted content, so plain text doesn't linger in free
+        // pages after an item is deleted or sealed into the vault
+        conn.pragma_update(None, "secure_delete", "ON")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+
+    /// Replace all item and tab rows with the in-memory ones
+    fn write_rows(conn: &Connection, cipher: &Cipher, data: &AppData) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM items", [])?;
+        for item in data.items.iter().chain(&data.vault).filter(|i| !i.sensitive) {
+            Self::upsert_item(conn, cipher, item)?;
+        }
+        Self::write_tabs(conn, cipher, &data.tabs)
+    }
+
+    fn upsert_item(conn: &Connection, cipher: &Cipher, item: &Item) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(item)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let json = seal_row(cipher, json, "items", &item.id)?;
+        let kind = match item.kind {
+            ItemKind::Text => "text",
+            ItemKind::Image => "image",
+            ItemKind::Files => "files",
+        };
+        // The category says what the content is ("email", "sensitive"), so
+        // it's only kept inside the sealed `data` while encryption is on
+        let category = if cipher.is_on() { "" } else { item.category.as_str() };
+        conn.prepare_cached(
+            "INSERT INTO items (id, kind, category, timestamp, pinned, in_vault, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+                kind = excluded.kind, category = excluded.category,
+                timestamp = excluded.timestamp, pinned = excluded.pinned,
+                in_vault = excluded.in_vault, data = excluded.data",
+        )?
+        .execute(params![
+            item.id,
+            kind,
+            category,
+            item.timestamp,
+            item.pinned,
+            item.in_vault,
+            json
+        ])?;
+        Ok(())
+    }
+
+    fn write_tabs(conn: &Connection, cipher: &Cipher, tabs: &[Tab]) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM tabs", [])?;
+        let mut stmt = conn.prepare_cached("INSERT INTO tabs (id, position, data) VALUES (?1, ?2, ?3)")?;
+        for (position, tab) in tabs.iter().enumerate() {
+            let json = serde_json::to_string(tab)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            let json = seal_row(cipher, json, "tabs", &tab.id)?;
+            stmt.execute(params![tab.id, position as i64, json])?;
+        }
+        Ok(())
+    }
+
+    /// Settings are stored one row per field, keyed by the camelCase field name
+    fn write_settings(conn: &Connection, settings: &Settings) -> rusqlite::Result<()> {
+        let value = serde_json::to_value(settings)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        )?;
+        if let serde_json::Value::Object(map) = value {
+            for (key, val) in map {
+                stmt.execute(params![key, val.to_string()])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_meta(conn: &Connection, key: &str) -> Option<String> {
+        conn.query_row("SELECT value FROM meta WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .unwrap_or_else(|e| {
+                error!("Failed to read meta {}: {}", key, e);
+                None
+            })
+    }
+
+    fn write_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete_meta(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM meta WHERE key = ?1", params![key])?;
+        Ok(())
+    }
 
-        match serde_json::to_string_pretty(&*data) {
-            Ok(json) => {
-                if let Err(e) = fs::write(&tmp_path, &json) {
-                    error!("Failed to write temp db file: {}", e);
-                    return;
+    /// The links tab used to be a hard-coded filter in `get_history`; turn it
+    /// into a smart tab once, so users can edit its query from then on
+    fn seed_links_tab(conn: &Connection, cipher: &Cipher, tabs: &mut [Tab]) {
+        if let Some(tab) = tabs.iter_mut().find(|t| t.id == "links" && t.query.is_none()) {
+            tab.query = Some(LINKS_QUERY.into());
+        }
+        let result = conn.unchecked_transaction().and_then(|tx| {
+            Self::write_tabs(&tx, cipher, tabs)?;
+            Self::write_meta(&tx, "links_tab", "1")?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            error!("Failed to seed the links tab: {}", e);
+        }
+    }
+
+    /// Load the whole database into memory. While it's locked, only the
+    /// settings can be read.
+    fn load_all(conn: &Connection, cipher: &Cipher) -> AppData {
+        let mut data = AppData {
+            tabs: vec![],
+            items: vec![],
+            vault: vec![],
+            settings: Settings::default(),
+            migrated_from_electron: Self::read_meta(conn, "migrated_from_electron").as_deref()
+                == Some("1"),
+        };
+
+        if !cipher.is_locked() {
+            match Self::load_rows(conn, cipher, "items", "SELECT id, data FROM items ORDER BY timestamp DESC, id DESC") {
+                Ok(items) => data.items = items,
+                Err(e) => error!("Failed to load items: {}", e),
+            }
+            match Self::load_rows(conn, cipher, "tabs", "SELECT id, data FROM tabs ORDER BY position") {
+                Ok(tabs) => data.tabs = tabs,
+                Err(e) => error!("Failed to load tabs: {}", e),
+            }
+        }
+        if data.tabs.is_empty() {
+            data.tabs = AppData::default().tabs;
+        }
+
+        let settings = conn
+            .prepare("SELECT key, value FROM settings")
+            .and_then(|mut stmt| {
+                let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+                let mut map = serde_json::Map::new();
+                for row in rows {
+                    let (key, value) = row?;
+                    if let Ok(val) = serde_json::from_str(&value) {
+                        map.insert(key, val);
+                    }
                 }
-                if let Err(e) = fs::rename(&tmp_path, &self.db_path) {
-                    error!("Failed to rename temp db file: {}", e);
-                    // Fallback: try direct write
-                    fs::write(&self.db_path, &json).ok();
+                Ok(map)
+            });
+        match settings {
+            Ok(map) => match serde_json::from_value(serde_json::Value::Object(map)) {
+                Ok(settings) => data.settings = settings,
+                Err(e) => error!("Failed to parse settings: {}, using defaults", e),
+            },
+            Err(e) => error!("Failed to load settings: {}", e),
+        }
+
+        data
+    }
+
+    /// Read (id, JSON document) rows, skipping rows that no longer open or parse
+    fn load_rows<T: serde::de::DeserializeOwned>(
+        conn: &Connection,
+        cipher: &Cipher,
+        table: &str,
+        sql: &str,
+    ) -> rusqlite::Result<Vec<T>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        let mut out = Vec::new();
+        for row in rows {
+            let (id, stored) = row?;
+            let json = match cipher.open_text(stored, &format!("{}:{}", table, id)) {
+                Ok(json) => json,
+                Err(e) => {
+                    warn!("Skipping unreadable row {}: {}", id, e);
+                    continue;
                 }
+            };
+            match serde_json::from_str(&json) {
+                Ok(v) => out.push(v),
+                Err(e) => warn!("Skipping unreadable row: {}", e),
             }
+        }
+        Ok(out)
+    }
+
+    /// Read the pre-SQLite `copas-db.json` for the one-time migration
+    fn load_legacy_json(path: &Path) -> AppData {
+        match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<AppData>(&content) {
+                Ok(data) => {
+                    info!("Migrating {} items from JSON database {:?}", data.items.len(), path);
+                    data
+                }
+                Err(e) => {
+                    error!("Failed to parse database: {}, using defaults", e);
+                    // Backup corrupted file
+                    let backup = path.with_extension("json.bak");
+                    fs::copy(path, &backup).ok();
+                    AppData::default()
+                }
+            },
             Err(e) => {
-                error!("Failed to serialize data: {}", e);
+                error!("Failed to read database: {}, using defaults", e);
+                AppData::default()
             }
         }
     }
@@ -173,10 +1108,11 @@ impl Storage {
                     .and_then(|v| v.as_str())
                     .unwrap_or("text")
                     .to_string();
-                let tab_id = item_val
+                let tab_ids = item_val
                     .get("tabId")
                     .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
+                    .map(|s| vec![s.to_string()])
+                    .unwrap_or_default();
                 let timestamp = item_val
                     .get("timestamp")
                     .and_then(|v| v.as_str())
@@ -198,15 +1134,28 @@ impl Storage {
                         kind: ItemKind::Text,
                         content_text: Some(content.clone()),
                         content_html: None,
+                        content_rtf: None,
                         image_path: None,
+                        image_width: None,
+                        image_height: None,
+                        image_size: None,
+                        image_dhash: None,
                         mime: None,
+                        files: Vec::new(),
+                        flavors: BTreeMap::new(),
+                        source_app: None,
                         category,
-                        tab_id,
+                        tags: Vec::new(),
+                        tab_ids,
                         timestamp,
                         pinned,
                         label,
                         content: Some(content),
                         in_vault: false,
+                        sealed: None,
+                        paste_count: 0,
+                        sensitive: false,
+                        expires_at: None,
                     });
                 }
             }
@@ -250,3 +1199,145 @@ impl Storage {
         paths
     }
 }
+
+// ─── Image reference counts ───────────────────────────────────────────────────
+
+/// Which items use which image file. Counts are rebuilt from the items on
+/// load, so they can't drift from what's actually stored.
+#[derive(Default)]
+struct ImageRefs {
+    /// Item id -> image file name
+    owners: HashMap<String, String>,
+    /// Image file name -> number of items using it
+    counts: HashMap<String, usize>,
+}
+
+impl ImageRefs {
+    fn build<'a>(items: impl IntoIterator<Item = &'a Item>) -> Self {
+        let mut refs = Self::default();
+        for item in items {
+            refs.track(item);
+        }
+        refs
+    }
+
+    /// Count the item's image, if it has one
+    fn track(&mut self, item: &Item) {
+        match item.image_path.as_deref() {
+            Some(name) => self.acquire(&item.id, name),
+            None => self.release(&item.id),
+        }
+    }
+
+    fn acquire(&mut self, item_id: &str, name: &str) {
+        if self.owners.get(item_id).map(String::as_str) == Some(name) {
+            return;
+        }
+        self.release(item_id);
+        self.owners.insert(item_id.to_string(), name.to_string());
+        *self.counts.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    fn release(&mut self, item_id: &str) {
+        let name = match self.owners.remove(item_id) {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some(count) = self.counts.get_mut(&name) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&name);
+            }
+        }
+    }
+
+    fn is_referenced(&self, name: &str) -> bool {
+        self.counts.contains_key(name)
+    }
+}
+
+/// Old -> new file names from `Storage::rewrite_blobs`
+#[derive(Default)]
+struct Renames {
+    /// Images and their thumbnails
+    images: HashMap<String, String>,
+    flavors: HashMap<String, String>,
+    /// Files actually rewritten
+    files: usize,
+}
+
+/// Path and name of the files in `dir` that `rewrite_blobs` handles
+fn blob_files(dir: &Path) -> Vec<(PathBuf, String)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .flatten()
+        .map(|entry| (entry.path(), entry.file_name().to_string_lossy().into_owned()))
+        .filter(|(path, name)| {
+            path.is_file() && !name.ends_with(".tmp") && !name.ends_with(crate::vault::SEALED_EXT)
+        })
+        .collect()
+}
+
+/// Write `plain` to `to_path` the way `to` stores it, through a temporary
+/// file, and remove `from_path` if the name changed
+fn replace_blob(from_path: &Path, to_path: &Path, to: &Cipher, plain: &[u8]) -> Result<(), String> {
+    let name = to_path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = to_path.with_file_name(format!("{}.tmp", name));
+    fs::write(&tmp, to.seal_blob(plain, &blob_aad(to_path))?).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, to_path).map_err(|e| e.to_string())?;
+    if from_path != to_path {
+        fs::remove_file(from_path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Seal a row's JSON, bound to its table and id
+fn seal_row(cipher: &Cipher, json: String, table: &str, id: &str) -> rusqlite::Result<String> {
+    cipher
+        .seal_text(json, &format!("{}:{}", table, id))
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(std::io::Error::other(e))))
+}
+
+/// Blobs are bound to their dir and file name, so one can't be swapped for another
+fn blob_aad(path: &Path) -> String {
+    let name = |p: Option<&Path>| {
+        p.and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    format!("{}/{}", name(path.parent()), name(Some(path)))
+}
+
+/// Longest side of a thumbnail, in pixels
+const THUMBNAIL_MAX_SIDE: u32 = 320;
+
+/// Scale to fit in `THUMBNAIL_MAX_SIDE`, keeping the aspect ratio and never upscaling
+fn thumbnail_size(width: u32, height: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= THUMBNAIL_MAX_SIDE {
+        return (width.max(1), height.max(1));
+    }
+    let scale = |side: u32| ((side as u64 * THUMBNAIL_MAX_SIDE as u64 / longest as u64) as u32).max(1);
+    (scale(width), scale(height))
+}
+
+/// How often the background GC looks for orphaned image files and flavor blobs
+const GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Flavor blobs are written before their item is saved, so GC leaves ones
+/// newer than this alone
+const FLAVOR_GRACE: Duration = Duration::from_secs(60);
+
+/// Run GC shortly after startup and then every `GC_INTERVAL`
+pub fn start_garbage_collector(storage: std::sync::Arc<Storage>) {
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(30));
+        loop {
+            storage.collect_garbage();
+            std::thread::sleep(GC_INTERVAL);
+        }
+    });
+}
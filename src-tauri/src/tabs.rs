@@ -0,0 +1,149 @@
+//! The tab tree: tabs nest through `parent_id` and are ordered among their
+//! siblings by `position`.
+//!
+//! `AppData.tabs` is kept in display order (depth first, siblings by
+//! position), which is also the order the tabs table is written in.
+
+use crate::models::Tab;
+use std::collections::HashSet;
+
+/// The "all" tab always comes first and never nests
+const ALL: &str = "all";
+
+/// A new tab id that can't collide with an existing one
+pub fn new_id() -> String {
+    format!("tab_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Repair and order the tree: parents that no longer exist or would form a
+/// cycle are dropped, tabs are put in display order and positions renumbered
+pub fn normalize(tabs: &mut Vec<Tab>) {
+    let ids: HashSet<String> = tabs.iter().map(|t| t.id.clone()).collect();
+    for tab in tabs.iter_mut() {
+        let valid = tab
+            .parent_id
+            .as_deref()
+            .is_some_and(|p| ids.contains(p) && p != ALL && !tab.system && tab.id != ALL);
+        if !valid {
+            tab.parent_id = None;
+        }
+    }
+    for i in 0..tabs.len() {
+        if creates_cycle(tabs, &tabs[i].id) {
+            tabs[i].parent_id = None;
+        }
+    }
+
+    let mut rest = std::mem::take(tabs);
+    // Stable: tabs saved before positions existed keep their old order
+    rest.sort_by_key(|t| (t.id != ALL, t.position));
+    place_children(None, &mut rest, tabs);
+}
+
+fn place_children(parent: Option<&str>, rest: &mut Vec<Tab>, out: &mut Vec<Tab>) {
+    let (children, others): (Vec<Tab>, Vec<Tab>) =
+        std::mem::take(rest).into_iter().partition(|t| t.parent_id.as_deref() == parent);
+    *rest = others;
+    for (position, mut child) in children.into_iter().enumerate() {
+        child.position = position;
+        let id = child.id.clone();
+        out.push(child);
+        place_children(Some(&id), rest, out);
+    }
+}
+
+/// Whether following parents up from `id` leads back to it
+fn creates_cycle(tabs: &[Tab], id: &str) -> bool {
+    let mut current = id;
+    for _ in 0..tabs.len() {
+        match tabs.iter().find(|t| t.id == current).and_then(|t| t.parent_id.as_deref()) {
+            Some(parent) if parent == id => return true,
+            Some(parent) => current = parent,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// `id` and every tab nested below it
+pub fn with_descendants(tabs: &[Tab], id: &str) -> Vec<String> {
+    let mut ids = vec![id.to_string()];
+    let mut i = 0;
+    while i < ids.len() {
+        let parent = ids[i].clone();
+        ids.extend(
+            tabs.iter()
+                .filter(|t| t.parent_id.as_deref() == Some(parent.as_str()))
+                .map(|t| t.id.clone()),
+        );
+        i += 1;
+    }
+    ids
+}
+
+/// Nest `id` under `parent_id` (None = top level) at `position` among its new
+/// siblings, or last when `position` is None
+pub fn move_tab(tabs: &mut Vec<Tab>, id: &str, parent_id: Option<&str>, position: Option<usize>) -> Result<(), String> {
+    let tab = tabs.iter().find(|t| t.id == id).ok_or("Tab not found")?;
+    if id == ALL {
+        return Err("The \"all\" tab can't be moved".into());
+    }
+    if let Some(parent) = parent_id {
+        if tab.system {
+            return Err("Built-in tabs can't be nested".into());
+        }
+        if parent == ALL || !tabs.iter().any(|t| t.id == parent) {
+            return Err("Parent tab not found".into());
+        }
+        if with_descendants(tabs, id).iter().any(|d| d == parent) {
+            return Err("A tab can't be nested inside itself".into());
+        }
+    }
+
+    let mut siblings: Vec<String> = tabs
+        .iter()
+        .filter(|t| t.parent_id.as_deref() == parent_id && t.id != id)
+        .map(|t| t.id.clone())
+        .collect();
+    // Nothing goes before "all"
+    let first = usize::from(parent_id.is_none() && siblings.first().is_some_and(|s| s == ALL));
+    let position = position.unwrap_or(siblings.len()).clamp(first, siblings.len());
+    siblings.insert(position, id.to_string());
+
+    for tab in tabs.iter_mut() {
+        if tab.id == id {
+            tab.parent_id = parent_id.map(str::to_string);
+        }
+        if let Some(p) = siblings.iter().position(|s| *s == tab.id) {
+            tab.position = p;
+        }
+    }
+    normalize(tabs);
+    Ok(())
+}
+
+/// Put the children of `parent_id` (None = top level) in the order of `ids`.
+/// Siblings missing from `ids` keep their relative order after the listed ones.
+pub fn reorder(tabs: &mut Vec<Tab>, parent_id: Option<&str>, ids: &[String]) {
+    let rank = |id: &str| {
+        if parent_id.is_none() && id == ALL {
+            return (0, 0);
+        }
+        match ids.iter().position(|i| i == id) {
+            Some(p) => (1, p),
+            None => (2, 0),
+        }
+    };
+    let mut siblings: Vec<(String, usize)> = tabs
+        .iter()
+        .filter(|t| t.parent_id.as_deref() == parent_id)
+        .map(|t| (t.id.clone(), t.position))
+        .collect();
+    siblings.sort_by_key(|(id, position)| (rank(id), *position));
+    for tab in tabs.iter_mut() {
+        if let Some(p) = siblings.iter().position(|(id, _)| *id == tab.id) {
+            tab.position = p;
+        }
+    }
+    normalize(tabs);
+}
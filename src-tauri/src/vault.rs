@@ -0,0 +1,801 @@
+//! Vault encryption.
+//!
+//! Vault items are encrypted with a random 256-bit vault key. The key is
+//! stored in the `vault` meta row, wrapped with a key stretched from the PIN
+//! by Argon2id, so the PIN itself is never stored. Unlocking unwraps the key
+//! into `Storage.vault`; vault items are only decrypted in memory, and only
+//! while it's there.
+//!
+//! Changing the PIN only re-wraps the key, so vault items never need to be
+//! re-encrypted. The same key can also be wrapped with a one-time recovery
+//! key, shown once when the vault is set up, which resets a forgotten PIN.
+//!
+//! The session is enforced here rather than in the UI: every function that
+//! touches vault content needs an open session, which closes after
+//! `vault_timeout` seconds without vault activity, when the machine sleeps or
+//! the screen locks (`start_auto_lock`). Wrong PINs are rate-limited with an
+//! exponential backoff that survives restarts.
+//!
+//! Vault items live in `AppData.vault`, apart from the history, so history
+//! queries, search and bulk commands never see them. They are only listed,
+//! searched and deleted here, with the vault unlocked.
+//!
+//! Sealing uses XChaCha20-Poly1305 with the item id as associated data, so
+//! sealed content can't be swapped between items. A sealed item keeps its
+//! metadata (kind, category, tags, label, tabs) in the clear. Its text, HTML
+//! and RTF go into `Item.sealed`, and its image file is replaced by an
+//! encrypted copy.
+
+use crate::models::{FileEntry, Item};
+use crate::storage::Storage;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter};
+use zeroize::Zeroizing;
+
+/// Meta row holding the wrapped vault key (`Keys`)
+const META_KEY: &str = "vault";
+
+/// Meta row counting wrong PINs in a row
+const LOCKOUT_KEY: &str = "vault_lockout";
+
+/// Error returned by everything that needs an open session
+pub const LOCKED: &str = "Vault is locked";
+
+/// Error returned for a wrong PIN
+pub const WRONG_PIN: &str = "Wrong PIN";
+
+/// Error returned for a wrong or already used recovery key
+pub const WRONG_RECOVERY_KEY: &str = "Wrong recovery key";
+
+/// Shortest PIN accepted
+const MIN_PIN_LEN: usize = 4;
+
+/// Recovery keys are 160 random bits, written as 8 groups of 4 Crockford
+/// base32 characters
+const RECOVERY_BYTES: usize = 20;
+const RECOVERY_GROUP: usize = 4;
+const BASE32: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Wrong PINs (or recovery keys) allowed before the backoff starts
+const FREE_ATTEMPTS: u32 = 3;
+
+/// First backoff delay, doubled with each further wrong PIN up to `MAX_DELAY`
+const BASE_DELAY: i64 = 30;
+const MAX_DELAY: i64 = 3600;
+
+/// How often the auto-lock checks the session
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A check arriving this much later than scheduled means the machine slept
+const SLEEP_GAP: Duration = Duration::from_secs(30);
+
+/// Serializes PIN and recovery key attempts, so the backoff can't be raced,
+/// and every read-modify-write of the stored `Keys`
+static ATTEMPT: Mutex<()> = Mutex::new(());
+
+/// Associated data of wrapped keys
+const KEY_AAD: &[u8] = b"copas-vault-key";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+
+/// Argon2id cost for new PINs: 19 MiB, 2 passes, 1 lane (the OWASP
+/// baseline). The parameters are stored with the key, so they can be raised
+/// later without breaking existing vaults.
+const M_COST: u32 = 19 * 1024;
+const T_COST: u32 = 2;
+const P_COST: u32 = 1;
+
+/// A decrypted vault item and its decrypted image, if it has one
+pub type Opened = (Item, Option<Vec<u8>>);
+
+/// Extension of encrypted image files in the images dir
+pub const SEALED_EXT: &str = "sealed";
+
+/// A 256-bit key, wiped from memory when dropped
+pub struct Key(Zeroizing<[u8; KEY_LEN]>);
+
+impl Key {
+    pub(crate) fn generate() -> Self {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        OsRng.fill_bytes(&mut *key);
+        Key(key)
+    }
+
+    /// A key read back from where it was kept (see `as_bytes`)
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != KEY_LEN {
+            return Err("Stored key has the wrong length".into());
+        }
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(bytes);
+        Ok(Key(key))
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &*self.0
+    }
+
+    /// Stretch a PIN into a key
+    fn derive(pin: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, String> {
+        let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN)).map_err(|e| e.to_string())?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(pin.as_bytes(), salt, &mut *key)
+            .map_err(|e| e.to_string())?;
+        Ok(Key(key))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new((&*self.0).into())
+    }
+
+    /// Encrypt `data`, returning the random nonce followed by the ciphertext
+    pub fn seal(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, Payload { msg: data, aad })
+            .map_err(|_| "Encryption failed".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt what `seal` returned. Fails if the data was changed or sealed
+    /// with another key or associated data.
+    pub fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < NONCE_LEN {
+            return Err("Sealed data is truncated".into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| "Sealed data is corrupted or was sealed with another key".into())
+    }
+}
+
+/// An unlocked vault: the key and when the vault was last used
+pub struct Session {
+    key: Key,
+    last_used: Instant,
+}
+
+impl Session {
+    /// Whether `timeout` seconds (0 = never) passed without vault activity
+    fn is_idle(&self, timeout: u64) -> bool {
+        timeout > 0 && self.last_used.elapsed() >= Duration::from_secs(timeout)
+    }
+}
+
+/// Wrong PINs in a row, and when the next attempt is allowed
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Lockout {
+    failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    until: Option<String>,
+}
+
+impl Lockout {
+    fn load(storage: &Storage) -> Self {
+        storage
+            .meta(LOCKOUT_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, storage: &Storage) {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        if let Err(e) = storage.set_meta(LOCKOUT_KEY, &json) {
+            warn!("Failed to save the vault lockout: {}", e);
+        }
+    }
+
+    /// Seconds until the next attempt is allowed
+    fn remaining(&self) -> u64 {
+        self.until
+            .as_deref()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .map(|until| (until.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds())
+            .map_or(0, |secs| secs.max(0) as u64)
+    }
+
+    /// Count a wrong PIN and start the next backoff period
+    fn fail(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        self.until = None;
+        if self.failures >= FREE_ATTEMPTS {
+            let doublings = (self.failures - FREE_ATTEMPTS).min(16);
+            let delay = (BASE_DELAY << doublings).min(MAX_DELAY);
+            self.until = Some((chrono::Utc::now() + chrono::Duration::seconds(delay)).to_rfc3339());
+        }
+    }
+}
+
+/// A key wrapped with a PIN, recovery key or passphrase
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WrappedKey {
+    /// Argon2id parameters the secret is stretched with
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    /// Argon2 salt (base64)
+    salt: String,
+    /// The vault key sealed with the stretched secret (base64)
+    key: String,
+}
+
+impl WrappedKey {
+    /// Wrap `key` with a new salt
+    pub(crate) fn new(key: &Key, pin: &str) -> Result<Self, String> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let wrapping = Key::derive(pin, &salt, M_COST, T_COST, P_COST)?;
+        Ok(Self {
+            m_cost: M_COST,
+            t_cost: T_COST,
+            p_cost: P_COST,
+            salt: STANDARD.encode(salt),
+            key: STANDARD.encode(wrapping.seal(&*key.0, KEY_AAD)?),
+        })
+    }
+
+    /// The key, if `pin` is right
+    pub(crate) fn unwrap(&self, pin: &str) -> Result<Key, String> {
+        let salt = STANDARD.decode(&self.salt).map_err(|e| e.to_string())?;
+        let sealed = STANDARD.decode(&self.key).map_err(|e| e.to_string())?;
+        let wrapping = Key::derive(pin, &salt, self.m_cost, self.t_cost, self.p_cost)?;
+        let bytes = Zeroizing::new(wrapping.open(&sealed, KEY_AAD).map_err(|_| WRONG_PIN.to_string())?);
+        Key::from_bytes(&bytes)
+    }
+}
+
+/// What the `vault` meta row holds: the vault key wrapped with the PIN and,
+/// optionally, with a recovery key. Both live in one row so they are always
+/// replaced together.
+#[derive(Serialize, Deserialize)]
+struct Keys {
+    #[serde(flatten)]
+    pin: WrappedKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recovery: Option<WrappedKey>,
+}
+
+/// Sealed content of a vault item
+#[derive(Serialize, Deserialize)]
+struct Secret {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rtf: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    /// None in items sealed before these fields were, which kept them in the row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<FileEntry>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_app: Option<String>,
+}
+
+/// The PIN hash used before the vault was encrypted. It's only kept to check
+/// old PINs once, when they are upgraded to a wrapped key.
+fn legacy_hash(pin: &str) -> String {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    pin.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+fn keys(storage: &Storage) -> Option<Keys> {
+    let json = storage.meta(META_KEY)?;
+    serde_json::from_str(&json)
+        .map_err(|e| warn!("Failed to read the vault key: {}", e))
+        .ok()
+}
+
+fn save_keys(storage: &Storage, keys: &Keys) -> Result<(), String> {
+    let json = serde_json::to_string(keys).map_err(|e| e.to_string())?;
+    storage.set_meta(META_KEY, &json)
+}
+
+fn check_pin(pin: &str) -> Result<(), String> {
+    if pin.chars().count() < MIN_PIN_LEN {
+        return Err(format!("The PIN needs at least {} characters", MIN_PIN_LEN));
+    }
+    Ok(())
+}
+
+/// A new random recovery key, formatted for display
+fn generate_recovery_key() -> Zeroizing<String> {
+    let mut bytes = Zeroizing::new([0u8; RECOVERY_BYTES]);
+    OsRng.fill_bytes(&mut *bytes);
+    // Sized up front so the key is never copied by a reallocation
+    let mut out = Zeroizing::new(String::with_capacity(RECOVERY_BYTES * 8 / 5 / RECOVERY_GROUP * (RECOVERY_GROUP + 1)));
+    let (mut bits, mut acc) = (0u32, 0u32);
+    for &byte in bytes.iter() {
+        acc = (acc << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            if !out.is_empty() && out.len() % (RECOVERY_GROUP + 1) == RECOVERY_GROUP {
+                out.push('-');
+            }
+            out.push(BASE32[((acc >> bits) & 31) as usize] as char);
+        }
+    }
+    out
+}
+
+/// A recovery key as typed by the user, in the form it's wrapped with:
+/// case, spaces and dashes are ignored and look-alike letters are read as
+/// the digits they stand for
+fn normalize_recovery_key(input: &str) -> Zeroizing<String> {
+    let normalized = input
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+    Zeroizing::new(normalized)
+}
+
+/// Whether a vault PIN has been set
+pub fn has_pin(storage: &Storage) -> bool {
+    storage.meta(META_KEY).is_some() || !storage.data.lock().unwrap().settings.vault_pin_hash.is_empty()
+}
+
+/// Whether a vault session is open and hasn't been idle for too long
+pub fn is_unlocked(storage: &Storage) -> bool {
+    let timeout = storage.data.lock().unwrap().settings.vault_timeout;
+    storage.vault.lock().unwrap().as_ref().is_some_and(|s| !s.is_idle(timeout))
+}
+
+/// Seconds until another PIN may be tried (0 = now)
+pub fn retry_after(storage: &Storage) -> u64 {
+    Lockout::load(storage).remaining()
+}
+
+/// Whether a recovery key is set up and unused
+pub fn has_recovery_key(storage: &Storage) -> bool {
+    keys(storage).is_some_and(|keys| keys.recovery.is_some())
+}
+
+/// Set up the vault with a new key wrapped by `pin`, and unlock it. With
+/// `recovery`, also returns a recovery key, which is never shown again.
+pub fn set_pin(storage: &Storage, pin: &str, recovery: bool) -> Result<Option<String>, String> {
+    check_pin(pin)?;
+    let _keys = ATTEMPT.lock().unwrap();
+    if has_pin(storage) {
+        return Err("A vault PIN is already set".into());
+    }
+    let key = Key::generate();
+    let recovery_key = if recovery { Some(generate_recovery_key()) } else { None };
+    save_keys(
+        storage,
+        &Keys {
+            pin: WrappedKey::new(&key, pin)?,
+            recovery: match &recovery_key {
+                Some(code) => Some(WrappedKey::new(&key, &normalize_recovery_key(code))?),
+                None => None,
+            },
+        },
+    )?;
+    info!("Vault created{}", if recovery { " with a recovery key" } else { "" });
+    open_session(storage, key);
+    Ok(recovery_key.map(|code| code.to_string()))
+}
+
+/// Open a session with `pin`. After `FREE_ATTEMPTS` wrong PINs in a row,
+/// further attempts are refused until the backoff period has passed.
+pub fn unlock(storage: &Storage, pin: &str) -> Result<(), String> {
+    let key = attempt(storage, || unwrap_key(storage, pin))?;
+    open_session(storage, key);
+    Ok(())
+}
+
+/// Replace the PIN after checking the current one. The vault key is only
+/// re-wrapped, in a single write, so vault items are untouched and a crash
+/// leaves either the old or the new PIN working. Wrong PINs count towards
+/// the same backoff as unlocking.
+pub fn change_pin(storage: &Storage, old_pin: &str, new_pin: &str) -> Result<(), String> {
+    check_pin(new_pin)?;
+    attempt(storage, || {
+        let key = unwrap_key(storage, old_pin)?;
+        let recovery = keys(storage).and_then(|keys| keys.recovery);
+        save_keys(storage, &Keys { pin: WrappedKey::new(&key, new_pin)?, recovery })
+    })?;
+    info!("Vault PIN changed");
+    Ok(())
+}
+
+/// Set a new PIN with the recovery key, and unlock the vault. The recovery
+/// key is used up; `new_recovery_key` makes another one.
+pub fn reset_pin(storage: &Storage, recovery_key: &str, new_pin: &str) -> Result<(), String> {
+    check_pin(new_pin)?;
+    let key = attempt(storage, || {
+        let key = keys(storage)
+            .and_then(|keys| keys.recovery)
+            .ok_or_else(|| WRONG_RECOVERY_KEY.to_string())?
+            .unwrap(&normalize_recovery_key(recovery_key))
+            .map_err(|e| if e == WRONG_PIN { WRONG_RECOVERY_KEY.into() } else { e })?;
+        save_keys(storage, &Keys { pin: WrappedKey::new(&key, new_pin)?, recovery: None })?;
+        Ok(key)
+    })?;
+    info!("Vault PIN reset with the recovery key");
+    open_session(storage, key);
+    Ok(())
+}
+
+/// Replace the recovery key with a new one and return it. Needs an open
+/// session.
+pub fn new_recovery_key(storage: &Storage) -> Result<String, String> {
+    let timeout = storage.data.lock().unwrap().settings.vault_timeout;
+    let code = generate_recovery_key();
+    let recovery = with_key(storage, timeout, |key| WrappedKey::new(key, &normalize_recovery_key(&code)))?;
+    let _keys = ATTEMPT.lock().unwrap();
+    let pin = keys(storage).ok_or("No vault PIN set")?.pin;
+    save_keys(storage, &Keys { pin, recovery: Some(recovery) })?;
+    info!("New vault recovery key created");
+    Ok(code.to_string())
+}
+
+/// Run a PIN or recovery key check under the backoff: refused while a
+/// backoff period is running, counted when it fails with a wrong secret and
+/// resetting the count when it succeeds
+fn attempt<T>(storage: &Storage, check: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let _attempt = ATTEMPT.lock().unwrap();
+    let mut lockout = Lockout::load(storage);
+    let wait = lockout.remaining();
+    if wait > 0 {
+        return Err(format!("Too many wrong PINs, try again in {} seconds", wait));
+    }
+    match check() {
+        Ok(value) => {
+            if lockout.failures > 0 {
+                Lockout::default().save(storage);
+            }
+            Ok(value)
+        }
+        Err(e) => {
+            if e == WRONG_PIN || e == WRONG_RECOVERY_KEY {
+                lockout.fail();
+                lockout.save(storage);
+                warn!("{} ({} in a row)", e, lockout.failures);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Unwrap the vault key with `pin`. A PIN set before the vault was encrypted
+/// is upgraded to a wrapped key here.
+fn unwrap_key(storage: &Storage, pin: &str) -> Result<Key, String> {
+    let key = match keys(storage) {
+        Some(keys) => keys.pin.unwrap(pin)?,
+        None => {
+            let legacy = storage.data.lock().unwrap().settings.vault_pin_hash.clone();
+            if legacy.is_empty() {
+                return Err("No vault PIN set".into());
+            }
+            if legacy != legacy_hash(pin) {
+                return Err(WRONG_PIN.into());
+            }
+            // The wrapped key is saved before anything is sealed with it, so
+            // an interrupted upgrade is picked up by the next unlock
+            let key = Key::generate();
+            save_keys(storage, &Keys { pin: WrappedKey::new(&key, pin)?, recovery: None })?;
+            let mut data = storage.data.lock().unwrap();
+            data.settings.vault_pin_hash.clear();
+            storage.save_settings(&data.settings);
+            info!("Upgraded the vault PIN to an Argon2id-wrapped key");
+            key
+        }
+    };
+    Ok(key)
+}
+
+/// Close the session, dropping the vault key from memory. Returns whether
+/// one was open.
+pub fn lock(storage: &Storage) -> bool {
+    storage.vault.lock().unwrap().take().is_some()
+}
+
+/// Lock the vault after `vault_timeout` of inactivity, when the machine
+/// sleeps and when the screen locks, and tell the UI (`vault-locked`)
+pub fn start_auto_lock(app_handle: AppHandle, storage: Arc<Storage>) {
+    std::thread::spawn(move || {
+        let mut last_check = SystemTime::now();
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            // The monotonic clock stops during suspend on some platforms; the
+            // wall clock doesn't, so a late check means the machine slept
+            let now = SystemTime::now();
+            let slept = now
+                .duration_since(last_check)
+                .is_ok_and(|gap| gap > WATCH_INTERVAL + SLEEP_GAP);
+            last_check = now;
+
+            if storage.vault.lock().unwrap().is_none() {
+                continue;
+            }
+            let reason = if slept {
+                "sleep"
+            } else if !is_unlocked(&storage) {
+                "inactivity"
+            } else if crate::screen_lock::is_locked() {
+                "screen lock"
+            } else {
+                continue;
+            };
+            if lock(&storage) {
+                info!("Vault locked on {}", reason);
+                if let Err(e) = app_handle.emit("vault-locked", reason) {
+                    warn!("Failed to emit vault-locked: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Keep `key` in memory and seal vault items that are still in plain text
+/// (flagged before the vault was encrypted, or sealed before their files,
+/// label and source app were)
+fn open_session(storage: &Storage, key: Key) {
+    let mut data = storage.data.lock().unwrap();
+    let mut sealed = Vec::new();
+    let mut flavors = Vec::new();
+    for item in data.vault.iter_mut().filter(|i| i.sealed.is_none() || has_plain_fields(i)) {
+        let dropped: Vec<String> = item.flavors.values().cloned().collect();
+        match seal_item(storage, &key, item) {
+            Ok(()) => {
+                flavors.extend(dropped);
+                sealed.push(&*item);
+            }
+            Err(e) => warn!("Failed to seal vault item {}: {}", item.id, e),
+        }
+    }
+    let resealed = !sealed.is_empty();
+    if resealed {
+        info!("Sealed {} vault items left in plain text", sealed.len());
+        storage.save_items(sealed);
+        storage.delete_unused_flavors(&data, &flavors);
+    }
+    drop(data);
+    if resealed {
+        storage.checkpoint();
+        storage.collect_garbage();
+    }
+    *storage.vault.lock().unwrap() = Some(Session {
+        key,
+        last_used: Instant::now(),
+    });
+}
+
+/// Whether a sealed item still has fields in plain text
+fn has_plain_fields(item: &Item) -> bool {
+    !item.files.is_empty() || !item.label.is_empty() || item.source_app.is_some()
+}
+
+/// Encrypt an item's content, file list, label and source app in place. Its
+/// captured clipboard flavors are dropped, since their blobs are shared and
+/// not sealed with the vault key; callers delete the blobs nothing else uses.
+fn seal_item(storage: &Storage, key: &Key, item: &mut Item) -> Result<(), String> {
+    if item.sealed.is_some() {
+        if !has_plain_fields(item) {
+            return Ok(());
+        }
+        *item = open_item(key, item)?;
+    }
+    let aad = item.id.as_bytes();
+    // The image goes first, so a failure leaves the item untouched
+    let image = match item.image_path.as_deref() {
+        Some(name) if !name.ends_with(SEALED_EXT) => {
+            let png = storage.read_image(name)?;
+            let sealed = key.seal(&png, aad)?;
+            let sealed_name = format!("{}.{}", hex::encode(Sha256::digest(&sealed)), SEALED_EXT);
+            fs::write(storage.images_dir().join(&sealed_name), &sealed).map_err(|e| e.to_string())?;
+            Some(sealed_name)
+        }
+        _ => None,
+    };
+    let secret = Secret {
+        text: item.content_text.clone(),
+        html: item.content_html.clone(),
+        rtf: item.content_rtf.clone(),
+        content: item.content.clone(),
+        files: Some(item.files.clone()),
+        label: Some(item.label.clone()),
+        source_app: item.source_app.clone(),
+    };
+    let json = Zeroizing::new(serde_json::to_vec(&secret).map_err(|e| e.to_string())?);
+    item.sealed = Some(STANDARD.encode(key.seal(&json, aad)?));
+    item.content_text = None;
+    item.content_html = None;
+    item.content_rtf = None;
+    item.content = None;
+    item.files.clear();
+    item.label.clear();
+    item.source_app = None;
+    item.flavors.clear();
+    if image.is_some() {
+        item.image_path = image;
+    }
+    item.in_vault = true;
+    Ok(())
+}
+
+/// Decrypted copy of a sealed item. The image stays sealed; see `open_image`.
+fn open_item(key: &Key, item: &Item) -> Result<Item, String> {
+    let mut item = item.clone();
+    if let Some(sealed) = item.sealed.take() {
+        let sealed = STANDARD.decode(sealed).map_err(|e| e.to_string())?;
+        let json = Zeroizing::new(key.open(&sealed, item.id.as_bytes())?);
+        let secret: Secret = serde_json::from_slice(&json).map_err(|e| e.to_string())?;
+        item.content_text = secret.text;
+        item.content_html = secret.html;
+        item.content_rtf = secret.rtf;
+        item.content = secret.content;
+        if let Some(files) = secret.files {
+            item.files = files;
+        }
+        if let Some(label) = secret.label {
+            item.label = label;
+        }
+        item.source_app = secret.source_app.or(item.source_app);
+    }
+    Ok(item)
+}
+
+/// Run `f` with the vault key and count it as vault activity, or fail if no
+/// session is open. A session idle for more than `timeout` seconds is closed
+/// here rather than waiting for the auto-lock.
+fn with_key<T>(storage: &Storage, timeout: u64, f: impl FnOnce(&Key) -> Result<T, String>) -> Result<T, String> {
+    let mut session = storage.vault.lock().unwrap();
+    if session.as_ref().is_some_and(|s| s.is_idle(timeout)) {
+        info!("Vault locked on inactivity");
+        *session = None;
+    }
+    match session.as_mut() {
+        Some(session) => {
+            session.last_used = Instant::now();
+            f(&session.key)
+        }
+        None => Err(LOCKED.into()),
+    }
+}
+
+/// Move an item from the history into the vault, encrypting it
+pub fn move_to_vault(storage: &Storage, id: &str) -> Result<(), String> {
+    let mut data = storage.data.lock().unwrap();
+    let timeout = data.settings.vault_timeout;
+    let pos = data.items.iter().position(|i| i.id == id).ok_or("Item not found")?;
+    let item = &mut data.items[pos];
+    if item.sensitive {
+        return Err("Sensitive items are never stored, so they can't go in the vault".into());
+    }
+    let flavors: Vec<String> = item.flavors.values().cloned().collect();
+    with_key(storage, timeout, |key| seal_item(storage, key, item))?;
+    let item = data.items.remove(pos);
+    storage.save_item(&item);
+    data.vault.push(item);
+    storage.delete_unused_flavors(&data, &flavors);
+    drop(data);
+    // Don't leave the plain text in the log, or the image and flavors on
+    // disk, until the next checkpoint or GC pass
+    storage.checkpoint();
+    storage.collect_garbage();
+    Ok(())
+}
+
+/// Take an item out of the vault and back into the history, decrypting it
+pub fn remove_from_vault(storage: &Storage, id: &str) -> Result<(), String> {
+    let mut data = storage.data.lock().unwrap();
+    let timeout = data.settings.vault_timeout;
+    let pos = data.vault.iter().position(|i| i.id == id).ok_or("Item not found")?;
+    let sealed = &data.vault[pos];
+    let (mut item, image) = with_key(storage, timeout, |key| Ok((open_item(key, sealed)?, open_image(storage, key, sealed)?)))?;
+    if let Some(png) = image {
+        item.image_path = Some(storage.store_image(&item.id, &png)?);
+    }
+    item.in_vault = false;
+    data.vault.remove(pos);
+    storage.save_item(&item);
+    // Back in its place in the newest-first history
+    let at = data.items.iter().position(|i| i.timestamp < item.timestamp).unwrap_or(data.items.len());
+    data.items.insert(at, item);
+    Ok(())
+}
+
+/// Delete vault items. Needs an open session. Returns how many were deleted.
+pub fn delete(storage: &Storage, ids: &[String]) -> Result<usize, String> {
+    let mut data = storage.data.lock().unwrap();
+    with_key(storage, data.settings.vault_timeout, |_| Ok(()))?;
+    let removed: Vec<String> = data.vault.iter().filter(|i| ids.contains(&i.id)).map(|i| i.id.clone()).collect();
+    data.vault.retain(|i| !removed.contains(&i.id));
+    drop(data);
+    storage.delete_items(&removed);
+    storage.collect_garbage();
+    if !removed.is_empty() {
+        info!("Deleted {} vault items", removed.len());
+    }
+    Ok(removed.len())
+}
+
+/// Delete every vault item. Needs an open session. Returns how many were
+/// deleted.
+pub fn clear(storage: &Storage) -> Result<usize, String> {
+    let ids: Vec<String> = storage.data.lock().unwrap().vault.iter().map(|i| i.id.clone()).collect();
+    delete(storage, &ids)
+}
+
+/// Decrypt the image of a sealed item
+fn open_image(storage: &Storage, key: &Key, item: &Item) -> Result<Option<Vec<u8>>, String> {
+    let name = match item.image_path.as_deref() {
+        Some(name) if name.ends_with(SEALED_EXT) => name,
+        _ => return Ok(None),
+    };
+    let sealed = fs::read(storage.images_dir().join(name)).map_err(|e| e.to_string())?;
+    key.open(&sealed, item.id.as_bytes()).map(Some)
+}
+
+/// Decrypted copies of every vault item, newest first
+pub fn open_items(storage: &Storage) -> Result<Vec<Item>, String> {
+    let data = storage.data.lock().unwrap();
+    with_key(storage, data.settings.vault_timeout, |key| {
+        let mut items = Vec::new();
+        for item in &data.vault {
+            match open_item(key, item) {
+                Ok(item) => items.push(item),
+                Err(e) => warn!("Failed to open vault item {}: {}", item.id, e),
+            }
+        }
+        items.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(items)
+    })
+}
+
+/// Decrypted vault items matching a query (see `query.rs`), best matches
+/// first when it has text terms. The decrypted text is only indexed for the
+/// length of the call.
+pub fn search(storage: &Storage, query: &str) -> Result<Vec<Item>, String> {
+    let mut items = open_items(storage)?;
+    let expr = crate::query::parse(query).map_err(|e| e.message)?;
+    let matcher = crate::query::Matcher::new(expr, &crate::search::SearchIndex::build(&items));
+    items.retain(|i| matcher.matches(i));
+    if matcher.has_text() {
+        items.sort_by(|a, b| matcher.score(b).total_cmp(&matcher.score(a)));
+    }
+    Ok(items)
+}
+
+/// A decrypted copy of item `id` and its image, if it's a vault item.
+/// Returns None for items outside the vault.
+pub fn open(storage: &Storage, id: &str) -> Result<Option<Opened>, String> {
+    let data = storage.data.lock().unwrap();
+    let item = match data.vault.iter().find(|i| i.id == id) {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+    with_key(storage, data.settings.vault_timeout, |key| Ok(Some((open_item(key, item)?, open_image(storage, key, item)?))))
+}
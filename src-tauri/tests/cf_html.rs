@@ -0,0 +1,64 @@
+use app_lib::cf_html;
+
+fn offset(payload: &str, key: &str) -> usize {
+    let line = payload.lines().find(|l| l.starts_with(key)).unwrap();
+    line[key.len() + 1..].parse().unwrap()
+}
+
+#[test]
+fn build_wraps_fragment_and_offsets_point_into_payload() {
+    let payload = cf_html::build("<b>bold</b>");
+
+    assert!(payload.starts_with("Version:0.9\r\n"));
+    let start_html = offset(&payload, "StartHTML");
+    let end_html = offset(&payload, "EndHTML");
+    let start_fragment = offset(&payload, "StartFragment");
+    let end_fragment = offset(&payload, "EndFragment");
+
+    assert_eq!(end_html, payload.len());
+    assert!(payload[start_html..].starts_with("<html><body>"));
+    assert_eq!(&payload[start_fragment..end_fragment], "<b>bold</b>");
+}
+
+#[test]
+fn build_marks_body_of_full_document() {
+    let html = "<html><head><style>b{color:red}</style></head><body class=\"x\"><b>é</b></body></html>";
+    let payload = cf_html::build(html);
+
+    let start_fragment = offset(&payload, "StartFragment");
+    let end_fragment = offset(&payload, "EndFragment");
+    assert_eq!(&payload[start_fragment..end_fragment], "<b>é</b>");
+    assert!(payload.contains("<style>b{color:red}</style>"));
+}
+
+#[test]
+fn parse_round_trips_build() {
+    let payload = cf_html::build("<p>Xin chào <i>thế giới</i></p>");
+    let html = cf_html::parse(payload.as_bytes()).unwrap();
+
+    assert!(html.starts_with("<html><body>"));
+    assert!(html.contains("<p>Xin chào <i>thế giới</i></p>"));
+}
+
+#[test]
+fn parse_falls_back_to_fragment_when_html_offsets_are_missing() {
+    let body = "<html><body><!--StartFragment--><u>x</u><!--EndFragment--></body></html>";
+    let header = "Version:1.0\r\nStartHTML:-1\r\nEndHTML:-1\r\nStartFragment:0000000000\r\nEndFragment:0000000000\r\n";
+    let start_fragment = header.len() + body.find("<u>").unwrap();
+    let end_fragment = header.len() + body.find("<!--EndFragment").unwrap();
+    let header = header
+        .replacen("StartFragment:0000000000", &format!("StartFragment:{:010}", start_fragment), 1)
+        .replacen("EndFragment:0000000000", &format!("EndFragment:{:010}", end_fragment), 1);
+
+    let mut payload = format!("{}{}", header, body).into_bytes();
+    // Windows pads the global memory block with NULs
+    payload.extend_from_slice(&[0, 0, 0]);
+
+    assert_eq!(cf_html::parse(&payload).as_deref(), Some("<u>x</u>"));
+}
+
+#[test]
+fn parse_rejects_empty_payload() {
+    assert_eq!(cf_html::parse(b""), None);
+    assert_eq!(cf_html::parse(b"Version:0.9\r\nStartHTML:-1\r\nEndHTML:-1\r\n\0"), None);
+}
@@ -0,0 +1,117 @@
+use app_lib::classifier::classify;
+
+/// (text, category, tags)
+const CASES: &[(&str, &str, &[&str])] = &[
+    // Whole values
+    ("https://example.com/a?b=c", "link", &[]),
+    ("www.example.vn", "link", &[]),
+    ("an@example.com", "email", &[]),
+    ("mailto:an.nguyen+copas@example.com.vn", "email", &[]),
+    ("550e8400-e29b-41d4-a716-446655440000", "uuid", &[]),
+    ("{550E8400-E29B-41D4-A716-446655440000}", "uuid", &[]),
+    ("192.168.1.10", "ip", &["ipv4"]),
+    ("10.0.0.0/8", "ip", &["ipv4"]),
+    ("127.0.0.1:8080", "ip", &["ipv4"]),
+    ("fe80::1", "ip", &["ipv6"]),
+    ("[2001:db8::1]:443", "ip", &["ipv6"]),
+    ("#ff8800", "color", &["hex"]),
+    ("#FFF", "color", &["hex"]),
+    ("rgb(255, 136, 0)", "color", &["rgb"]),
+    ("rgba(0 0 0 / 50%)", "color", &["rgb"]),
+    ("hsl(210deg, 50%, 40%)", "color", &["hsl"]),
+    ("2024-10-17", "date", &[]),
+    ("17/10/2024", "date", &[]),
+    ("17.10.2024", "date", &[]),
+    ("2024-10-17T09:30:00+07:00", "date", &["datetime"]),
+    ("2024-10-17 09:30", "date", &["datetime"]),
+    ("0912 345 678", "phone", &["vn"]),
+    ("0912.345.678", "phone", &["vn"]),
+    ("+84 912 345 678", "phone", &["vn"]),
+    ("(028) 3822 1234", "phone", &["vn"]),
+    ("+1 (555) 123-4567", "phone", &[]),
+    ("42", "number", &[]),
+    ("-3.14", "number", &[]),
+    ("1.234.567", "number", &[]),
+    ("1,234,567.89", "number", &[]),
+    ("150.000đ", "number", &["currency", "vnd"]),
+    ("1.500.000 VNĐ", "number", &["currency", "vnd"]),
+    ("$1,299.99", "number", &["currency", "usd"]),
+    ("12,5 €", "number", &["currency", "eur"]),
+    ("15%", "number", &["percent"]),
+    ("/usr/local/bin/copas", "path", &["unix"]),
+    ("~/Documents/notes.md", "path", &["unix"]),
+    (r"C:\Users\An\Desktop\report.docx", "path", &["windows"]),
+    (r"\\server\share\file.txt", "path", &["windows"]),
+    // Structured text
+    (r#"{"name": "CoPas", "tags": ["a", "b"]}"#, "json", &[]),
+    ("[1, 2, 3]", "json", &[]),
+    ("name: copas\nversion: 2.12.0\ndependencies:\n  - tauri\n  - serde\n", "yaml", &[]),
+    ("SELECT id, name FROM users WHERE active = 1;", "sql", &[]),
+    ("select *\nfrom items\nwhere pinned = true", "sql", &[]),
+    ("INSERT INTO items (id) VALUES (1)", "sql", &[]),
+    ("CREATE TABLE tabs (id TEXT PRIMARY KEY)", "sql", &[]),
+    ("# Title\n\nSome **bold** text and a [link](https://example.com).", "markdown", &["link"]),
+    ("## Steps\n- one\n- two\n", "markdown", &[]),
+    ("Example:\n\n```rust\nfn main() {}\n```\n\n- note", "markdown", &["rust"]),
+    // Code, with the language as a tag
+    ("fn main() {\n    let mut v = Vec::new();\n    println!(\"{:?}\", v);\n}", "code", &["rust"]),
+    ("def greet(name):\n    print(f\"hi {name}\")\n\nif __name__ == '__main__':\n    greet('An')", "code", &["python"]),
+    ("const add = (a, b) => a + b;\nconsole.log(add(1, 2));", "code", &["javascript"]),
+    ("interface User {\n  name: string;\n}\nconst u: User = { name: 'An' };\nexport const x = () => u;", "code", &["typescript"]),
+    ("package main\n\nfunc main() {\n\tx := 1\n\tfmt.Println(x)\n}", "code", &["go"]),
+    ("public class Main {\n    public static void main(String[] args) {\n        System.out.println(\"hi\");\n    }\n}", "code", &["java"]),
+    ("#include <stdio.h>\nint main() {\n    printf(\"hi\");\n}", "code", &["cpp"]),
+    ("<?php\necho $this->name;", "code", &["php"]),
+    ("<div class=\"card\"><span>hi</span></div>", "code", &["html"]),
+    (".card {\n  color: red;\n  margin: 0;\n}", "code", &["css"]),
+    ("git commit -m \"fix\"", "code", &["shell"]),
+    ("ls -la ~/Downloads", "code", &["shell"]),
+    ("#!/bin/bash\nset -e\ncd \"$(dirname \"$0\")\" && make", "code", &["shell"]),
+    // Prose stays text
+    ("Xin chào, hẹn gặp lại vào thứ Hai nhé!", "text", &[]),
+    ("I'll import the data from the new file tomorrow.", "text", &[]),
+    ("Select the best option from the list below.", "text", &[]),
+    ("We met (briefly) on Monday; it went well => good news.", "text", &[]),
+    ("Note: the meeting moved.\nThanks: An", "text", &[]),
+    ("go home now", "text", &[]),
+    ("cat is sleeping", "text", &[]),
+    ("version 1.2.3", "text", &[]),
+    ("1.2.3", "text", &[]),
+    ("Read this: https://example.com/post today", "link", &[]),
+    ("", "text", &[]),
+];
+
+#[test]
+fn classifies_table() {
+    let failures: Vec<String> = CASES
+        .iter()
+        .filter_map(|(text, category, tags)| {
+            let c = classify(text);
+            let expected = (*category, to_strings(tags));
+            (c.category != expected.0 || c.tags != expected.1)
+                .then(|| format!("{text:?}: got {} {:?}, want {} {:?}", c.category, c.tags, expected.0, expected.1))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn secondary_matches_become_tags() {
+    // Markdown wrapping a Rust snippet: the fence language, then the other
+    // document type that matched
+    let c = classify("# Build\n\n```rust\nfn main() {\n    let mut v = Vec::new();\n    println!(\"{:?}\", v);\n}\n```\n");
+    assert_eq!(c.category, "markdown");
+    assert_eq!(c.tags, vec!["rust", "code"]);
+
+    // Code mentioning a URL
+    let c = classify("const url = \"https://example.com\";\nconsole.log(await fetch(url));");
+    assert_eq!(c.category, "code");
+    assert_eq!(c.tags, vec!["javascript", "link"]);
+
+    // Whole values are exclusive: a date isn't also a phone number
+    assert!(classify("2024-10-17").tags.is_empty());
+}
+
+fn to_strings(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|t| t.to_string()).collect()
+}
@@ -0,0 +1,143 @@
+use app_lib::clipboard_source::{
+    first_available, ChangeSource, EventSource, Fallback, Listener, PollingSource, DEBOUNCE, MAX_DEBOUNCE,
+};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, Instant};
+
+/// A source that reports `changes` changes, then fails
+struct FakeSource {
+    changes: usize,
+}
+
+impl ChangeSource for FakeSource {
+    fn wait(&mut self) -> Result<(), String> {
+        if self.changes == 0 {
+            return Err("gone".into());
+        }
+        self.changes -= 1;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+}
+
+fn unavailable(_: Sender<()>, _: bool) -> Result<&'static str, String> {
+    Err("no display".into())
+}
+
+/// Sends one change, then stops
+fn one_change(tx: Sender<()>, _: bool) -> Result<&'static str, String> {
+    tx.send(()).unwrap();
+    Ok("one-change")
+}
+
+#[test]
+fn picks_the_first_listener_that_starts() {
+    static SPAWNED: AtomicBool = AtomicBool::new(false);
+    let never: Listener = |_, _| {
+        SPAWNED.store(true, Ordering::SeqCst);
+        Ok("never")
+    };
+    let source = first_available(&[("A", unavailable), ("B", one_change), ("C", never)], 50, false);
+    assert_eq!(source.name(), "one-change");
+    assert!(!SPAWNED.load(Ordering::SeqCst), "later listeners aren't started");
+
+    assert_eq!(first_available(&[("A", unavailable)], 50, false).name(), "polling");
+    assert_eq!(first_available(&[], 50, false).name(), "polling");
+}
+
+#[test]
+fn listeners_are_told_whether_to_watch_primary() {
+    static PRIMARY: AtomicUsize = AtomicUsize::new(0);
+    let listener: Listener = |_, watch_primary| {
+        PRIMARY.fetch_add(if watch_primary { 10 } else { 1 }, Ordering::SeqCst);
+        Ok("primary")
+    };
+    first_available(&[("A", listener)], 50, true);
+    first_available(&[("A", listener)], 50, false);
+    assert_eq!(PRIMARY.load(Ordering::SeqCst), 11);
+}
+
+#[test]
+fn falls_back_to_polling_when_events_stop() {
+    // The listener sends one change and hangs up
+    let mut source = first_available(&[("A", one_change)], 50, false);
+    assert_eq!(source.name(), "one-change");
+    source.wait().unwrap();
+    source.wait().unwrap();
+    assert_eq!(source.name(), "polling");
+
+    let mut source = Fallback::new(Box::new(FakeSource { changes: 2 }), 50);
+    for _ in 0..2 {
+        source.wait().unwrap();
+        assert_eq!(source.name(), "fake");
+    }
+    let start = Instant::now();
+    source.wait().unwrap();
+    assert_eq!(source.name(), "polling");
+    assert!(start.elapsed() >= Duration::from_millis(50), "polls right away");
+}
+
+#[test]
+fn polling_waits_at_least_50ms() {
+    let start = Instant::now();
+    PollingSource::new(0).wait().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn coalesces_queued_events() {
+    let (tx, rx) = channel();
+    let mut source = EventSource::new(rx, "events");
+    for _ in 0..5 {
+        tx.send(()).unwrap();
+    }
+    source.wait().unwrap();
+    // Nothing was left queued, so the next wait sees the listener is gone
+    drop(tx);
+    assert_eq!(source.wait().unwrap_err(), "events listener stopped");
+}
+
+#[test]
+fn waits_for_the_clipboard_to_settle() {
+    let (tx, rx) = channel();
+    let mut source = EventSource::new(rx, "events");
+    let start = Instant::now();
+    let sender = std::thread::spawn(move || {
+        for _ in 0..3 {
+            tx.send(()).unwrap();
+            std::thread::sleep(DEBOUNCE / 5);
+        }
+        tx
+    });
+    source.wait().unwrap();
+    assert!(start.elapsed() >= DEBOUNCE, "read before the clipboard settled");
+
+    // The whole burst was a single change
+    drop(sender.join().unwrap());
+    assert!(source.wait().is_err());
+}
+
+#[test]
+fn a_steady_stream_of_events_still_wakes() {
+    let (tx, rx) = channel();
+    let mut source = EventSource::new(rx, "events");
+    let stop = std::sync::Arc::new(AtomicBool::new(false));
+    let sending = stop.clone();
+    let sender = std::thread::spawn(move || {
+        while !sending.load(Ordering::SeqCst) {
+            let _ = tx.send(());
+            std::thread::sleep(DEBOUNCE / 10);
+        }
+    });
+    let start = Instant::now();
+    source.wait().unwrap();
+    let waited = start.elapsed();
+    stop.store(true, Ordering::SeqCst);
+    sender.join().unwrap();
+    assert!(waited >= MAX_DEBOUNCE, "returned during the burst");
+    assert!(waited < MAX_DEBOUNCE * 2, "held back for {waited:?}");
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared by the storage integration tests
+
+use app_lib::models::Item;
+use app_lib::storage::Storage;
+use std::path::Path;
+
+pub fn text_item(id: &str, text: &str) -> Item {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "kind": "text",
+        "contentText": text,
+        "contentHtml": format!("<b>{}</b>", text),
+        "category": "text",
+        "timestamp": "2026-10-01T00:00:00Z",
+        "pinned": false,
+    }))
+    .unwrap()
+}
+
+pub fn add(storage: &Storage, item: Item) {
+    storage.save_item(&item);
+    storage.data.lock().unwrap().items.push(item);
+}
+
+/// The item with `id`, in the history or the vault
+pub fn find(storage: &Storage, id: &str) -> Option<Item> {
+    let data = storage.data.lock().unwrap();
+    data.items.iter().chain(&data.vault).find(|i| i.id == id).cloned()
+}
+
+/// Whether `needle` appears in any file under `dir`
+pub fn on_disk(dir: &Path, needle: &[u8]) -> bool {
+    std::fs::read_dir(dir).unwrap().flatten().any(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            return on_disk(&path, needle);
+        }
+        let bytes = std::fs::read(&path).unwrap_or_default();
+        bytes.windows(needle.len()).any(|w| w == needle)
+    })
+}
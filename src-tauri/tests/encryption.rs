@@ -0,0 +1,214 @@
+mod common;
+
+use app_lib::clipboard_backend::Flavor;
+use app_lib::encryption::{self, KeySource};
+use app_lib::storage::Storage;
+use common::{add, find, on_disk, text_item};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const PASSPHRASE: &str = "correct horse battery";
+
+fn png() -> Vec<u8> {
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([10, 200, 10, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+/// An item with an image, its thumbnail and a captured flavor
+fn add_image(storage: &Storage, id: &str) -> String {
+    let png = png();
+    let mut item = text_item(id, "");
+    item.content_text = None;
+    item.content_html = None;
+    let name = storage.store_image(id, &png).unwrap();
+    storage
+        .store_thumbnail(&name, &image::load_from_memory(&png).unwrap().to_rgba8())
+        .unwrap();
+    item.flavors = storage.store_flavors(&[Flavor {
+        mime: "application/x-secret".into(),
+        data: b"flavor-secret".to_vec(),
+    }]);
+    item.image_path = Some(name.clone());
+    add(storage, item);
+    name
+}
+
+/// The file name of an item's image
+fn image_path(storage: &Storage, id: &str) -> String {
+    find(storage, id).unwrap().image_path.unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Names of the files in `dir`
+fn file_names(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+const PNG_MAGIC: &[u8] = b"\x89PNG";
+
+#[test]
+fn passphrase_encryption_keeps_the_history_unreadable_at_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = {
+        let storage = Storage::new(dir.path());
+        add(&storage, text_item("a", "hunter2-history"));
+        add_image(&storage, "img");
+        assert!(!encryption::status(&storage).enabled);
+
+        assert!(encryption::enable(&storage, Some("short")).is_err());
+        encryption::enable(&storage, Some(PASSPHRASE)).unwrap();
+        assert!(encryption::enable(&storage, Some(PASSPHRASE)).is_err());
+        let status = encryption::status(&storage);
+        assert!(status.enabled && !status.locked);
+        assert_eq!(status.source, Some(KeySource::Passphrase));
+
+        // Still readable in the running app
+        let image = image_path(&storage, "img");
+        assert_eq!(storage.read_image(&image).unwrap(), png());
+        assert_eq!(find(&storage, "a").unwrap().content_text.as_deref(), Some("hunter2-history"));
+        add(&storage, text_item("b", "hunter2-later"));
+        image
+    };
+    assert!(!on_disk(dir.path(), b"hunter2-history"));
+    assert!(!on_disk(dir.path(), b"hunter2-later"));
+    assert!(!on_disk(dir.path(), b"flavor-secret"));
+    assert!(!on_disk(dir.path(), PNG_MAGIC));
+
+    let storage = Storage::new(dir.path());
+    assert!(encryption::status(&storage).locked);
+    assert!(find(&storage, "a").is_none());
+    assert!(storage.read_image(&image).is_err());
+    // Nothing is swept while the references are unknown
+    assert_eq!(storage.collect_garbage(), 0);
+    assert_eq!(encryption::unlock(&storage, "wrong horse battery").unwrap_err(), encryption::WRONG_PASSPHRASE);
+
+    encryption::unlock(&storage, PASSPHRASE).unwrap();
+    assert!(!encryption::status(&storage).locked);
+    assert_eq!(find(&storage, "a").unwrap().content_text.as_deref(), Some("hunter2-history"));
+    assert_eq!(find(&storage, "b").unwrap().content_text.as_deref(), Some("hunter2-later"));
+    assert_eq!(storage.read_image(&image).unwrap(), png());
+    let flavors = storage.load_flavors(&find(&storage, "img").unwrap().flavors);
+    assert_eq!(flavors[0].data, b"flavor-secret");
+    assert_eq!(storage.collect_garbage(), 0);
+}
+
+#[test]
+fn disabling_encryption_decrypts_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = {
+        let storage = Storage::new(dir.path());
+        add(&storage, text_item("a", "hunter2-history"));
+        let image = add_image(&storage, "img");
+        encryption::enable(&storage, Some(PASSPHRASE)).unwrap();
+        assert_ne!(image_path(&storage, "img"), image);
+
+        assert_eq!(encryption::disable(&storage, Some("nope")).unwrap_err(), encryption::WRONG_PASSPHRASE);
+        encryption::disable(&storage, Some(PASSPHRASE)).unwrap();
+        assert!(!encryption::status(&storage).enabled);
+        assert!(encryption::disable(&storage, Some(PASSPHRASE)).is_err());
+        // Back under its plain content hash
+        assert_eq!(image_path(&storage, "img"), image);
+        image
+    };
+    assert!(on_disk(dir.path(), b"hunter2-history"));
+
+    let storage = Storage::new(dir.path());
+    let status = encryption::status(&storage);
+    assert!(!status.enabled && !status.locked);
+    assert_eq!(find(&storage, "a").unwrap().content_text.as_deref(), Some("hunter2-history"));
+    assert!(image::open(storage.images_dir().join(&image)).is_ok());
+    assert!(image::open(storage.thumbs_dir().join(&image)).is_ok());
+}
+
+/// The `category` column of every item row
+fn category_columns(dir: &std::path::Path) -> Vec<String> {
+    let conn = rusqlite::Connection::open(dir.join("copas.db")).unwrap();
+    let mut stmt = conn.prepare("SELECT category FROM items ORDER BY id").unwrap();
+    let categories = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+    categories
+}
+
+#[test]
+fn categories_are_only_kept_in_sealed_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut email = text_item("a", "someone@example.com");
+    email.category = "email".into();
+    {
+        let storage = Storage::new(dir.path());
+        add(&storage, email.clone());
+        assert_eq!(category_columns(dir.path()), ["email"]);
+
+        encryption::enable(&storage, Some(PASSPHRASE)).unwrap();
+        assert_eq!(category_columns(dir.path()), [""]);
+        let mut link = text_item("b", "https://example.com");
+        link.category = "link".into();
+        add(&storage, link);
+        assert_eq!(category_columns(dir.path()), ["", ""]);
+    }
+
+    let storage = Storage::new(dir.path());
+    encryption::unlock(&storage, PASSPHRASE).unwrap();
+    assert_eq!(find(&storage, "a").unwrap().category, "email");
+    assert_eq!(find(&storage, "b").unwrap().category, "link");
+    encryption::disable(&storage, Some(PASSPHRASE)).unwrap();
+    assert_eq!(category_columns(dir.path()), ["email", "link"]);
+}
+
+#[test]
+fn interrupted_migrations_resume_on_unlock() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = {
+        let storage = Storage::new(dir.path());
+        let plain = add_image(&storage, "img");
+        encryption::enable(&storage, Some(PASSPHRASE)).unwrap();
+        // As if the app stopped before this file was rewritten and renamed
+        let image = image_path(&storage, "img");
+        std::fs::remove_file(storage.images_dir().join(&image)).unwrap();
+        std::fs::write(storage.images_dir().join(&plain), png()).unwrap();
+        storage.set_meta("encryption_migration", "encrypt").unwrap();
+        image
+    };
+    assert!(on_disk(dir.path(), PNG_MAGIC));
+
+    let storage = Storage::new(dir.path());
+    encryption::unlock(&storage, PASSPHRASE).unwrap();
+    assert_eq!(storage.read_image(&image).unwrap(), png());
+    drop(storage);
+    assert!(!on_disk(dir.path(), PNG_MAGIC));
+}
+
+#[test]
+fn encrypted_files_are_not_named_by_their_content_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path());
+    add_image(&storage, "img");
+    let plain = format!("{}.png", sha256(&png()));
+    assert_eq!(file_names(storage.images_dir()), [plain.as_str()]);
+    assert_eq!(file_names(storage.flavors_dir()), [sha256(b"flavor-secret")]);
+
+    encryption::enable(&storage, Some(PASSPHRASE)).unwrap();
+    let image = image_path(&storage, "img");
+    assert_ne!(image, plain);
+    assert_eq!(file_names(storage.images_dir()), [image.as_str()]);
+    assert_eq!(file_names(storage.thumbs_dir()), [image.as_str()]);
+    let flavors = file_names(storage.flavors_dir());
+    assert_ne!(flavors, [sha256(b"flavor-secret")]);
+    assert_eq!(find(&storage, "img").unwrap().flavors.values().collect::<Vec<_>>(), [&flavors[0]]);
+
+    // New copies of the same image still share its file
+    add_image(&storage, "again");
+    assert_eq!(image_path(&storage, "again"), image);
+    assert_eq!(file_names(storage.images_dir()), [image.as_str()]);
+    assert_eq!(storage.collect_garbage(), 0);
+    assert_eq!(storage.read_image(&image).unwrap(), png());
+}
@@ -0,0 +1,118 @@
+use app_lib::history::{self, HistoryQuery};
+use app_lib::models::{AppData, HistoryResult, Item, SortOrder};
+use app_lib::search::SearchIndex;
+
+fn item(id: &str, text: &str, day: u32, pinned: bool) -> Item {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "kind": "text",
+        "contentText": text,
+        "category": "text",
+        "timestamp": format!("2026-10-{day:02}T12:00:00Z"),
+        "pinned": pinned,
+    }))
+    .unwrap()
+}
+
+fn data(items: Vec<Item>) -> AppData {
+    AppData { items, ..AppData::default() }
+}
+
+fn run(data: &AppData, query: &HistoryQuery) -> HistoryResult {
+    history::query(data, &SearchIndex::build(&data.items), query)
+}
+
+fn ids(result: &HistoryResult) -> Vec<&str> {
+    result.items.iter().map(|i| i.id.as_str()).collect()
+}
+
+/// Twelve items, two of them pinned, with several sharing a timestamp
+fn twelve() -> AppData {
+    data((0..12).map(|n| item(&format!("i{n:02}"), "note", 1 + n / 3, n == 3 || n == 7)).collect())
+}
+
+#[test]
+fn pages_cover_every_item_once() {
+    let data = twelve();
+    let all = run(&data, &HistoryQuery::default());
+    assert_eq!(all.total, 12);
+    assert!(!all.has_more);
+    assert_eq!(&ids(&all)[..2], ["i07", "i03"]);
+
+    let mut paged = Vec::new();
+    for (page, has_more) in [(0, true), (1, true), (2, false)] {
+        let query = HistoryQuery { page: Some(page), page_size: Some(5), ..Default::default() };
+        let result = run(&data, &query);
+        assert_eq!(result.total, 12);
+        assert_eq!(result.has_more, has_more, "page {page}");
+        paged.extend(result.items.into_iter().map(|i| i.id));
+    }
+    assert_eq!(paged, ids(&all));
+
+    // Pages that run exactly to the end
+    let query = HistoryQuery { page: Some(1), page_size: Some(6), ..Default::default() };
+    let result = run(&data, &query);
+    assert_eq!(result.items.len(), 6);
+    assert!(!result.has_more);
+}
+
+#[test]
+fn pages_past_the_end_are_empty() {
+    let data = twelve();
+    for (page, page_size) in [(3, 5), (100, 5), (usize::MAX, 500), (1, usize::MAX)] {
+        let query = HistoryQuery { page: Some(page), page_size: Some(page_size), ..Default::default() };
+        let result = run(&data, &query);
+        assert!(result.items.is_empty(), "page {page} of {page_size}");
+        assert!(!result.has_more, "page {page} of {page_size}");
+        assert_eq!(result.total, 12);
+    }
+
+    // A page size of zero still returns something
+    let query = HistoryQuery { page_size: Some(0), ..Default::default() };
+    let result = run(&data, &query);
+    assert_eq!(result.items.len(), 1);
+    assert!(result.has_more);
+}
+
+#[test]
+fn sort_orders_keep_pinned_items_first() {
+    let with = |mut item: Item, paste_count: u32, label: &str| {
+        item.paste_count = paste_count;
+        item.label = label.into();
+        item
+    };
+    let data = data(vec![
+        with(item("a", "banana", 1, false), 5, "zeta"),
+        with(item("b", "Apple", 2, true), 0, ""),
+        with(item("c", "cherry", 3, false), 9, "alpha"),
+        with(item("d", "date", 4, false), 1, "Delta"),
+        with(item("e", "elderberry", 5, true), 9, "Beta"),
+    ]);
+    let cases = [
+        (SortOrder::Newest, ["e", "b", "d", "c", "a"]),
+        (SortOrder::Oldest, ["b", "e", "a", "c", "d"]),
+        // Ties go to the newest
+        (SortOrder::MostPasted, ["e", "b", "c", "a", "d"]),
+        (SortOrder::Alphabetical, ["b", "e", "a", "c", "d"]),
+        // Labelled items first, ignoring case
+        (SortOrder::Label, ["e", "b", "c", "d", "a"]),
+    ];
+    for (sort, expected) in cases {
+        let result = run(&data, &HistoryQuery { sort: Some(sort), ..Default::default() });
+        assert_eq!(ids(&result), expected, "{sort:?}");
+    }
+    assert_eq!(ids(&run(&data, &HistoryQuery::default())), ["e", "b", "d", "c", "a"]);
+}
+
+#[test]
+fn relevance_ignores_pinning() {
+    let mut labelled = item("label", "something", 1, false);
+    labelled.label = "rust".into();
+    let data = data(vec![labelled, item("pinned", "rust", 2, true), item("other", "python", 3, false)]);
+
+    // Searching sorts by relevance unless told otherwise
+    let search = |sort| HistoryQuery { search: Some("rust".into()), sort, ..Default::default() };
+    assert_eq!(ids(&run(&data, &search(None))), ["label", "pinned"]);
+    assert_eq!(ids(&run(&data, &search(Some(SortOrder::Relevance)))), ["label", "pinned"]);
+    assert_eq!(ids(&run(&data, &search(Some(SortOrder::Oldest)))), ["pinned", "label"]);
+}
@@ -0,0 +1,178 @@
+use app_lib::models::Item;
+use app_lib::query::{parse, Matcher, QueryError};
+use app_lib::search::SearchIndex;
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+
+/// Noon local time, `days` days ago
+fn days_ago(days: i64) -> String {
+    let date = Local::now().date_naive() - Duration::days(days);
+    local_noon(date)
+}
+
+fn local_noon(date: NaiveDate) -> String {
+    Local
+        .from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap())
+        .unwrap()
+        .to_rfc3339()
+}
+
+fn items() -> Vec<Item> {
+    let september = local_noon(NaiveDate::from_ymd_opt(2026, 9, 1).unwrap());
+    [
+        serde_json::json!({
+            "id": "note", "kind": "text", "contentText": "Tiếng Việt meeting notes", "category": "text",
+            "label": "Weekly sync", "sourceApp": "Firefox", "tabIds": ["work"], "timestamp": days_ago(0), "pinned": false,
+        }),
+        serde_json::json!({
+            "id": "link", "kind": "text", "contentText": "https://example.com/docs", "category": "link",
+            "sourceApp": "Google Chrome", "pinned": true, "timestamp": days_ago(10),
+        }),
+        serde_json::json!({
+            "id": "mail", "kind": "text", "contentText": "someone@example.com draft", "category": "email",
+            "timestamp": days_ago(7), "pinned": false,
+        }),
+        serde_json::json!({
+            "id": "img", "kind": "image", "category": "image", "timestamp": september, "pinned": false,
+        }),
+    ]
+    .into_iter()
+    .map(|json| serde_json::from_value(json).unwrap())
+    .collect()
+}
+
+/// Ids of the items `query` matches, in `items()` order
+fn matching(query: &str) -> Vec<String> {
+    let items = items();
+    let index = SearchIndex::build(&items);
+    let matcher = Matcher::new(parse(query).unwrap_or_else(|e| panic!("{query:?}: {e:?}")), &index);
+    items.into_iter().filter(|i| matcher.matches(i)).map(|i| i.id).collect()
+}
+
+fn check(cases: &[(&str, &[&str])]) {
+    for (query, expected) in cases {
+        assert_eq!(matching(query), *expected, "{query:?}");
+    }
+}
+
+#[test]
+fn field_filters() {
+    check(&[
+        ("kind:image", &["img"]),
+        ("kind:TEXT", &["note", "link", "mail"]),
+        ("kind:files", &[]),
+        ("category:link", &["link"]),
+        ("cat:Email", &["mail"]),
+        ("tab:work", &["note"]),
+        ("tab:other", &[]),
+        ("pinned:true", &["link"]),
+        ("pinned:no", &["note", "mail", "img"]),
+        ("label:week", &["note"]),
+        ("label:\"weekly sync\"", &["note"]),
+        ("app:fire", &["note"]),
+        ("source:chrome", &["link"]),
+        ("source_app:safari", &[]),
+    ]);
+}
+
+#[test]
+fn dates_are_whole_local_days() {
+    check(&[
+        ("on:2026-09-01", &["img"]),
+        ("date:2026-08-31", &[]),
+        // `after:` includes the day itself, `before:` doesn't
+        ("after:2026-09-01 kind:image", &["img"]),
+        ("after:2026-09-02 kind:image", &[]),
+        ("before:2026-09-01 kind:image", &[]),
+        ("before:2026-09-02 kind:image", &["img"]),
+        ("timestamp:>=2026-09-01 kind:image", &["img"]),
+        ("timestamp:>2026-09-01 kind:image", &[]),
+        ("timestamp:<=2026-09-01 kind:image", &["img"]),
+    ]);
+}
+
+#[test]
+fn relative_dates() {
+    check(&[
+        ("on:today kind:text", &["note"]),
+        ("after:today kind:text", &["note"]),
+        ("before:today kind:text", &["link", "mail"]),
+        ("on:this-week kind:text", &["note"]),
+        ("on:7d kind:text", &["mail"]),
+        ("after:7d kind:text", &["note", "mail"]),
+        ("before:7d kind:text", &["link"]),
+        ("on:1w kind:text", &["mail"]),
+        ("after:2w kind:text", &["note", "link", "mail"]),
+    ]);
+}
+
+#[test]
+fn operators_and_grouping() {
+    check(&[
+        ("tieng viet", &["note"]),
+        ("meeting draft", &[]),
+        ("meeting AND draft", &[]),
+        ("meeting && notes", &["note"]),
+        ("meeting OR draft", &["note", "mail"]),
+        ("meeting || draft", &["note", "mail"]),
+        ("example -draft", &["link"]),
+        ("example NOT draft", &["link"]),
+        ("-kind:text", &["img"]),
+        // AND binds tighter than OR, NOT tighter than AND
+        ("draft OR meeting kind:image", &["mail"]),
+        ("(draft OR meeting) kind:text", &["note", "mail"]),
+        ("NOT draft example", &["link"]),
+        ("-(draft OR meeting)", &["link", "img"]),
+        ("(category:link OR category:email) NOT pinned:true", &["mail"]),
+    ]);
+}
+
+#[test]
+fn quoted_phrases() {
+    check(&[
+        ("\"meeting notes\"", &["note"]),
+        ("\"notes meeting\"", &[]),
+        // Case and accents are ignored
+        ("\"TIENG VIET\"", &["note"]),
+        ("\"@example.com\"", &["mail"]),
+        ("\"weekly sync\"", &["note"]),
+        ("-\"example.com\"", &["note", "img"]),
+    ]);
+}
+
+fn error(query: &str) -> QueryError {
+    parse(query).unwrap_err()
+}
+
+fn span(query: &str) -> (usize, usize) {
+    let e = error(query);
+    (e.start, e.end)
+}
+
+#[test]
+fn errors_point_at_utf16_offsets() {
+    // Unterminated quotes run to the end; the emoji is two UTF-16 units
+    assert_eq!(error("😀 \"abc").message, "Unterminated quote");
+    assert_eq!(span("😀 \"abc"), (3, 7));
+    assert_eq!(span("label:\"abc"), (6, 10));
+
+    assert_eq!(error("kind:").message, "Missing value for 'kind:'");
+    assert_eq!(span("é kind:"), (2, 7));
+    assert!(error("kind:video").message.starts_with("Unknown kind"));
+    assert!(error("pinned:maybe").message.starts_with("Expected true or false"));
+
+    assert!(error("after:2026-13-01").message.starts_with("Invalid date"));
+    assert_eq!(span("😀 after:2026-13-01"), (3, 19));
+    assert_eq!(span("on:someday"), (0, 10));
+
+    // Dangling operators
+    assert_eq!(error("foo OR").message, "Expected a search term");
+    assert_eq!(span("foo OR"), (6, 6));
+    assert_eq!(span("foo AND"), (7, 7));
+    assert_eq!(span("NOT"), (3, 3));
+    assert_eq!(span("OR foo"), (0, 2));
+    assert_eq!(span("foo OR OR bar"), (7, 9));
+    assert_eq!(error("(foo").message, "Missing ')'");
+    assert_eq!(span("(foo"), (0, 4));
+    assert_eq!(error("foo)").message, "Unexpected ')'");
+    assert_eq!(span("😀 foo)"), (6, 7));
+}
@@ -0,0 +1,164 @@
+use app_lib::models::Item;
+use app_lib::search::{fold, highlights, SearchIndex};
+
+fn item(id: &str, text: &str) -> Item {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "kind": "text",
+        "contentText": text,
+        "category": "text",
+        "timestamp": "2026-10-01T00:00:00Z",
+        "pinned": false,
+    }))
+    .unwrap()
+}
+
+/// Ids of the items found for `query`, best first
+fn ranked(index: &SearchIndex, query: &str) -> Vec<String> {
+    let mut hits: Vec<(String, f32)> = index.search(query).into_iter().collect();
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hits.into_iter().map(|(id, _)| id).collect()
+}
+
+/// Ids of the items found for `query`, sorted by id
+fn found(index: &SearchIndex, query: &str) -> Vec<String> {
+    let mut ids: Vec<String> = index.search(query).into_keys().collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn folding_strips_case_and_accents() {
+    let cases = [
+        ("Tiếng Việt", "tieng viet"),
+        ("Đà Nẵng", "da nang"),
+        ("ĐƯỜNG đi", "duong di"),
+        ("ÀÉÎÕÜ", "aeiou"),
+        // Decomposed input folds the same as precomposed
+        ("Cafe\u{301}", "cafe"),
+        ("plain ascii", "plain ascii"),
+    ];
+    for (text, folded) in cases {
+        assert_eq!(fold(text), folded, "{text:?}");
+    }
+}
+
+#[test]
+fn finds_vietnamese_without_diacritics() {
+    let index = SearchIndex::build(&[item("vi", "Tiếng Việt có dấu"), item("en", "plain english")]);
+    for query in ["tieng viet", "Tiếng Việt", "TIENG", "dau", "co dau"] {
+        assert_eq!(found(&index, query), ["vi"], "{query:?}");
+    }
+}
+
+#[test]
+fn every_term_must_match() {
+    let index = SearchIndex::build(&[item("a", "red apple"), item("b", "green apple"), item("c", "red car")]);
+    assert_eq!(found(&index, "apple"), ["a", "b"]);
+    assert_eq!(found(&index, "red apple"), ["a"]);
+    assert!(found(&index, "blue apple").is_empty());
+    assert!(found(&index, "").is_empty());
+    assert!(found(&index, "!!").is_empty());
+}
+
+#[test]
+fn matches_prefixes() {
+    let index = SearchIndex::build(&[item("a", "meeting notes"), item("b", "meat")]);
+    assert_eq!(found(&index, "meeti"), ["a"]);
+    assert_eq!(found(&index, "me"), ["a", "b"]);
+    // One edit from "meat", so a typo match as well
+    assert_eq!(found(&index, "meet"), ["a", "b"]);
+    // Not a prefix of anything, and too far from both words
+    assert!(found(&index, "meetx").is_empty());
+}
+
+#[test]
+fn typo_tolerance_grows_with_term_length() {
+    let index = SearchIndex::build(&[item("cat", "cat"), item("house", "house"), item("keyboard", "keyboard")]);
+    let cases: [(&str, &[&str]); 7] = [
+        // Up to 3 characters: exact or prefix only
+        ("cot", &[]),
+        ("ca", &["cat"]),
+        // 4 to 7 characters: one edit
+        ("horse", &["house"]),
+        ("huose", &[]),
+        // 8 and more: two edits
+        ("keybaord", &["keyboard"]),
+        ("kaybaord", &[]),
+        ("keyboards", &["keyboard"]),
+    ];
+    for (query, expected) in cases {
+        assert_eq!(found(&index, query), expected, "{query:?}");
+    }
+}
+
+#[test]
+fn ranks_exact_over_prefix_over_fuzzy_and_labels_over_text() {
+    let index = SearchIndex::build(&[item("fuzzy", "text"), item("prefix", "testing"), item("exact", "test")]);
+    assert_eq!(ranked(&index, "test"), ["exact", "prefix", "fuzzy"]);
+
+    let mut labelled = item("label", "something else");
+    labelled.label = "rust".into();
+    let index = SearchIndex::build(&[item("body", "rust"), labelled]);
+    assert_eq!(ranked(&index, "rust"), ["label", "body"]);
+}
+
+#[test]
+fn sensitive_text_is_not_indexed() {
+    let mut secret = item("secret", "hunter2 password");
+    secret.sensitive = true;
+    secret.label = "bank".into();
+    let index = SearchIndex::build(&[secret]);
+    assert!(found(&index, "hunter2").is_empty());
+    assert_eq!(found(&index, "bank"), ["secret"]);
+}
+
+#[test]
+fn stays_in_sync_with_writes() {
+    let mut index = SearchIndex::build(&[item("a", "alpha"), item("b", "beta")]);
+
+    index.insert(&item("c", "gamma alpha"));
+    assert_eq!(found(&index, "alpha"), ["a", "c"]);
+
+    // Re-inserting replaces the old terms
+    index.insert(&item("a", "delta"));
+    assert_eq!(found(&index, "alpha"), ["c"]);
+    assert_eq!(found(&index, "delta"), ["a"]);
+
+    index.remove("c");
+    assert!(found(&index, "alpha").is_empty());
+    assert!(found(&index, "gamma").is_empty());
+    index.remove("missing");
+    assert_eq!(found(&index, "beta"), ["b"]);
+
+    index.rebuild(&[item("z", "omega")]);
+    assert!(found(&index, "beta").is_empty());
+    assert_eq!(found(&index, "omega"), ["z"]);
+}
+
+/// (field, start, end) of each highlight
+fn ranges(item: &Item, query: &str) -> Vec<(String, usize, usize)> {
+    highlights(item, query).into_iter().map(|r| (r.field, r.start, r.end)).collect()
+}
+
+#[test]
+fn highlights_are_utf16_ranges() {
+    let text = |s: &str, start, end| (s.to_string(), start, end);
+
+    // The emoji takes two UTF-16 units
+    let vi = item("vi", "😀 Tiếng Việt");
+    assert_eq!(ranges(&vi, "tieng viet"), [text("text", 3, 8), text("text", 9, 13)]);
+
+    // A combining accent is part of the word it follows
+    let cafe = item("cafe", "Cafe\u{301} au lait, cafe");
+    assert_eq!(ranges(&cafe, "cafe"), [text("text", 0, 5), text("text", 15, 19)]);
+
+    // Prefix and fuzzy matches are highlighted too, in the label as well
+    let mut labelled = item("l", "testing 𝒳 text");
+    labelled.label = "Test".into();
+    assert_eq!(
+        ranges(&labelled, "test"),
+        [text("text", 0, 7), text("text", 11, 15), text("label", 0, 4)]
+    );
+    assert!(ranges(&labelled, "").is_empty());
+}
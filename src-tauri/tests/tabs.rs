@@ -0,0 +1,92 @@
+use app_lib::models::{AppData, Tab};
+use app_lib::tabs;
+
+fn tree(tabs: &[Tab]) -> Vec<(String, Option<String>, usize)> {
+    tabs.iter().map(|t| (t.id.clone(), t.parent_id.clone(), t.position)).collect()
+}
+
+fn entry(id: &str, parent: Option<&str>, position: usize) -> (String, Option<String>, usize) {
+    (id.to_string(), parent.map(str::to_string), position)
+}
+
+fn sample() -> Vec<Tab> {
+    let mut tabs = AppData::default().tabs;
+    tabs.push(Tab::new("work", "Work", "💼", false));
+    tabs.push(Tab::new("client", "Client", "🎯", false));
+    tabs::normalize(&mut tabs);
+    tabs
+}
+
+#[test]
+fn normalize_keeps_legacy_order_and_repairs_parents() {
+    let mut tabs = sample();
+    assert_eq!(
+        tree(&tabs),
+        vec![
+            entry("all", None, 0),
+            entry("links", None, 1),
+            entry("important", None, 2),
+            entry("work", None, 3),
+            entry("client", None, 4),
+        ]
+    );
+
+    tabs[3].parent_id = Some("client".into());
+    tabs[4].parent_id = Some("work".into());
+    tabs[2].parent_id = Some("gone".into());
+    tabs[1].parent_id = Some("important".into());
+    tabs::normalize(&mut tabs);
+    // The cycle is broken and the dangling parent dropped; built-in tabs never nest
+    assert_eq!(
+        tree(&tabs),
+        vec![
+            entry("all", None, 0),
+            entry("links", None, 1),
+            entry("important", None, 2),
+            entry("work", None, 3),
+            entry("client", Some("work"), 0),
+        ]
+    );
+}
+
+#[test]
+fn move_tab_nests_and_orders_siblings() {
+    let mut tabs = sample();
+    tabs::move_tab(&mut tabs, "client", Some("work"), None).unwrap();
+    tabs::move_tab(&mut tabs, "important", Some("work"), Some(0)).unwrap();
+    assert_eq!(
+        tree(&tabs),
+        vec![
+            entry("all", None, 0),
+            entry("links", None, 1),
+            entry("work", None, 2),
+            entry("important", Some("work"), 0),
+            entry("client", Some("work"), 1),
+        ]
+    );
+    assert_eq!(tabs::with_descendants(&tabs, "work"), vec!["work", "important", "client"]);
+
+    // Nothing goes before "all"
+    tabs::move_tab(&mut tabs, "work", None, Some(0)).unwrap();
+    assert_eq!(tabs[0].id, "all");
+    assert_eq!(tabs[1].id, "work");
+
+    assert!(tabs::move_tab(&mut tabs, "work", Some("client"), None).is_err());
+    assert!(tabs::move_tab(&mut tabs, "links", Some("work"), None).is_err());
+    assert!(tabs::move_tab(&mut tabs, "client", Some("all"), None).is_err());
+    assert!(tabs::move_tab(&mut tabs, "all", None, Some(3)).is_err());
+}
+
+#[test]
+fn reorder_puts_listed_tabs_first() {
+    let mut tabs = sample();
+    tabs::reorder(&mut tabs, None, &["client".into(), "all".into(), "important".into()]);
+    let ids: Vec<&str> = tabs.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec!["all", "client", "important", "links", "work"]);
+}
+
+#[test]
+fn new_ids_are_unique() {
+    let ids: std::collections::HashSet<String> = (0..1000).map(|_| tabs::new_id()).collect();
+    assert_eq!(ids.len(), 1000);
+}
@@ -0,0 +1,354 @@
+mod common;
+
+use app_lib::clipboard_backend::{Flavor, TEXT_HTML, TEXT_RTF};
+use app_lib::models::{Item, MASK};
+use app_lib::storage::Storage;
+use app_lib::vault;
+use common::{add, find, on_disk, text_item};
+
+fn in_history(storage: &Storage, id: &str) -> bool {
+    storage.data.lock().unwrap().items.iter().any(|i| i.id == id)
+}
+
+fn flavor(mime: &str, data: &str) -> Flavor {
+    Flavor {
+        mime: mime.into(),
+        data: data.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn vault_items_are_sealed_at_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        add(&storage, text_item("a", "hunter2-secret"));
+        assert!(vault::move_to_vault(&storage, "a").is_err(), "needs a PIN first");
+
+        vault::set_pin(&storage, "2468", false).unwrap();
+        assert!(vault::set_pin(&storage, "1111", false).is_err(), "PIN can't be overwritten");
+        vault::move_to_vault(&storage, "a").unwrap();
+
+        let sealed = find(&storage, "a").unwrap();
+        assert!(sealed.in_vault && sealed.sealed.is_some());
+        assert_eq!(sealed.content_text, None);
+        assert_eq!(sealed.masked().content_text.as_deref(), Some(MASK));
+        assert_eq!(sealed.masked().sealed, None);
+    }
+    assert!(!on_disk(dir.path(), b"hunter2-secret"));
+    assert!(!on_disk(dir.path(), b"2468"));
+
+    let storage = Storage::new(dir.path());
+    assert!(vault::has_pin(&storage));
+    assert!(!vault::is_unlocked(&storage));
+    assert!(vault::open_items(&storage).is_err());
+    assert!(vault::open(&storage, "a").is_err());
+    assert_eq!(vault::unlock(&storage, "1357").unwrap_err(), vault::WRONG_PIN);
+
+    vault::unlock(&storage, "2468").unwrap();
+    let opened = vault::open_items(&storage).unwrap();
+    assert_eq!(opened[0].content_text.as_deref(), Some("hunter2-secret"));
+    assert_eq!(opened[0].content_html.as_deref(), Some("<b>hunter2-secret</b>"));
+    // Only the in-memory copy is decrypted
+    assert_eq!(find(&storage, "a").unwrap().content_text, None);
+
+    vault::remove_from_vault(&storage, "a").unwrap();
+    let item = find(&storage, "a").unwrap();
+    assert!(!item.in_vault && item.sealed.is_none());
+    assert_eq!(item.content_text.as_deref(), Some("hunter2-secret"));
+
+    vault::lock(&storage);
+    assert!(vault::move_to_vault(&storage, "a").is_err());
+}
+
+#[test]
+fn vault_images_are_encrypted_and_restored() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path());
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([200, 10, 10, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let mut item = text_item("img", "");
+    item.content_text = None;
+    item.content_html = None;
+    item.image_path = Some(storage.store_image("img", &png).unwrap());
+    let plain = item.image_path.clone().unwrap();
+    add(&storage, item);
+
+    vault::set_pin(&storage, "2468", false).unwrap();
+    vault::move_to_vault(&storage, "img").unwrap();
+    let sealed = find(&storage, "img").unwrap().image_path.unwrap();
+    assert!(sealed.ends_with(vault::SEALED_EXT));
+    assert!(!storage.images_dir().join(&plain).exists());
+    assert!(image::open(storage.images_dir().join(&sealed)).is_err());
+
+    let (_, image) = vault::open(&storage, "img").unwrap().unwrap();
+    assert_eq!(image.as_deref(), Some(&png[..]));
+
+    vault::remove_from_vault(&storage, "img").unwrap();
+    assert_eq!(find(&storage, "img").unwrap().image_path.as_deref(), Some(plain.as_str()));
+    assert_eq!(std::fs::read(storage.images_dir().join(&plain)).unwrap(), png);
+}
+
+#[test]
+fn vault_file_lists_labels_and_sources_are_sealed() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        let mut item: Item = serde_json::from_value(serde_json::json!({
+            "id": "f",
+            "kind": "files",
+            "contentText": "/home/me/taxes-2026.pdf",
+            "files": [{"uri": "file:///home/me/taxes-2026.pdf", "size": 5}],
+            "category": "file",
+            "timestamp": "2026-10-01T00:00:00Z",
+            "pinned": false,
+        }))
+        .unwrap();
+        item.label = "label-secret".into();
+        item.source_app = Some("source-secret".into());
+        add(&storage, item);
+        vault::set_pin(&storage, "2468", false).unwrap();
+        vault::move_to_vault(&storage, "f").unwrap();
+
+        let sealed = find(&storage, "f").unwrap();
+        assert!(sealed.files.is_empty() && sealed.label.is_empty() && sealed.source_app.is_none());
+    }
+    assert!(!on_disk(dir.path(), b"taxes-2026"));
+    assert!(!on_disk(dir.path(), b"label-secret"));
+    assert!(!on_disk(dir.path(), b"source-secret"));
+
+    let storage = Storage::new(dir.path());
+    vault::unlock(&storage, "2468").unwrap();
+    let opened = vault::open_items(&storage).unwrap();
+    assert_eq!(opened[0].files[0].uri, "file:///home/me/taxes-2026.pdf");
+    assert_eq!(opened[0].label, "label-secret");
+    assert_eq!(opened[0].source_app.as_deref(), Some("source-secret"));
+
+    vault::remove_from_vault(&storage, "f").unwrap();
+    let item = find(&storage, "f").unwrap();
+    assert_eq!(item.files.len(), 1);
+    assert_eq!(item.label, "label-secret");
+}
+
+#[test]
+fn legacy_pins_and_flagged_items_are_migrated_on_unlock() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    "2468".hash(&mut hasher);
+    let legacy = format!("{:x}", hasher.finish());
+
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        let mut item = text_item("old", "flagged-before-encryption");
+        item.in_vault = true;
+        item.flavors = storage.store_flavors(&[flavor(TEXT_HTML, "<b>flagged-flavor</b>")]);
+        add(&storage, item);
+        add(&storage, text_item("other", "left alone"));
+        let mut data = storage.data.lock().unwrap();
+        data.settings.vault_pin_hash = legacy;
+        storage.save_settings(&data.settings);
+    }
+
+    let storage = Storage::new(dir.path());
+    assert!(vault::has_pin(&storage));
+    assert!(vault::unlock(&storage, "1357").is_err());
+    vault::unlock(&storage, "2468").unwrap();
+    // Gone as soon as the item is sealed, not at the next start
+    assert!(!on_disk(storage.flavors_dir(), b"flagged-flavor"));
+    assert!(storage.data.lock().unwrap().settings.vault_pin_hash.is_empty());
+    assert!(find(&storage, "old").unwrap().sealed.is_some());
+    assert!(find(&storage, "other").unwrap().sealed.is_none());
+    drop(storage);
+    assert!(!on_disk(dir.path(), b"flagged-before-encryption"));
+
+    // From now on the PIN unwraps the stored key
+    let storage = Storage::new(dir.path());
+    vault::unlock(&storage, "2468").unwrap();
+    let opened = vault::open_items(&storage).unwrap();
+    assert_eq!(opened.len(), 1);
+    assert_eq!(opened[0].content_text.as_deref(), Some("flagged-before-encryption"));
+}
+
+#[test]
+fn wrong_pins_back_off_across_restarts() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        vault::set_pin(&storage, "2468", false).unwrap();
+        assert!(vault::lock(&storage));
+        for _ in 0..2 {
+            assert_eq!(vault::unlock(&storage, "0000").unwrap_err(), vault::WRONG_PIN);
+            assert_eq!(vault::retry_after(&storage), 0);
+        }
+        assert_eq!(vault::unlock(&storage, "0000").unwrap_err(), vault::WRONG_PIN);
+        let wait = vault::retry_after(&storage);
+        assert!(wait > 0 && wait <= 30, "{}", wait);
+        // Even the right PIN is refused until the backoff has passed
+        assert!(vault::unlock(&storage, "2468").unwrap_err().starts_with("Too many wrong PINs"));
+        assert!(!vault::is_unlocked(&storage));
+    }
+    let storage = Storage::new(dir.path());
+    assert!(vault::retry_after(&storage) > 0);
+    assert!(vault::unlock(&storage, "2468").is_err());
+}
+
+#[test]
+fn idle_sessions_are_closed() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path());
+    add(&storage, text_item("a", "idle"));
+    storage.data.lock().unwrap().settings.vault_timeout = 1;
+    vault::set_pin(&storage, "2468", false).unwrap();
+    vault::move_to_vault(&storage, "a").unwrap();
+    assert!(vault::is_unlocked(&storage));
+
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    assert!(!vault::is_unlocked(&storage));
+    assert_eq!(vault::open_items(&storage).unwrap_err(), vault::LOCKED);
+    assert_eq!(vault::remove_from_vault(&storage, "a").unwrap_err(), vault::LOCKED);
+    assert!(!vault::lock(&storage), "the idle session was already dropped");
+}
+
+#[test]
+fn changing_the_pin_keeps_vault_items() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        add(&storage, text_item("a", "kept"));
+        vault::set_pin(&storage, "2468", false).unwrap();
+        vault::move_to_vault(&storage, "a").unwrap();
+        let sealed = find(&storage, "a").unwrap().sealed;
+
+        assert_eq!(vault::change_pin(&storage, "0000", "1357").unwrap_err(), vault::WRONG_PIN);
+        assert!(vault::change_pin(&storage, "2468", "13").is_err(), "too short");
+        vault::change_pin(&storage, "2468", "1357").unwrap();
+        // Only the key is re-wrapped
+        assert_eq!(find(&storage, "a").unwrap().sealed, sealed);
+    }
+    let storage = Storage::new(dir.path());
+    assert_eq!(vault::unlock(&storage, "2468").unwrap_err(), vault::WRONG_PIN);
+    vault::unlock(&storage, "1357").unwrap();
+    assert_eq!(vault::open_items(&storage).unwrap()[0].content_text.as_deref(), Some("kept"));
+    assert_eq!(vault::retry_after(&storage), 0, "a right PIN resets the count");
+}
+
+#[test]
+fn recovery_keys_reset_the_pin_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path());
+    add(&storage, text_item("a", "recovered"));
+    assert_eq!(vault::set_pin(&storage, "2468", false).unwrap(), None);
+    assert!(!vault::has_recovery_key(&storage));
+    assert_eq!(
+        vault::reset_pin(&storage, "0000-0000", "1357").unwrap_err(),
+        vault::WRONG_RECOVERY_KEY
+    );
+
+    let code = vault::new_recovery_key(&storage).unwrap();
+    assert_eq!(code.len(), 39, "{}", code);
+    assert!(vault::has_recovery_key(&storage));
+    vault::move_to_vault(&storage, "a").unwrap();
+    vault::lock(&storage);
+    assert!(vault::new_recovery_key(&storage).is_err(), "needs an open session");
+
+    // Typed loosely: lower case, no dashes
+    let typed = code.replace('-', " ").to_lowercase();
+    vault::reset_pin(&storage, &typed, "1357").unwrap();
+    assert!(vault::is_unlocked(&storage));
+    assert_eq!(vault::open_items(&storage).unwrap()[0].content_text.as_deref(), Some("recovered"));
+    assert!(!vault::has_recovery_key(&storage));
+
+    vault::lock(&storage);
+    assert_eq!(vault::reset_pin(&storage, &code, "9999").unwrap_err(), vault::WRONG_RECOVERY_KEY);
+    assert!(vault::unlock(&storage, "2468").is_err());
+    vault::unlock(&storage, "1357").unwrap();
+}
+
+#[test]
+fn recovery_keys_can_be_created_at_setup() {
+    let dir = tempfile::tempdir().unwrap();
+    let code = {
+        let storage = Storage::new(dir.path());
+        vault::set_pin(&storage, "2468", true).unwrap().unwrap()
+    };
+    assert!(!on_disk(dir.path(), code.as_bytes()));
+    let storage = Storage::new(dir.path());
+    assert!(vault::has_recovery_key(&storage));
+    vault::reset_pin(&storage, &code, "1357").unwrap();
+    vault::lock(&storage);
+    vault::unlock(&storage, "1357").unwrap();
+}
+
+#[test]
+fn vault_items_are_kept_apart_from_the_history() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        for (id, text) in [("a", "apart"), ("b", "history")] {
+            let mut item = text_item(id, text);
+            item.label = "shared".into();
+            add(&storage, item);
+        }
+        vault::set_pin(&storage, "2468", false).unwrap();
+        vault::move_to_vault(&storage, "a").unwrap();
+        assert!(!in_history(&storage, "a"));
+        assert!(storage.search.lock().unwrap().search("shared").contains_key("b"));
+        assert!(!storage.search.lock().unwrap().search("shared").contains_key("a"));
+    }
+
+    // Reloaded into its partition
+    let storage = Storage::new(dir.path());
+    assert!(!in_history(&storage, "a"));
+    assert_eq!(storage.data.lock().unwrap().vault.len(), 1);
+    assert!(!storage.search.lock().unwrap().search("shared").contains_key("a"));
+
+    // Searching and deleting need the vault unlocked
+    assert_eq!(vault::search(&storage, "apart").unwrap_err(), vault::LOCKED);
+    assert_eq!(vault::clear(&storage).unwrap_err(), vault::LOCKED);
+    vault::unlock(&storage, "2468").unwrap();
+    let found = vault::search(&storage, "apart").unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].content_text.as_deref(), Some("apart"));
+    assert!(vault::search(&storage, "history").unwrap().is_empty());
+
+    // Only vault ids are deleted from the vault
+    assert_eq!(vault::delete(&storage, &["b".into()]).unwrap(), 0);
+    assert!(in_history(&storage, "b"));
+    assert_eq!(vault::clear(&storage).unwrap(), 1);
+    assert!(storage.data.lock().unwrap().vault.is_empty());
+    drop(storage);
+    let storage = Storage::new(dir.path());
+    assert!(storage.data.lock().unwrap().vault.is_empty());
+    assert!(in_history(&storage, "b"));
+}
+
+#[test]
+fn flavors_of_vault_items_are_deleted_when_sealed() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::new(dir.path());
+    let mut item = text_item("a", "hunter2-secret");
+    item.flavors = storage.store_flavors(&[flavor(TEXT_HTML, "<b>hunter2-flavor</b>"), flavor(TEXT_RTF, "shared-rtf")]);
+    add(&storage, item);
+    // Another history item copied the same RTF
+    let mut other = text_item("b", "shared");
+    other.flavors = storage.store_flavors(&[flavor(TEXT_RTF, "shared-rtf")]);
+    add(&storage, other);
+
+    vault::set_pin(&storage, "2468", false).unwrap();
+    vault::move_to_vault(&storage, "a").unwrap();
+    assert!(find(&storage, "a").unwrap().flavors.is_empty());
+    assert!(!on_disk(storage.flavors_dir(), b"hunter2-flavor"));
+    assert_eq!(storage.load_flavors(&find(&storage, "b").unwrap().flavors)[0].data, b"shared-rtf");
+}
+
+#[test]
+fn vault_fields_in_queries_are_refused() {
+    for query in ["vault:true", "in_vault:false", "note -vault:yes"] {
+        let err = app_lib::query::parse(query).unwrap_err();
+        assert!(err.message.contains("Vault items"), "{query:?}: {}", err.message);
+    }
+}
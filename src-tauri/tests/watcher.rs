@@ -0,0 +1,937 @@
+use app_lib::clipboard_backend::{
+    ClipboardBackend, ClipboardImage, Flavor, MemoryClipboard, MemoryContents, TEXT_HTML, TEXT_PLAIN,
+    TEXT_RTF,
+};
+use app_lib::clipboard_watcher::ClipboardWatcher;
+use app_lib::models::{ConcealedMode, ItemKind, Tab, TabRule, LINKS_QUERY, MASK};
+use app_lib::query::TabFilter;
+use app_lib::storage::Storage;
+use std::sync::{Arc, Mutex};
+
+struct Harness {
+    _dir: tempfile::TempDir,
+    storage: Arc<Storage>,
+    clipboard: MemoryClipboard,
+    watcher: ClipboardWatcher,
+    emitted: Arc<Mutex<Vec<String>>>,
+}
+
+fn harness() -> Harness {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(Storage::new(dir.path()));
+    let clipboard = MemoryClipboard::new();
+    let emitted = Arc::new(Mutex::new(Vec::new()));
+    let sink = emitted.clone();
+    let watcher = ClipboardWatcher::new(storage.clone(), Box::new(clipboard.clone()), move |item| {
+        sink.lock().unwrap().push(item.id.clone())
+    });
+    Harness {
+        _dir: dir,
+        storage,
+        clipboard,
+        watcher,
+        emitted,
+    }
+}
+
+fn copy_text(clipboard: &MemoryClipboard, text: &str) {
+    clipboard.clone().set_text(text).unwrap();
+}
+
+fn texts(storage: &Storage) -> Vec<String> {
+    let data = storage.data.lock().unwrap();
+    data.items.iter().map(|i| i.get_text().to_string()).collect()
+}
+
+#[test]
+fn ignores_content_present_at_startup() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(Storage::new(dir.path()));
+    let clipboard = MemoryClipboard::new();
+    copy_text(&clipboard, "already there");
+
+    let mut watcher = ClipboardWatcher::new(storage.clone(), Box::new(clipboard), |_| {});
+    watcher.check();
+
+    assert!(texts(&storage).is_empty());
+}
+
+#[test]
+fn captures_new_text() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "hello world");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items.len(), 1);
+    let item = &data.items[0];
+    assert_eq!(item.kind, ItemKind::Text);
+    assert_eq!(item.content_text.as_deref(), Some("hello world"));
+    assert_eq!(item.category, "text");
+    assert_eq!(*h.emitted.lock().unwrap(), vec![item.id.clone()]);
+}
+
+#[test]
+fn unchanged_clipboard_is_recorded_once() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "same");
+    h.watcher.check();
+    h.watcher.check();
+    h.watcher.check();
+
+    assert_eq!(texts(&h.storage), vec!["same"]);
+    assert_eq!(h.emitted.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn links_go_to_links_tab() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "https://example.com/page");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].category, "link");
+    let links = data.tabs.iter().find(|t| t.id == "links").unwrap();
+    assert!(TabFilter::new(links, &h.storage.search.lock().unwrap()).matches(&data.items[0]));
+}
+
+#[test]
+fn captures_html_alongside_text() {
+    let mut h = harness();
+    h.clipboard
+        .clone()
+        .set_html("<b>bold</b>", "bold")
+        .unwrap();
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].content_text.as_deref(), Some("bold"));
+    assert_eq!(data.items[0].content_html.as_deref(), Some("<b>bold</b>"));
+}
+
+#[test]
+fn captures_rtf_from_backends_without_flavors() {
+    let mut h = harness();
+    h.clipboard.replace(MemoryContents {
+        text: Some("styled".into()),
+        rtf: Some("{\\rtf1 {\\b styled}}".into()),
+        ..Default::default()
+    });
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].content_rtf.as_deref(), Some("{\\rtf1 {\\b styled}}"));
+    assert_eq!(data.items[0].content_html, None);
+}
+
+#[test]
+fn set_rich_keeps_every_format() {
+    let mut clipboard = MemoryClipboard::new();
+    clipboard
+        .set_rich("plain", Some("<b>plain</b>"), Some("{\\rtf1 plain}"))
+        .unwrap();
+
+    let contents = clipboard.contents();
+    assert_eq!(contents.text.as_deref(), Some("plain"));
+    assert_eq!(contents.html.as_deref(), Some("<b>plain</b>"));
+    assert_eq!(contents.rtf.as_deref(), Some("{\\rtf1 plain}"));
+}
+
+#[test]
+fn decodes_utf16_html_flavors() {
+    let mut data = vec![0xFF, 0xFE];
+    data.extend("<b>hi</b>\0".encode_utf16().flat_map(|u| u.to_le_bytes()));
+    assert_eq!(app_lib::clipboard_backend::decode_text(&data), "<b>hi</b>");
+    assert_eq!(app_lib::clipboard_backend::decode_text(b"<b>hi</b>\0"), "<b>hi</b>");
+}
+
+fn copy_from(clipboard: &MemoryClipboard, app: &str, text: &str) {
+    clipboard.replace(MemoryContents {
+        text: Some(text.into()),
+        source_app: Some(app.into()),
+        ..Default::default()
+    });
+}
+
+#[test]
+fn records_source_app() {
+    let mut h = harness();
+    copy_from(&h.clipboard, "Firefox", "from the browser");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].source_app.as_deref(), Some("Firefox"));
+}
+
+#[test]
+fn excluded_apps_are_ignored() {
+    let mut h = harness();
+    copy_from(&h.clipboard, "KeePassXC", "hunter2");
+    h.watcher.check();
+    h.storage.data.lock().unwrap().settings.excluded_apps.push("bank.exe".into());
+    copy_from(&h.clipboard, "Bank", "account 123");
+    h.watcher.check();
+    copy_from(&h.clipboard, "Editor", "notes");
+    h.watcher.check();
+
+    assert_eq!(texts(&h.storage), vec!["notes"]);
+}
+
+#[test]
+fn allow_list_skips_other_and_unknown_apps() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.allowed_apps = vec!["code".into()];
+    copy_from(&h.clipboard, "Slack", "chat");
+    h.watcher.check();
+    copy_text(&h.clipboard, "no idea where from");
+    h.watcher.check();
+    copy_from(&h.clipboard, "Code", "fn main() {}");
+    h.watcher.check();
+
+    assert_eq!(texts(&h.storage), vec!["fn main() {}"]);
+}
+
+#[test]
+fn app_query_filters_by_source() {
+    let mut h = harness();
+    copy_from(&h.clipboard, "Firefox", "a link");
+    h.watcher.check();
+    copy_from(&h.clipboard, "Terminal", "ls -la");
+    h.watcher.check();
+
+    let expr = app_lib::query::parse("app:fire").unwrap();
+    let matcher = app_lib::query::Matcher::new(expr, &h.storage.search.lock().unwrap());
+    let data = h.storage.data.lock().unwrap();
+    let found: Vec<&str> = data.items.iter().filter(|i| matcher.matches(i)).map(|i| i.get_text()).collect();
+    assert_eq!(found, vec!["a link"]);
+}
+
+fn copy_concealed(clipboard: &MemoryClipboard, text: &str, marker: &str) {
+    clipboard.replace(MemoryContents {
+        text: Some(text.into()),
+        flavors: vec![flavor(TEXT_PLAIN, text), flavor(marker, "secret")],
+        ..Default::default()
+    });
+}
+
+#[test]
+fn concealed_copies_are_skipped_by_default() {
+    let mut h = harness();
+    copy_concealed(&h.clipboard, "hunter2", "org.nspasteboard.ConcealedType");
+    h.watcher.check();
+    copy_concealed(&h.clipboard, "s3cret", "ExcludeClipboardContentFromMonitorProcessing");
+    h.watcher.check();
+    copy_text(&h.clipboard, "visible");
+    h.watcher.check();
+
+    assert_eq!(texts(&h.storage), vec!["visible"]);
+}
+
+#[test]
+fn concealed_copies_can_be_kept_masked_in_memory() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.concealed_clipboard = ConcealedMode::Mask;
+    copy_concealed(&h.clipboard, "hunter2", "x-kde-passwordManagerHint");
+    h.watcher.check();
+
+    {
+        let data = h.storage.data.lock().unwrap();
+        let item = &data.items[0];
+        assert!(item.sensitive);
+        assert!(item.expires_at.is_some());
+        assert!(item.flavors.is_empty());
+        assert_eq!(item.get_text(), "hunter2");
+        assert_eq!(item.masked().get_text(), MASK);
+    }
+
+    // Not searchable and never written to disk
+    let expr = app_lib::query::parse("hunter2").unwrap();
+    let matcher = app_lib::query::Matcher::new(expr, &h.storage.search.lock().unwrap());
+    assert!(!h.storage.data.lock().unwrap().items.iter().any(|i| matcher.matches(i)));
+    let reopened = Storage::new(h._dir.path());
+    assert!(texts(&reopened).is_empty());
+}
+
+#[test]
+fn detected_secrets_are_masked_and_not_persisted() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "4111 1111 1111 1111");
+    h.watcher.check();
+    copy_text(&h.clipboard, "just text");
+    h.watcher.check();
+
+    {
+        let data = h.storage.data.lock().unwrap();
+        let card = &data.items[1];
+        assert!(card.sensitive);
+        assert_eq!(card.category, "sensitive");
+        assert!(card.expires_at.is_some());
+    }
+    let reopened = Storage::new(h._dir.path());
+    assert_eq!(texts(&reopened), vec!["just text"]);
+
+    // Detection can be turned off
+    h.storage.data.lock().unwrap().settings.detect_sensitive = false;
+    copy_text(&h.clipboard, "5500 0000 0000 0004");
+    h.watcher.check();
+    assert!(!h.storage.data.lock().unwrap().items[0].sensitive);
+}
+
+#[test]
+fn expired_items_are_removed() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.concealed_clipboard = ConcealedMode::Mask;
+    copy_concealed(&h.clipboard, "hunter2", "org.nspasteboard.TransientType");
+    h.watcher.check();
+    copy_text(&h.clipboard, "kept");
+    h.watcher.check();
+    assert!(h.storage.expire_items().is_empty());
+
+    let id = {
+        let mut data = h.storage.data.lock().unwrap();
+        let item = data.items.iter_mut().find(|i| i.sensitive).unwrap();
+        item.expires_at = Some((chrono::Utc::now() - chrono::Duration::seconds(1)).to_rfc3339());
+        item.id.clone()
+    };
+    assert_eq!(h.storage.expire_items(), vec![id]);
+    assert_eq!(texts(&h.storage), vec!["kept"]);
+}
+
+#[test]
+fn classifier_tags_are_stored_and_queryable() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "fn main() {\n    let mut n = 1;\n    println!(\"{}\", n);\n}");
+    h.watcher.check();
+    copy_text(&h.clipboard, "#ff8800");
+    h.watcher.check();
+
+    let expr = app_lib::query::parse("tag:rust").unwrap();
+    let matcher = app_lib::query::Matcher::new(expr, &h.storage.search.lock().unwrap());
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].category, "color");
+    assert_eq!(data.items[1].category, "code");
+    assert_eq!(data.items[1].tags, vec!["rust"]);
+    let found: Vec<&str> = data.items.iter().filter(|i| matcher.matches(i)).map(|i| i.category.as_str()).collect();
+    assert_eq!(found, vec!["code"]);
+}
+
+#[test]
+fn tab_rules_file_new_items() {
+    let mut h = harness();
+    {
+        let mut data = h.storage.data.lock().unwrap();
+        let important = data.tabs.iter_mut().find(|t| t.id == "important").unwrap();
+        important.rules = vec![
+            TabRule::TextMatches { pattern: r"^TICKET-\d+".into() },
+            TabRule::SourceApp { app: "slack.exe".into() },
+            TabRule::LongerThan { chars: 40 },
+        ];
+        data.tabs.push(Tab {
+            rules: vec![TabRule::Category { category: "link".into() }],
+            ..Tab::new("tab_reading", "Reading", "📚", false)
+        });
+    }
+    copy_text(&h.clipboard, "TICKET-42 login fails");
+    h.watcher.check();
+    copy_from(&h.clipboard, "Slack", "TICKET-7 https://example.com/issue");
+    h.watcher.check();
+    copy_text(&h.clipboard, "https://example.com/docs");
+    h.watcher.check();
+    copy_text(&h.clipboard, "just a note");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    let tabs: Vec<&[String]> = data.items.iter().rev().map(|i| i.tab_ids.as_slice()).collect();
+    assert_eq!(tabs[0], ["important"]);
+    assert_eq!(tabs[1], ["important", "tab_reading"]);
+    assert_eq!(tabs[2], ["tab_reading"]);
+    assert!(tabs[3].is_empty());
+}
+
+#[test]
+fn saved_tab_rules_apply_to_later_captures() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "TICKET-1 before the rule");
+    h.watcher.check();
+    {
+        let mut data = h.storage.data.lock().unwrap();
+        let important = data.tabs.iter_mut().find(|t| t.id == "important").unwrap();
+        important.rules = vec![TabRule::TextMatches { pattern: r"^TICKET-\d+".into() }];
+        h.storage.save_tabs(&data.tabs);
+    }
+    copy_text(&h.clipboard, "TICKET-2 after the rule");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items[0].tab_ids, ["important"]);
+    assert!(data.items[1].tab_ids.is_empty());
+}
+
+#[test]
+fn smart_tabs_show_items_matching_their_query() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "https://example.com/docs");
+    h.watcher.check();
+    copy_text(&h.clipboard, "someone@example.com");
+    h.watcher.check();
+    copy_text(&h.clipboard, "just a note");
+    h.watcher.check();
+    {
+        let mut data = h.storage.data.lock().unwrap();
+        // Moved there by hand
+        data.items[0].tab_ids = vec!["links".into()];
+    }
+
+    let data = h.storage.data.lock().unwrap();
+    let index = h.storage.search.lock().unwrap();
+    let shown = |tab: &Tab| {
+        let filter = TabFilter::new(tab, &index);
+        data.items.iter().rev().filter(|i| filter.matches(i)).map(|i| i.get_text()).collect::<Vec<_>>()
+    };
+    let links = data.tabs.iter().find(|t| t.id == "links").unwrap();
+    assert_eq!(shown(links), vec!["https://example.com/docs", "just a note"]);
+    let recent_mail = Tab {
+        query: Some("category:email after:this-week on:today".into()),
+        ..Tab::new("tab_mail", "Mail", "📧", false)
+    };
+    assert_eq!(shown(&recent_mail), vec!["someone@example.com"]);
+    let old_mail = Tab { query: Some("category:email before:7d".into()), ..recent_mail };
+    assert!(shown(&old_mail).is_empty());
+}
+
+#[test]
+fn links_tab_query_is_seeded_only_once() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::new(dir.path());
+        let mut data = storage.data.lock().unwrap();
+        let links = data.tabs.iter_mut().find(|t| t.id == "links").unwrap();
+        assert_eq!(links.query.as_deref(), Some(LINKS_QUERY));
+        links.query = None;
+        storage.save_tabs(&data.tabs);
+    }
+    // Clearing the query sticks across restarts
+    let storage = Storage::new(dir.path());
+    let data = storage.data.lock().unwrap();
+    assert!(data.tabs.iter().all(|t| t.query.is_none()));
+}
+
+#[test]
+fn single_tab_ids_from_older_databases_are_migrated() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let conn = rusqlite::Connection::open(dir.path().join("copas.db")).unwrap();
+        conn.execute_batch(
+            r#"CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE items (id TEXT PRIMARY KEY, kind TEXT NOT NULL, category TEXT NOT NULL, tab_id TEXT,
+                timestamp TEXT NOT NULL, pinned INTEGER NOT NULL DEFAULT 0, in_vault INTEGER NOT NULL DEFAULT 0,
+                data TEXT NOT NULL);
+            CREATE INDEX idx_items_tab ON items(tab_id);
+            INSERT INTO meta VALUES ('schema_version', '1');
+            INSERT INTO items VALUES ('a', 'text', 'text', 'important', '2026-09-01T00:00:00Z', 0, 0,
+                '{"id":"a","kind":"text","contentText":"filed","category":"text","tabId":"important","timestamp":"2026-09-01T00:00:00Z","pinned":false}');
+            INSERT INTO items VALUES ('b', 'text', 'text', NULL, '2026-09-02T00:00:00Z', 0, 0,
+                '{"id":"b","kind":"text","contentText":"loose","category":"text","timestamp":"2026-09-02T00:00:00Z","pinned":false}');"#,
+        )
+        .unwrap();
+    }
+    {
+        let storage = Storage::new(dir.path());
+        let data = storage.data.lock().unwrap();
+        let a = data.items.iter().find(|i| i.id == "a").unwrap();
+        assert_eq!(a.tab_ids, vec!["important"]);
+        assert!(data.items.iter().find(|i| i.id == "b").unwrap().tab_ids.is_empty());
+    }
+
+    let conn = rusqlite::Connection::open(dir.path().join("copas.db")).unwrap();
+    let version: String = conn.query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |r| r.get(0)).unwrap();
+    assert_eq!(version, "3");
+    drop(conn);
+    // Membership is kept with the item, so it survives another restart
+    let storage = Storage::new(dir.path());
+    let data = storage.data.lock().unwrap();
+    assert_eq!(data.items.iter().find(|i| i.id == "a").unwrap().tab_ids, vec!["important"]);
+}
+
+#[test]
+fn item_tabs_table_from_schema_2_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let conn = rusqlite::Connection::open(dir.path().join("copas.db")).unwrap();
+        conn.execute_batch(
+            r#"CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE items (id TEXT PRIMARY KEY, kind TEXT NOT NULL, category TEXT NOT NULL,
+                timestamp TEXT NOT NULL, pinned INTEGER NOT NULL DEFAULT 0, in_vault INTEGER NOT NULL DEFAULT 0,
+                data TEXT NOT NULL);
+            CREATE TABLE item_tabs (item_id TEXT NOT NULL, tab_id TEXT NOT NULL, PRIMARY KEY (item_id, tab_id));
+            INSERT INTO meta VALUES ('schema_version', '2');
+            INSERT INTO items VALUES ('a', 'text', 'text', '2026-09-01T00:00:00Z', 0, 0,
+                '{"id":"a","kind":"text","contentText":"filed","category":"text","tabIds":["important"],"timestamp":"2026-09-01T00:00:00Z","pinned":false}');
+            INSERT INTO item_tabs VALUES ('a', 'important');"#,
+        )
+        .unwrap();
+    }
+    {
+        let storage = Storage::new(dir.path());
+        let data = storage.data.lock().unwrap();
+        assert_eq!(data.items[0].tab_ids, vec!["important"]);
+    }
+
+    let conn = rusqlite::Connection::open(dir.path().join("copas.db")).unwrap();
+    let tables: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = 'item_tabs'", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(tables, 0);
+}
+
+#[test]
+fn copying_existing_text_bumps_it_instead_of_duplicating() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "first");
+    h.watcher.check();
+    copy_text(&h.clipboard, "second");
+    h.watcher.check();
+
+    let first_ts = {
+        let data = h.storage.data.lock().unwrap();
+        data.items.iter().find(|i| i.get_text() == "first").unwrap().timestamp.clone()
+    };
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    copy_text(&h.clipboard, "first");
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items.len(), 2);
+    let first = data.items.iter().find(|i| i.get_text() == "first").unwrap();
+    assert!(first.timestamp > first_ts);
+    assert_eq!(h.emitted.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn history_is_trimmed_to_max_keeping_pinned() {
+    let mut h = harness();
+    {
+        let mut data = h.storage.data.lock().unwrap();
+        data.settings.max_history = 3;
+    }
+
+    copy_text(&h.clipboard, "pinned");
+    h.watcher.check();
+    {
+        let mut data = h.storage.data.lock().unwrap();
+        data.items[0].pinned = true;
+    }
+
+    for n in 0..5 {
+        copy_text(&h.clipboard, &format!("item {}", n));
+        h.watcher.check();
+    }
+
+    let kept = texts(&h.storage);
+    assert_eq!(kept, vec!["item 4", "item 3", "item 2", "pinned"]);
+}
+
+#[test]
+fn captures_images_to_disk() {
+    let mut h = harness();
+    let image = ClipboardImage {
+        width: 2,
+        height: 2,
+        bytes: vec![255; 16],
+    };
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items.len(), 1);
+    let item = &data.items[0];
+    assert_eq!(item.kind, ItemKind::Image);
+    assert_eq!(item.mime.as_deref(), Some("image/png"));
+    let path = h.storage.images_dir().join(item.image_path.as_ref().unwrap());
+    assert!(path.exists());
+}
+
+#[test]
+fn image_items_get_metadata_and_thumbnail() {
+    let mut h = harness();
+    let image = ClipboardImage {
+        width: 640,
+        height: 100,
+        bytes: vec![128; 640 * 100 * 4],
+    };
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+
+    let item = h.storage.data.lock().unwrap().items[0].clone();
+    let name = item.image_path.unwrap();
+    assert_eq!((item.image_width, item.image_height), (Some(640), Some(100)));
+    let size = std::fs::metadata(h.storage.images_dir().join(&name)).unwrap().len();
+    assert_eq!(item.image_size, Some(size));
+
+    let thumb = h.storage.thumbs_dir().join(&name);
+    assert_eq!(image::image_dimensions(&thumb).unwrap(), (320, 50));
+}
+
+#[test]
+fn missing_thumbnails_are_backfilled() {
+    let h = harness();
+    let legacy = h.storage.images_dir().join("1700000000000abcdef12.png");
+    image::RgbaImage::new(40, 1000).save(&legacy).unwrap();
+
+    let thumb = h.storage.ensure_thumbnail("1700000000000abcdef12.png").unwrap();
+    assert_eq!(image::image_dimensions(&thumb).unwrap(), (12, 320));
+    let (width, height, size) = h.storage.image_info("1700000000000abcdef12.png").unwrap();
+    assert_eq!((width, height), (40, 1000));
+    assert_eq!(size, std::fs::metadata(&legacy).unwrap().len());
+
+    // Nothing refers to it, so GC takes the thumbnail too
+    h.storage.collect_garbage();
+    assert!(!thumb.exists());
+}
+
+fn gradient(width: usize, height: usize, shade: u8) -> ClipboardImage {
+    let mut bytes = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let v = ((x * 255 / width) as u8 / 2).saturating_add((y * 100 / height) as u8).saturating_add(shade);
+            bytes.extend_from_slice(&[v, 255 - v, v / 2, 255]);
+        }
+    }
+    ClipboardImage { width, height, bytes }
+}
+
+#[test]
+fn rescaled_image_bumps_existing_item() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.bump_similar_images = true;
+    h.clipboard.clone().set_image(&gradient(120, 80, 0)).unwrap();
+    h.watcher.check();
+    let (id, first_ts) = {
+        let data = h.storage.data.lock().unwrap();
+        (data.items[0].id.clone(), data.items[0].timestamp.clone())
+    };
+    copy_text(&h.clipboard, "between");
+    h.watcher.check();
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    // Same picture at a different scale with slightly shifted colours
+    h.clipboard.clone().set_image(&gradient(60, 40, 3)).unwrap();
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    let images: Vec<_> = data.items.iter().filter(|i| i.kind == ItemKind::Image).collect();
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].id, id);
+    assert!(images[0].timestamp > first_ts);
+    assert_eq!(h.emitted.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn near_duplicate_images_are_recorded_by_default() {
+    let mut h = harness();
+    h.clipboard.clone().set_image(&gradient(120, 80, 0)).unwrap();
+    h.watcher.check();
+    copy_text(&h.clipboard, "between");
+    h.watcher.check();
+    h.clipboard.clone().set_image(&gradient(60, 40, 3)).unwrap();
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items.iter().filter(|i| i.kind == ItemKind::Image).count(), 2);
+}
+
+#[test]
+fn different_images_are_kept_apart() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.bump_similar_images = true;
+    h.clipboard.clone().set_image(&gradient(64, 64, 0)).unwrap();
+    h.watcher.check();
+
+    // Mirrored, so every horizontal gradient flips
+    let mut mirrored = gradient(64, 64, 0);
+    for row in mirrored.bytes.chunks_exact_mut(64 * 4) {
+        let pixels: Vec<[u8; 4]> = row.chunks_exact(4).rev().map(|p| [p[0], p[1], p[2], p[3]]).collect();
+        row.copy_from_slice(&pixels.concat());
+    }
+    h.clipboard.clone().set_image(&mirrored).unwrap();
+    h.watcher.check();
+
+    assert_eq!(h.storage.data.lock().unwrap().items.len(), 2);
+}
+
+#[test]
+fn dhash_is_stable_and_round_trips_as_hex() {
+    let a = image::RgbaImage::from_raw(120, 80, gradient(120, 80, 0).bytes).unwrap();
+    let b = image::RgbaImage::from_raw(120, 80, gradient(120, 80, 0).bytes).unwrap();
+    assert_eq!(app_lib::image_hash::distance(app_lib::image_hash::dhash(&a), app_lib::image_hash::dhash(&b)), 0);
+    let hex = app_lib::image_hash::to_hex(u64::MAX - 1);
+    assert_eq!(app_lib::image_hash::from_hex(&hex), Some(u64::MAX - 1));
+}
+
+fn image_names(storage: &Storage) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(storage.images_dir())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn identical_images_share_one_file_until_gc() {
+    let mut h = harness();
+    let image = ClipboardImage {
+        width: 1,
+        height: 1,
+        bytes: vec![10, 20, 30, 255],
+    };
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+    copy_text(&h.clipboard, "between");
+    h.watcher.check();
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+
+    let ids: Vec<String> = {
+        let data = h.storage.data.lock().unwrap();
+        let images: Vec<_> = data.items.iter().filter(|i| i.kind == ItemKind::Image).collect();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].image_path, images[1].image_path);
+        assert_eq!(images[0].image_path.as_ref().unwrap().len(), 64 + ".png".len());
+        images.iter().map(|i| i.id.clone()).collect()
+    };
+    // A leftover from before content addressing
+    std::fs::write(h.storage.images_dir().join("1700000000000abcdef12.png"), b"old").unwrap();
+    assert_eq!(image_names(&h.storage).len(), 2);
+
+    // Still used by the other item
+    h.storage.data.lock().unwrap().items.retain(|i| i.id != ids[0]);
+    h.storage.delete_items(&ids[..1]);
+    assert_eq!(h.storage.collect_garbage(), 3);
+    assert_eq!(image_names(&h.storage).len(), 1);
+
+    h.storage.data.lock().unwrap().items.retain(|i| i.id != ids[1]);
+    h.storage.delete_items(&ids[1..]);
+    let freed = h.storage.collect_garbage();
+    assert!(freed > 0);
+    assert!(image_names(&h.storage).is_empty());
+    assert_eq!(h.storage.reclaimed_bytes(), freed + 3);
+}
+
+#[test]
+fn trimmed_images_are_collected() {
+    let mut h = harness();
+    h.storage.data.lock().unwrap().settings.max_history = 1;
+    let image = ClipboardImage {
+        width: 1,
+        height: 1,
+        bytes: vec![1, 2, 3, 255],
+    };
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+    copy_text(&h.clipboard, "newer");
+    h.watcher.check();
+
+    assert!(h.storage.collect_garbage() > 0);
+    assert!(image_names(&h.storage).is_empty());
+}
+
+#[test]
+fn only_stored_images_are_served() {
+    let mut h = harness();
+    let image = ClipboardImage {
+        width: 1,
+        height: 1,
+        bytes: vec![1, 2, 3, 255],
+    };
+    h.clipboard.clone().set_image(&image).unwrap();
+    h.watcher.check();
+    let name = h.storage.data.lock().unwrap().items[0].image_path.clone().unwrap();
+    assert!(h.storage.is_stored_image(&name));
+
+    std::fs::write(h.storage.images_dir().join("unused.png"), b"old").unwrap();
+    for name in ["unused.png", "../copas.db", "..", "", "/etc/passwd", &format!("../images/{}", name)] {
+        assert!(!h.storage.is_stored_image(name), "{name:?}");
+    }
+}
+
+#[test]
+fn history_survives_reopen() {
+    let mut h = harness();
+    copy_text(&h.clipboard, "persisted");
+    h.watcher.check();
+
+    let reopened = Storage::new(h._dir.path());
+    assert_eq!(texts(&reopened), vec!["persisted"]);
+}
+
+#[test]
+fn memory_clipboard_replaces_contents() {
+    let clipboard = MemoryClipboard::new();
+    clipboard.replace(MemoryContents {
+        text: Some("text".into()),
+        html: Some("<p>text</p>".into()),
+        ..Default::default()
+    });
+    clipboard.clone().set_files(&["/tmp/a.txt".into()]).unwrap();
+
+    let contents = clipboard.contents();
+    assert!(contents.text.is_none());
+    assert!(contents.html.is_none());
+    assert_eq!(contents.files, Some(vec!["/tmp/a.txt".into()]));
+}
+
+#[test]
+fn captures_copied_files() {
+    let mut h = harness();
+    let doc = h._dir.path().join("report final.pdf");
+    std::fs::write(&doc, b"12345").unwrap();
+    let folder = h._dir.path().join("folder");
+    std::fs::create_dir(&folder).unwrap();
+
+    h.clipboard.replace(MemoryContents {
+        text: Some(format!("{}\n{}", doc.display(), folder.display())),
+        files: Some(vec![doc.clone(), folder.clone()]),
+        ..Default::default()
+    });
+    h.watcher.check();
+    h.watcher.check();
+
+    let data = h.storage.data.lock().unwrap();
+    assert_eq!(data.items.len(), 1);
+    let item = &data.items[0];
+    assert_eq!(item.kind, ItemKind::Files);
+    assert_eq!(item.category, "file");
+    assert_eq!(item.files.len(), 2);
+    assert!(item.files[0].uri.starts_with("file://"));
+    assert!(item.files[0].uri.ends_with("report%20final.pdf"));
+    assert_eq!(item.files[0].size, Some(5));
+    assert_eq!(item.files[0].mime.as_deref(), Some("application/pdf"));
+    assert_eq!(item.files[1].size, None);
+    assert_eq!(item.files[1].mime.as_deref(), Some("inode/directory"));
+    assert_eq!(app_lib::files::entry_paths(&item.files), vec![doc, folder]);
+}
+
+fn flavor(mime: &str, data: &str) -> Flavor {
+    Flavor {
+        mime: mime.into(),
+        data: data.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn keeps_every_flavor() {
+    let mut h = harness();
+    let flavors = vec![
+        flavor(TEXT_PLAIN, "rich"),
+        flavor(TEXT_HTML, "<i>rich</i>"),
+        flavor(TEXT_RTF, "{\\rtf1 rich}"),
+        flavor("application/x-app-specific", "\u{1}\u{2}"),
+    ];
+    h.clipboard.clone().set_flavors(&flavors).unwrap();
+    h.watcher.check();
+
+    let item = h.storage.data.lock().unwrap().items[0].clone();
+    assert_eq!(item.content_text.as_deref(), Some("rich"));
+    assert_eq!(item.content_html.as_deref(), Some("<i>rich</i>"));
+    assert_eq!(item.content_rtf.as_deref(), Some("{\\rtf1 rich}"));
+    assert_eq!(item.flavors.len(), 4);
+
+    let mut restored = h.storage.load_flavors(&item.flavors);
+    restored.sort_by(|a, b| a.mime.cmp(&b.mime));
+    let mut expected = flavors.clone();
+    expected.sort_by(|a, b| a.mime.cmp(&b.mime));
+    assert_eq!(restored, expected);
+
+    // Restoring puts the same flavors back on the clipboard
+    let mut other = MemoryClipboard::new();
+    other.set_flavors(&restored).unwrap();
+    assert_eq!(other.get_html().as_deref(), Some("<i>rich</i>"));
+    assert_eq!(other.contents().flavors.len(), 4);
+}
+
+#[test]
+fn unreferenced_flavor_blobs_are_swept_on_open() {
+    let mut h = harness();
+    h.clipboard
+        .clone()
+        .set_flavors(&[flavor(TEXT_PLAIN, "kept"), flavor("text/rtf", "{\\rtf1 kept}")])
+        .unwrap();
+    h.watcher.check();
+    let orphan = h.storage.flavors_dir().join("orphan");
+    std::fs::write(&orphan, b"stale").unwrap();
+
+    let reopened = Storage::new(h._dir.path());
+    let item = reopened.data.lock().unwrap().items[0].clone();
+    assert_eq!(reopened.load_flavors(&item.flavors).len(), 2);
+    assert!(!orphan.exists());
+}
+
+/// Make every flavor blob look `minutes` older
+fn age_flavor_blobs(storage: &Storage, minutes: u64) {
+    let then = std::time::SystemTime::now() - std::time::Duration::from_secs(minutes * 60);
+    for entry in std::fs::read_dir(storage.flavors_dir()).unwrap() {
+        let file = std::fs::File::options().append(true).open(entry.unwrap().path()).unwrap();
+        file.set_modified(then).unwrap();
+    }
+}
+
+#[test]
+fn flavor_blobs_of_deleted_items_are_collected() {
+    let mut h = harness();
+    h.clipboard
+        .clone()
+        .set_flavors(&[flavor(TEXT_PLAIN, "gone"), flavor(TEXT_RTF, "{\\rtf1 gone}")])
+        .unwrap();
+    h.watcher.check();
+    h.clipboard.clone().set_flavors(&[flavor(TEXT_PLAIN, "kept")]).unwrap();
+    h.watcher.check();
+    let (gone, kept) = {
+        let data = h.storage.data.lock().unwrap();
+        (data.items[1].clone(), data.items[0].clone())
+    };
+    let size = |name: &String| std::fs::metadata(h.storage.flavors_dir().join(name)).unwrap().len();
+    let gone_size: u64 = gone.flavors.values().map(size).sum();
+
+    h.storage.data.lock().unwrap().items.retain(|i| i.id != gone.id);
+    h.storage.delete_items(std::slice::from_ref(&gone.id));
+    // Blobs this new may belong to an item that isn't saved yet
+    assert_eq!(h.storage.collect_garbage(), 0);
+    assert_eq!(h.storage.load_flavors(&gone.flavors).len(), 2);
+
+    age_flavor_blobs(&h.storage, 5);
+    assert_eq!(h.storage.collect_garbage(), gone_size);
+    assert_eq!(h.storage.reclaimed_bytes(), gone_size);
+    assert!(h.storage.load_flavors(&gone.flavors).is_empty());
+    assert_eq!(h.storage.load_flavors(&kept.flavors).len(), 1);
+
+    // Copying the same content again keeps an old unused blob from being collected
+    h.storage.data.lock().unwrap().items.clear();
+    h.storage.delete_items(std::slice::from_ref(&kept.id));
+    age_flavor_blobs(&h.storage, 5);
+    let stored = h.storage.store_flavors(&[flavor(TEXT_PLAIN, "kept")]);
+    assert_eq!(stored, kept.flavors);
+    assert_eq!(h.storage.collect_garbage(), 0);
+    assert_eq!(h.storage.load_flavors(&stored).len(), 1);
+}
+
+#[test]
+fn primary_selection_is_opt_in() {
+    let mut h = harness();
+    h.clipboard.replace(MemoryContents {
+        primary: Some("highlighted".into()),
+        ..Default::default()
+    });
+    h.watcher.check();
+    assert!(texts(&h.storage).is_empty());
+
+    h.storage.data.lock().unwrap().settings.capture_primary_selection = true;
+    h.clipboard.replace(MemoryContents {
+        primary: Some("highlighted again".into()),
+        ..Default::default()
+    });
+    h.watcher.check();
+    h.watcher.check();
+    assert_eq!(texts(&h.storage), vec!["highlighted again"]);
+}
@@ -89,43 +89,62 @@
 
     window.copas = {
         getTabs: () => invoke('get_tabs'),
-        createTab: (data) => invoke('create_tab', { tab: data }),
-        renameTab: (data) => invoke('rename_tab', { tab: data }),
+        createTab: (data) => invoke('create_tab', { name: data.name, icon: data.icon, query: data.query || null, parentId: data.parentId || null }),
+        moveTab: (id, parentId, position) => invoke('move_tab', { id, parentId: parentId || null, position: position ?? null }),
+        renameTab: (data) => invoke('rename_tab', { id: data.id, name: data.name, icon: data.icon }),
+        setTabQuery: (id, query) => invoke('set_tab_query', { id, query: query || null }),
+        getTabCounts: () => invoke('get_tab_counts'),
         deleteTab: (id) => invoke('delete_tab', { id }),
-        getHistory: (opts) => invoke('get_history', { search: opts.search, tabId: opts.tabId, page: opts.page, pageSize: opts.pageSize }),
-        deleteItem: (id) => invoke('delete_item', { id }),
-        deleteMultiple: (ids) => invoke('delete_multiple', { ids }),
+        setTabRules: (id, rules) => invoke('set_tab_rules', { id, rules }),
+        applyTabRules: () => invoke('apply_tab_rules'),
+        getHistory: (opts) => invoke('get_history', { search: opts.search, tabId: opts.tabId, page: opts.page, pageSize: opts.pageSize, sort: opts.sort || null, sourceApp: opts.sourceApp || null, tabIds: opts.tabIds || null, tabMatch: opts.tabMatch || null }),
+        deleteItem: (id, vault) => invoke('delete_item', { id, vault }),
+        deleteMultiple: (ids, vault) => invoke('delete_multiple', { ids, vault }),
         pinItem: (id) => invoke('pin_item', { id }),
-        moveToTab: (data) => invoke('move_to_tab', { data }),
+        moveToTab: (itemId, tabId, fromTabId) => invoke('move_to_tab', { itemId, tabId, fromTabId: fromTabId || null }),
+        addToTab: (ids, tabId) => invoke('add_to_tab', { ids, tabId }),
+        removeFromTab: (ids, tabId) => invoke('remove_from_tab', { ids, tabId }),
+        setItemTabs: (ids, tabIds) => invoke('set_item_tabs', { ids, tabIds }),
         labelItem: (data) => invoke('label_item', { id: data.id, label: data.label }),
-        copyToClipboard: (content) => invoke('copy_to_clipboard', { content }),
+        copyToClipboard: (content, itemId) => invoke('copy_to_clipboard', { content, itemId: itemId || null }),
         bulkCopy: (contents) => invoke('bulk_copy', { contents }),
-        clearHistory: (tabId) => invoke('clear_history', { tabId }),
+        clearHistory: (tabId, vault) => invoke('clear_history', { tabId, vault }),
         getStats: () => invoke('get_stats'),
         getSettings: () => invoke('get_settings'),
         setSettings: (s) => invoke('set_settings', { settings: s }),
-        pasteAndHide: (content, imagePath, contentHtml) => invoke('paste_and_hide', { content, imagePath, contentHtml: contentHtml || null }),
-        bulkPasteAndHide: (contents, htmlContents, imagePaths) => invoke('bulk_paste_and_hide', { contents, htmlContents: htmlContents || null, imagePaths: imagePaths || null }),
+        pasteAndHide: (content, imagePath, contentHtml, itemId) => invoke('paste_and_hide', { content, imagePath, contentHtml: contentHtml || null, itemId: itemId || null }),
+        bulkPasteAndHide: (contents, htmlContents, imagePaths, itemIds) => invoke('bulk_paste_and_hide', { contents, htmlContents: htmlContents || null, imagePaths: imagePaths || null, itemIds: itemIds || null }),
         hidePopup: () => invoke('hide_popup'),
         showPopup: () => invoke('window_show'),
         onClipboardUpdate: (cb) => listen('clipboard-updated', (e) => cb(e.payload)),
         onHistoryCleared: (cb) => listen('history-cleared', () => cb()),
+        onItemsExpired: (cb) => listen('items-expired', (e) => cb(e.payload)),
         onPopupShown: (cb) => listen('popup-shown', () => cb()),
         onStartScreenshot: (cb) => listen('start-screenshot', () => cb()),
         checkForUpdate: () => invoke('check_for_update'),
         installUpdate: () => invoke('install_update'),
         getVersion: () => invoke('get_version'),
         getImageUrl: (filename) => invoke('get_image_url', { filename }),
+        getThumbnailUrl: (id) => invoke('get_thumbnail_url', { id }),
         captureScreen: () => invoke('capture_screen'),
         setFullscreen: (f) => invoke('window_fullscreen', { fullscreen: f }),
         copyImageToClipboard: (b64) => invoke('copy_image_to_clipboard', { base64: b64 }),
         saveScreenshotToFile: (b64, filePath) => invoke('save_screenshot_to_file', { base64Data: b64, filePath }),
-        setVaultPin: (pin) => invoke('set_vault_pin', { pin }),
+        setVaultPin: (pin, recovery) => invoke('set_vault_pin', { pin, recovery }),
         verifyVaultPin: (pin) => invoke('verify_vault_pin', { pin }),
+        changeVaultPin: (oldPin, newPin) => invoke('change_vault_pin', { oldPin, newPin }),
+        resetVaultPin: (recoveryKey, newPin) => invoke('reset_vault_pin', { recoveryKey, newPin }),
+        newVaultRecoveryKey: () => invoke('new_vault_recovery_key'),
+        lockVault: () => invoke('lock_vault'),
+        onVaultLocked: (cb) => listen('vault-locked', (e) => cb(e.payload)),
         hasVaultPin: () => invoke('has_vault_pin'),
         moveToVault: (id) => invoke('move_to_vault', { id }),
         removeFromVault: (id) => invoke('remove_from_vault', { id }),
-        getVaultItems: () => invoke('get_vault_items'),
+        getVaultItems: (search) => invoke('get_vault_items', { search }),
+        getEncryptionStatus: () => invoke('get_encryption_status'),
+        enableEncryption: (passphrase) => invoke('enable_encryption', { passphrase: passphrase || null }),
+        disableEncryption: (passphrase) => invoke('disable_encryption', { passphrase: passphrase || null }),
+        unlockDatabase: (passphrase) => invoke('unlock_database', { passphrase }),
         getMachineId: () => invoke('get_machine_id'),
         activateLicense: (key) => invoke('activate_license', { key }),
         checkLicense: () => invoke('check_license'),
@@ -137,7 +156,7 @@
         quit: () => invoke('window_quit')
     };
 
-    let tabs = [], activeTabId = 'all', allItems = [], displayItems = [];
+    let tabs = [], tabCounts = {}, activeTabId = 'all', allItems = [], displayItems = [];
     let searchQuery = '', isSelectMode = false, selectedIds = new Set(), settings = {};
     let focusedIndex = -1;
 
@@ -153,6 +172,8 @@
     async function init() {
         settings = await window.copas.getSettings();
         applyTheme(settings.theme);
+        const enc = await window.copas.getEncryptionStatus();
+        if (enc.locked) await unlockDatabase(enc);
         await loadAllItems();
         await loadTabs();
         await loadItems();
@@ -222,33 +243,58 @@
 
     // ===== TABS =====
     async function loadTabs() { tabs = await window.copas.getTabs(); renderTabs(); }
+    // Tabs arrive in display order (depth first); nesting is shown by indenting
+    function tabDepth(t) { let d = 0; for (let p = t.parentId; p && d < 10; p = tabs.find(x => x.id === p)?.parentId) d++; return d; }
     function renderTabs() {
         tabListEl.innerHTML = tabs.map(t => `
-      <div class="tab-item ${t.id === activeTabId ? 'active' : ''}" data-id="${t.id}">
-        <span class="ti">${t.icon}</span><span class="tn">${esc(t.name)}</span>
+      <div class="tab-item ${t.id === activeTabId ? 'active' : ''}" data-id="${t.id}" ${t.id !== 'all' ? 'draggable="true"' : ''} ${tabDepth(t) ? `style="padding-left:${12 + tabDepth(t) * 14}px"` : ''}>
+        <span class="ti">${t.icon}</span><span class="tn">${esc(t.name)}</span>${t.query ? `<span class="ts" title="${esc(t.query)}">🔎</span>` : ''}
         <span class="tc">${countTab(t.id)}</span>
-        ${!t.system ? `<button class="tm" data-id="${t.id}">⋯</button>` : ''}
+        ${t.id !== 'all' ? `<button class="tm" data-id="${t.id}">⋯</button>` : ''}
       </div>`).join('');
         tabListEl.querySelectorAll('.tab-item').forEach(el => {
             el.addEventListener('click', e => { if (e.target.classList.contains('tm')) return; activeTabId = el.dataset.id; renderTabs(); loadItems(); });
+            el.addEventListener('dragstart', e => { e.dataTransfer.setData('text/x-copas-tab', el.dataset.id); e.dataTransfer.effectAllowed = 'move'; });
+            el.addEventListener('dragover', e => { if (!e.dataTransfer.types.includes('text/x-copas-tab')) return; e.preventDefault(); setDropZone(el, tabDropZone(el, e)); });
+            el.addEventListener('dragleave', () => setDropZone(el, null));
+            el.addEventListener('drop', async e => { e.preventDefault(); const zone = tabDropZone(el, e); setDropZone(el, null); await dropTab(e.dataTransfer.getData('text/x-copas-tab'), el.dataset.id, zone); });
         });
         tabListEl.querySelectorAll('.tm').forEach(b => b.addEventListener('click', e => { e.stopPropagation(); showTabMenu(b.dataset.id, e); }));
     }
-    function countTab(id) {
-        if (id === 'all') return allItems.length;
-        if (id === 'links') return allItems.filter(i => i.category === 'link' || i.tabId === 'links').length;
-        return allItems.filter(i => i.tabId === id).length;
+    // Top or bottom quarter of a tab: drop next to it; middle: nest inside it
+    function tabDropZone(el, e) {
+        const t = tabs.find(x => x.id === el.dataset.id); const r = el.getBoundingClientRect(); const y = (e.clientY - r.top) / r.height;
+        if (t.id === 'all') return 'after';
+        if (t.system) return y < .5 ? 'before' : 'after';
+        return y < .25 ? 'before' : y > .75 ? 'after' : 'into';
     }
+    function setDropZone(el, zone) { el.classList.remove('drop-before', 'drop-after', 'drop-into'); if (zone) el.classList.add('drop-' + zone); }
+    async function dropTab(id, targetId, zone) {
+        if (!id || id === targetId) return;
+        const target = tabs.find(t => t.id === targetId); if (!target) return;
+        let res;
+        if (zone === 'into') res = await window.copas.moveTab(id, target.id, null);
+        else {
+            const siblings = tabs.filter(t => (t.parentId || null) === (target.parentId || null) && t.id !== id);
+            res = await window.copas.moveTab(id, target.parentId, siblings.indexOf(target) + (zone === 'after' ? 1 : 0));
+        }
+        if (!res.success) { toast(`⚠️ ${res.error || 'Không di chuyển được thẻ'}`, 'error'); return; }
+        await loadTabs(); await refresh();
+    }
+    function countTab(id) { return tabCounts[id] ?? 0; }
+    // Smart tabs are counted by the backend, which evaluates their queries
+    async function loadTabCounts() { try { tabCounts = await window.copas.getTabCounts(); } catch { } }
 
     // ===== ITEMS =====
     async function loadAllItems() {
         let items = (await window.copas.getHistory({ search: '', tabId: 'all', page: 0, pageSize: 9999 })).items;
         for (let i of items) {
             if (i.kind === 'image' || i.imagePath) {
-                i.imageUrl = await window.copas.getImageUrl(i.imagePath);
+                i.imageUrl = await window.copas.getThumbnailUrl(i.id);
             }
         }
         allItems = items;
+        await loadTabCounts();
     }
 
     async function loadItems() {
@@ -256,7 +302,7 @@
         let items = r.items;
         for (let i of items) {
             if (i.kind === 'image' || i.imagePath) {
-                i.imageUrl = await window.copas.getImageUrl(i.imagePath);
+                i.imageUrl = await window.copas.getThumbnailUrl(i.id);
             }
         }
 
@@ -269,7 +315,9 @@
         scrollEl.scrollTop = 0;
     }
 
-    const catNames = { text: 'Văn bản', link: 'Liên kết', email: 'Email', code: 'Code', phone: 'SĐT', number: 'Số' };
+    const catNames = { text: 'Văn bản', link: 'Liên kết', email: 'Email', code: 'Code', phone: 'SĐT', number: 'Số', file: 'Tệp', sensitive: 'Nhạy cảm', color: 'Màu', json: 'JSON', yaml: 'YAML', sql: 'SQL', path: 'Đường dẫn', ip: 'IP', uuid: 'UUID', date: 'Ngày', markdown: 'Markdown' };
+    // Categories shown in a monospace font
+    const monoCats = ['code', 'json', 'yaml', 'sql', 'path', 'uuid', 'ip'];
     const catIcons = {
         text: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M14 2H6a2 2 0 0 0-2 2v16a2 2 0 0 0 2 2h12a2 2 0 0 0 2-2V8z"/><path d="M14 2v6h6M16 13H8M16 17H8M10 9H8"/></svg>',
         link: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M10 13a5 5 0 0 0 7.54.54l3-3a5 5 0 0 0-7.07-7.07l-1.72 1.71"/><path d="M14 11a5 5 0 0 0-7.54-.54l-3 3a5 5 0 0 0 7.07 7.07l1.71-1.71"/></svg>',
@@ -277,7 +325,9 @@
         code: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="m16 18 6-6-6-6M8 6l-6 6 6 6"/></svg>',
         phone: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M22 16.92v3a2 2 0 0 1-2.18 2 19.79 19.79 0 0 1-8.63-3.07 19.5 19.5 0 0 1-6-6 19.79 19.79 0 0 1-3.07-8.67A2 2 0 0 1 4.11 2h3a2 2 0 0 1 2 1.72c.127.96.361 1.903.7 2.81a2 2 0 0 1-.45 2.11L8.09 9.91a16 16 0 0 0 6 6l1.27-1.27a2 2 0 0 1 2.11-.45c.907.339 1.85.573 2.81.7A2 2 0 0 1 22 16.92z"/></svg>',
         number: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><line x1="4" y1="9" x2="20" y2="9"/><line x1="4" y1="15" x2="20" y2="15"/><line x1="10" y1="3" x2="8" y2="21"/><line x1="16" y1="3" x2="14" y2="21"/></svg>',
-        image: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><rect x="3" y="3" width="18" height="18" rx="2" ry="2"/><circle cx="8.5" cy="8.5" r="1.5"/><polyline points="21 15 16 10 5 21"/></svg>'
+        image: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><rect x="3" y="3" width="18" height="18" rx="2" ry="2"/><circle cx="8.5" cy="8.5" r="1.5"/><polyline points="21 15 16 10 5 21"/></svg>',
+        file: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M22 19a2 2 0 0 1-2 2H4a2 2 0 0 1-2-2V5a2 2 0 0 1 2-2h5l2 3h9a2 2 0 0 1 2 2z"/></svg>',
+        sensitive: '<svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><rect x="3" y="11" width="18" height="11" rx="2"/><path d="M7 11V7a5 5 0 0 1 10 0v4"/></svg>'
     };
 
     function renderItems(items) {
@@ -290,11 +340,20 @@
     function cardHTML(i, idx) {
         let contentHtml = '';
         if (i.kind === 'image' && i.imageUrl) {
-            contentHtml = `<img src="${i.imageUrl}" class="card-img" alt="Copied image">`;
+            const dims = i.imageWidth ? `<div class="card-time">${i.imageWidth}×${i.imageHeight}${i.imageSize != null ? ` · ${fmtSize(i.imageSize)}` : ''}</div>` : '';
+            contentHtml = `<img src="${i.imageUrl}" class="card-img" alt="Copied image" loading="lazy">${dims}`;
+        } else if (i.kind === 'files' && i.files) {
+            const rows = i.files.map(f => {
+                const name = fileName(f.uri);
+                const n = searchQuery ? hi(esc(name), searchQuery) : esc(name);
+                return `<div>${n}${f.size != null ? ` <span class="card-time">${fmtSize(f.size)}</span>` : ''}</div>`;
+            }).join('');
+            contentHtml = `<div class="card-txt">${rows}</div>`;
         } else {
             const rawText = i.contentText || i.content || '';
             const c = searchQuery ? hi(esc(rawText), searchQuery) : esc(rawText);
-            contentHtml = `<div class="card-txt ${i.category === 'code' ? 'code' : ''}">${c}</div>`;
+            const swatch = i.category === 'color' ? `<span class="card-swatch" style="background:${esc(rawText.trim().replace(/"/g, ''))}"></span>` : '';
+            contentHtml = `<div class="card-txt ${monoCats.includes(i.category) ? 'code' : ''}">${swatch}${c}</div>`;
         }
 
         const s = selectedIds.has(i.id);
@@ -303,8 +362,9 @@
       <div class="card-body">
         <div class="card-top">
           ${i.label ? `<span class="card-label"><svg width="10" height="10" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M20.59 13.41l-7.17 7.17a2 2 0 0 1-2.83 0L2 12V2h10l8.59 8.59a2 2 0 0 1 0 2.82z"/><line x1="7" y1="7" x2="7.01" y2="7"/></svg> ${esc(i.label)}</span>` : ''}
-          <span class="card-cat ${i.category}">${catIcons[i.category] || ''} ${catNames[i.category] || 'Hình ảnh'}</span>
+          <span class="card-cat ${i.category}">${catIcons[i.category] || ''} ${catNames[i.category] || 'Hình ảnh'}${i.category === 'code' && i.tags?.length ? ` · ${esc(i.tags[0])}` : ''}</span>
           ${i.pinned ? '<span class="card-label">📌</span>' : ''}
+          ${i.sourceApp ? `<span class="card-time">${esc(i.sourceApp)}</span>` : ''}
           <span class="card-time">${timeAgo(i.timestamp)}</span>
         </div>
         ${contentHtml}
@@ -355,11 +415,11 @@
                     try {
                         if (item.kind === 'image') {
                             document.title = 'PASTE IMG: ' + (item.imagePath || '');
-                            await window.copas.pasteAndHide('', item.imagePath || '', null);
+                            await window.copas.pasteAndHide('', item.imagePath || '', null, item.id);
                         } else {
                             let text = parseSnippets(item.contentText || item.content || '');
                             document.title = 'PASTE TXT: ' + text.substring(0, 30);
-                            await window.copas.pasteAndHide(text, null, item.contentHtml || null);
+                            await window.copas.pasteAndHide(text, null, item.contentHtml || null, item.id);
                         }
                         document.title = 'PASTE OK!';
                     } catch (err) {
@@ -379,7 +439,7 @@
                 if (item) {
                     // Note: bulkCopy currently only supports text in the backend
                     if (item.kind !== 'image') {
-                        await window.copas.copyToClipboard(item.contentText || item.content || '');
+                        await window.copas.copyToClipboard(item.contentText || item.content || '', item.id);
                         toast('📋 Đã copy (không dán)', 'info');
                     } else {
                         toast('Cần dán trực tiếp đối với hình ảnh', 'warning');
@@ -394,10 +454,10 @@
                 const item = displayItems.find(i => i.id === id);
                 if (item) {
                     if (item.kind === 'image') {
-                        await window.copas.pasteAndHide('', item.imagePath, null);
+                        await window.copas.pasteAndHide('', item.imagePath, null, item.id);
                     } else {
                         let text = parseSnippets(item.contentText || item.content || '');
-                        await window.copas.pasteAndHide(text, null, item.contentHtml || null);
+                        await window.copas.pasteAndHide(text, null, item.contentHtml || null, item.id);
                     }
                 }
             });
@@ -414,6 +474,7 @@
         $('#btn-theme').addEventListener('click', toggleTheme);
         $('#btn-settings').addEventListener('click', toggleSettings);
         $('#close-settings').addEventListener('click', () => settingsPanel.style.display = 'none');
+        $('#set-encryption').addEventListener('click', toggleEncryption);
         $('#btn-guide').addEventListener('click', toggleGuide);
         $('#close-guide').addEventListener('click', () => guidePanel.style.display = 'none');
 
@@ -437,6 +498,7 @@
         });
         $('#btn-bulk-paste').addEventListener('click', bulkPaste);
         $('#btn-del-sel').addEventListener('click', deleteSel);
+        $('#btn-tab-sel').addEventListener('click', () => { if (selectedIds.size) showItemTabsDlg([...selectedIds]); });
 
         // Settings
         $('#save-settings').addEventListener('click', saveSettings);
@@ -504,10 +566,10 @@
             } else if (focusedIndex >= 0 && focusedIndex < displayItems.length) {
                 const item = displayItems[focusedIndex];
                 if (item.kind === 'image') {
-                    await window.copas.pasteAndHide('', item.imagePath || '', null);
+                    await window.copas.pasteAndHide('', item.imagePath || '', null, item.id);
                 } else {
                     let text = parseSnippets(item.contentText || item.content || '');
-                    await window.copas.pasteAndHide(text, null, item.contentHtml || null);
+                    await window.copas.pasteAndHide(text, null, item.contentHtml || null, item.id);
                 }
             }
             return;
@@ -565,6 +627,7 @@
         selCountEl.textContent = `${selectedIds.size} đã chọn`;
         $('#btn-bulk-paste').disabled = !selectedIds.size;
         $('#btn-del-sel').disabled = !selectedIds.size;
+        $('#btn-tab-sel').disabled = !selectedIds.size;
     }
     function reRenderSel() {
         itemsEl.querySelectorAll('.card').forEach(c => {
@@ -580,9 +643,11 @@
         const contents = [];
         const htmlContents = [];
         const imagePaths = [];
+        const itemIds = [];
 
         displayItems.forEach(i => {
-            if (selectedIds.has(i.id)) {
+            if (selectedIds.has(i.id) && !i.sensitive) {
+                itemIds.push(i.id);
                 if (i.kind === 'image' && i.imagePath) {
                     imagePaths.push(i.imagePath);
                 } else if (i.kind !== 'image') {
@@ -600,7 +665,8 @@
                 await window.copas.bulkPasteAndHide(
                     contents,
                     htmlContents.length > 0 ? htmlContents : null,
-                    imagePaths.length > 0 ? imagePaths : null
+                    imagePaths.length > 0 ? imagePaths : null,
+                    itemIds
                 );
             } catch (err) {
                 toast('❌ Lỗi dán: ' + (err.message || err), 'error');
@@ -615,7 +681,7 @@
         if (!selectedIds.size) return;
         const contents = [];
         displayItems.forEach(i => {
-            if (selectedIds.has(i.id) && i.kind !== 'image') contents.push(i.contentText || i.content || '');
+            if (selectedIds.has(i.id) && i.kind !== 'image' && !i.sensitive) contents.push(i.contentText || i.content || '');
         });
         if (contents.length > 0) {
             await window.copas.bulkCopy(contents);
@@ -642,6 +708,13 @@
         if (scrEl) scrEl.value = settings.shortcutScreenshot || '';
         $('#set-max').value = settings.maxHistory || 1000;
         $('#set-delim').value = settings.pasteDelimiter || '\\n';
+        $('#set-excluded').value = (settings.excludedApps || []).join(', ');
+        $('#set-allowed').value = (settings.allowedApps || []).join(', ');
+        $('#set-concealed').value = settings.concealedClipboard || 'skip';
+        $('#set-sensitive-ttl').value = settings.sensitiveTtl ?? 60;
+        $('#set-child-tabs').value = settings.includeChildTabs === false ? 'no' : 'yes';
+        $('#set-vault-timeout').value = settings.vaultTimeout ?? 300;
+        updateEncryptionUI();
         $$('.th-opt').forEach(b => b.classList.toggle('active', b.dataset.theme === settings.theme));
     }
     async function saveSettings() {
@@ -651,6 +724,12 @@
             shortcutScreenshot: scrEl ? (scrEl.value || settings.shortcutScreenshot || '') : (settings.shortcutScreenshot || ''),
             maxHistory: parseInt($('#set-max').value) || 1000,
             pasteDelimiter: $('#set-delim').value,
+            excludedApps: appList($('#set-excluded').value),
+            allowedApps: appList($('#set-allowed').value),
+            concealedClipboard: $('#set-concealed').value,
+            sensitiveTtl: Math.max(0, parseInt($('#set-sensitive-ttl').value) || 0),
+            includeChildTabs: $('#set-child-tabs').value === 'yes',
+            vaultTimeout: Math.max(0, parseInt($('#set-vault-timeout').value) || 0),
             theme: document.querySelector('.th-opt.active')?.dataset.theme || settings.theme
         };
         applyTheme(ns.theme); settings = { ...settings, ...ns };
@@ -659,6 +738,7 @@
         settingsPanel.style.display = 'none';
         updateGuideShortcut();
     }
+    function appList(v) { return v.split(',').map(a => a.trim()).filter(Boolean); }
     function updateGuideShortcut() { const el = $('#guide-sc'); if (el) el.textContent = settings.shortcutToggle || 'Ctrl+Shift+V'; }
     function setupShortcutRecorder() {
         $$('.sc-rec').forEach(btn => {
@@ -684,15 +764,18 @@
     function bindRealtime() {
         window.copas.onClipboardUpdate(async item => {
             if (item.kind === 'image' || item.imagePath) {
-                item.imageUrl = await window.copas.getImageUrl(item.imagePath);
+                item.imageUrl = await window.copas.getThumbnailUrl(item.id);
             }
             allItems.unshift(item);
             await loadItems();
+            await loadTabCounts();
             renderTabs();
             updateStats();
             scrollEl.scrollTo({ top: 0, behavior: 'smooth' });
         });
         window.copas.onHistoryCleared(() => refresh());
+        window.copas.onItemsExpired(ids => { ids.forEach(id => selectedIds.delete(id)); refresh(); });
+        window.copas.onVaultLocked(() => { document.querySelector('.vault-dlg')?.remove(); toast('🔒 Vault đã tự khóa', 'info'); });
         // When popup is shown, focus search
         window.copas.onPopupShown(() => {
             searchInput.focus();
@@ -701,30 +784,34 @@
     }
     async function refresh() { await loadAllItems(); await loadItems(); renderTabs(); updateStats(); }
     async function updateStats() {
-        try { const s = await window.copas.getStats(); $('#stat-total').textContent = s.totalItems.toLocaleString(); $('#stat-size').textContent = fmtB(s.storageSize); } catch { }
+        try { const s = await window.copas.getStats(); $('#stat-total').textContent = s.totalItems.toLocaleString(); $('#stat-total').title = s.vaultItems ? `+ ${s.vaultItems} mục trong Vault` : ''; $('#stat-size').textContent = fmtB(s.storageSize + s.imagesSize); $('#stat-size').title = `Ảnh: ${fmtB(s.imagesSize)} · Đã giải phóng: ${fmtB(s.reclaimedBytes)}`; } catch { }
     }
 
     // ===== DIALOGS =====
-    function showNewTabDlg() {
+    function showNewTabDlg(parentId) {
         const icons = ['📁', '💼', '🏠', '🎯', '🔖', '💡', '🎨', '🛒', '📌', '🚀', '❤️', '📎', '🌐', '📸', '🎵', '🔥', '💎', '🎮'];
         let icon = '📁';
         const ov = mk('div', 'dlg-overlay');
-        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">Tạo thẻ mới</div><input class="dlg-input" id="nt-name" placeholder="Tên thẻ..." autofocus><div class="dlg-body">Icon:</div><div class="dlg-row">${icons.map(ic => `<button class="dlg-emoji ${ic === icon ? 'on' : ''}" data-i="${ic}">${ic}</button>`).join('')}</div><div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn primary" id="nt-ok">Tạo</button></div></div>`;
+        const parent = typeof parentId === 'string' ? tabs.find(t => t.id === parentId) : null;
+        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">${parent ? `Tạo thẻ con trong "${esc(parent.name)}"` : 'Tạo thẻ mới'}</div><input class="dlg-input" id="nt-name" placeholder="Tên thẻ..." autofocus><div class="dlg-body">Icon:</div><div class="dlg-row">${icons.map(ic => `<button class="dlg-emoji ${ic === icon ? 'on' : ''}" data-i="${ic}">${ic}</button>`).join('')}</div><div class="dlg-body">Thẻ thông minh (tùy chọn) — hiện mọi mục khớp truy vấn:</div><input class="dlg-input" id="nt-query" placeholder="vd: kind:image after:this-week"><div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn primary" id="nt-ok">Tạo</button></div></div>`;
         dlgRoot.appendChild(ov);
         ov.querySelectorAll('.dlg-emoji').forEach(b => b.addEventListener('click', () => { ov.querySelectorAll('.dlg-emoji').forEach(x => x.classList.remove('on')); b.classList.add('on'); icon = b.dataset.i; }));
         ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
         ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
-        ov.querySelector('#nt-ok').addEventListener('click', async () => { const n = ov.querySelector('#nt-name').value.trim(); if (!n) { toast('⚠️ Nhập tên!', 'error'); return; } await window.copas.createTab({ name: n, icon }); toast(`✅ Tạo thẻ "${n}"!`, 'success'); ov.remove(); await loadTabs(); renderTabs(); });
+        ov.querySelector('#nt-ok').addEventListener('click', async () => { const n = ov.querySelector('#nt-name').value.trim(); if (!n) { toast('⚠️ Nhập tên!', 'error'); return; } try { await window.copas.createTab({ name: n, icon, query: ov.querySelector('#nt-query').value.trim(), parentId: parent?.id }); } catch (e) { toast(`⚠️ Truy vấn không hợp lệ: ${e}`, 'error'); return; } toast(`✅ Tạo thẻ "${n}"!`, 'success'); ov.remove(); await loadTabs(); renderTabs(); });
         setTimeout(() => ov.querySelector('#nt-name')?.focus(), 100);
     }
     function showTabMenu(id, ev) {
-        closeMenus(); const tab = tabs.find(t => t.id === id); if (!tab || tab.system) return;
+        closeMenus(); const tab = tabs.find(t => t.id === id); if (!tab || tab.id === 'all') return;
         const m = mk('div', 'ctx-menu'); m.style.left = ev.clientX + 'px'; m.style.top = ev.clientY + 'px';
-        m.innerHTML = `<button class="ctx-item" data-a="rename">✏️ Đổi tên</button><button class="ctx-item" data-a="clear">🧹 Xóa nội dung</button><div class="ctx-sep"></div><button class="ctx-item danger" data-a="del">🗑 Xóa thẻ</button>`;
+        m.innerHTML = `<button class="ctx-item" data-a="query">🔎 Truy vấn thông minh</button><button class="ctx-item" data-a="rules">⚙️ Quy tắc tự động</button>${tab.system ? '' : `<button class="ctx-item" data-a="child">➕ Tạo thẻ con</button><button class="ctx-item" data-a="rename">✏️ Đổi tên</button><button class="ctx-item" data-a="clear">🧹 Xóa nội dung</button><div class="ctx-sep"></div><button class="ctx-item danger" data-a="del">🗑 Xóa thẻ</button>`}`;
         document.body.appendChild(m);
         const r = m.getBoundingClientRect(); if (r.right > innerWidth) m.style.left = (innerWidth - r.width - 8) + 'px'; if (r.bottom > innerHeight) m.style.top = (innerHeight - r.height - 8) + 'px';
         m.addEventListener('click', async e => {
             const a = e.target.closest('.ctx-item')?.dataset.a; m.remove();
+            if (a === 'child') showNewTabDlg(id);
+            if (a === 'query') showQueryDlg(id);
+            if (a === 'rules') showRulesDlg(id);
             if (a === 'rename') showRenameDlg(id);
             if (a === 'clear') showConfirm('Xóa nội dung?', `Xóa nội dung chưa ghim?`, async () => { await window.copas.clearHistory(id); toast('🧹 Đã xóa!', 'info'); await refresh(); });
             if (a === 'del') showConfirm('Xóa thẻ?', `Xóa thẻ "${tab.name}"?`, async () => { await window.copas.deleteTab(id); if (activeTabId === id) activeTabId = 'all'; toast('🗑 Đã xóa!', 'info'); await loadTabs(); await refresh(); });
@@ -741,6 +828,65 @@
         ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
         ov.querySelector('#rn-ok').addEventListener('click', async () => { const n = ov.querySelector('#rn-in').value.trim(); if (!n) return; await window.copas.renameTab({ id, name: n, icon }); toast('✅ Đã lưu!', 'success'); ov.remove(); await loadTabs(); renderTabs(); });
     }
+    function showQueryDlg(id) {
+        const tab = tabs.find(t => t.id === id); if (!tab) return;
+        const ov = mk('div', 'dlg-overlay');
+        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">🔎 Truy vấn: ${esc(tab.name)}</div><div class="dlg-body">Thẻ hiện mọi mục khớp truy vấn, cùng các mục đã xếp vào thẻ. Để trống để tắt. Vd: <code>kind:image after:this-week</code>, <code>category:code app:code</code>, <code>pinned:true after:7d</code></div><input class="dlg-input" id="q-in" value="${esc(tab.query || '')}"><div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn primary" id="q-ok">Lưu</button></div></div>`;
+        dlgRoot.appendChild(ov);
+        ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
+        ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
+        ov.querySelector('#q-ok').addEventListener('click', async () => { const res = await window.copas.setTabQuery(id, ov.querySelector('#q-in').value.trim()); if (!res.success) { toast(`⚠️ ${res.error || 'Không lưu được truy vấn'}`, 'error'); return; } toast('✅ Đã lưu!', 'success'); ov.remove(); await loadTabs(); await refresh(); });
+        setTimeout(() => ov.querySelector('#q-in')?.focus(), 100);
+    }
+    const ruleTypes = { textMatches: ['Văn bản khớp regex', 'pattern'], sourceApp: ['Ứng dụng nguồn', 'app'], category: ['Loại', 'category'], tag: ['Tag', 'tag'], longerThan: ['Dài hơn (ký tự)', 'chars'], shorterThan: ['Ngắn hơn (ký tự)', 'chars'] };
+    function showRulesDlg(id) {
+        const tab = tabs.find(t => t.id === id); if (!tab) return;
+        const rules = (tab.rules || []).map(r => ({ ...r }));
+        const ov = mk('div', 'dlg-overlay');
+        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">⚙️ Quy tắc: ${esc(tab.name)}</div><div class="dlg-body">Mục mới khớp bất kỳ quy tắc nào sẽ tự vào thẻ này.</div><div id="rl-list"></div><button class="dlg-btn cancel" id="rl-add">+ Thêm quy tắc</button><div class="dlg-foot" style="margin-top:12px"><button class="dlg-btn cancel" id="rl-close">Hủy</button><button class="dlg-btn cancel" id="rl-apply">Áp dụng cho lịch sử</button><button class="dlg-btn primary" id="rl-ok">Lưu</button></div></div>`;
+        dlgRoot.appendChild(ov);
+        const listEl = ov.querySelector('#rl-list');
+        const draw = () => {
+            listEl.innerHTML = rules.map((r, i) => `<div class="rule-row" data-i="${i}"><select class="dlg-input rule-type">${Object.entries(ruleTypes).map(([k, [n]]) => `<option value="${k}" ${k === r.type ? 'selected' : ''}>${n}</option>`).join('')}</select><input class="dlg-input rule-val" type="${ruleTypes[r.type][1] === 'chars' ? 'number' : 'text'}" value="${esc(String(r[ruleTypes[r.type][1]] ?? ''))}"><button class="dlg-btn danger rule-rm">✕</button></div>`).join('');
+            listEl.querySelectorAll('.rule-row').forEach(row => {
+                const i = +row.dataset.i;
+                row.querySelector('.rule-type').addEventListener('change', e => { rules[i] = { type: e.target.value }; draw(); });
+                row.querySelector('.rule-val').addEventListener('input', e => { const f = ruleTypes[rules[i].type][1]; rules[i][f] = f === 'chars' ? Math.max(0, parseInt(e.target.value, 10) || 0) : e.target.value; });
+                row.querySelector('.rule-rm').addEventListener('click', () => { rules.splice(i, 1); draw(); });
+            });
+        };
+        draw();
+        const save = async () => {
+            const clean = rules.filter(r => ruleTypes[r.type][1] === 'chars' ? true : (r[ruleTypes[r.type][1]] || '').trim()).map(r => ruleTypes[r.type][1] === 'chars' ? { ...r, chars: r.chars || 0 } : r);
+            const res = await window.copas.setTabRules(id, clean);
+            if (!res.success) { toast(`⚠️ ${res.error || 'Không lưu được quy tắc'}`, 'error'); return false; }
+            await loadTabs(); return true;
+        };
+        ov.querySelector('#rl-add').addEventListener('click', () => { rules.push({ type: 'textMatches', pattern: '' }); draw(); });
+        ov.querySelector('#rl-close').addEventListener('click', () => ov.remove());
+        ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
+        ov.querySelector('#rl-ok').addEventListener('click', async () => { if (await save()) { toast('✅ Đã lưu!', 'success'); ov.remove(); } });
+        ov.querySelector('#rl-apply').addEventListener('click', async () => { if (!(await save())) return; const res = await window.copas.applyTabRules(); toast(`✅ Đã xếp ${res.filed} mục`, 'success'); ov.remove(); await refresh(); });
+    }
+    // Tabs an item (or every selected item) is filed into; an item can be in several
+    function showItemTabsDlg(ids) {
+        const items = allItems.filter(i => ids.includes(i.id));
+        const choices = tabs.filter(t => t.id !== 'all');
+        const before = new Set(choices.filter(t => items.length && items.every(i => (i.tabIds || []).includes(t.id))).map(t => t.id));
+        const ov = mk('div', 'dlg-overlay');
+        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">📁 Thẻ${ids.length > 1 ? ` (${ids.length} mục)` : ''}</div><div class="dlg-body">Một mục có thể nằm trong nhiều thẻ.</div>${choices.map(t => `<label class="tab-check"><input type="checkbox" data-id="${t.id}" ${before.has(t.id) ? 'checked' : ''}> ${t.icon} ${esc(t.name)}</label>`).join('')}<div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn primary" id="it-ok">Lưu</button></div></div>`;
+        dlgRoot.appendChild(ov);
+        ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
+        ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
+        ov.querySelector('#it-ok').addEventListener('click', async () => {
+            for (const cb of ov.querySelectorAll('input[type=checkbox]')) {
+                const tid = cb.dataset.id;
+                if (cb.checked && !before.has(tid)) await window.copas.addToTab(ids, tid);
+                if (!cb.checked && before.has(tid)) await window.copas.removeFromTab(ids, tid);
+            }
+            toast('✅ Đã lưu!', 'success'); ov.remove(); await refresh();
+        });
+    }
     function showLabelDlg(id) {
         const item = allItems.find(i => i.id === id); if (!item) return;
         const ov = mk('div', 'dlg-overlay');
@@ -752,9 +898,9 @@
         ov.querySelector('#lb-rm')?.addEventListener('click', async () => { await window.copas.labelItem({ id, label: '' }); toast('Đã xóa tên', 'info'); ov.remove(); await refresh(); });
         setTimeout(() => ov.querySelector('#lb-in')?.focus(), 100);
     }
-    function showConfirm(title, msg, onOk) {
+    function showConfirm(title, msg, onOk, okLabel = 'Xóa') {
         const ov = mk('div', 'dlg-overlay');
-        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">${title}</div><div class="dlg-body">${msg}</div><div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn danger">Xóa</button></div></div>`;
+        ov.innerHTML = `<div class="dlg-box"><div class="dlg-title">${title}</div><div class="dlg-body">${msg}</div><div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn danger">${okLabel}</button></div></div>`;
         dlgRoot.appendChild(ov);
         ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
         ov.querySelector('.danger').addEventListener('click', () => { onOk(); ov.remove(); });
@@ -769,19 +915,20 @@
         const svgCopy = '<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><rect x="9" y="9" width="13" height="13" rx="2"/><path d="M5 15H4a2 2 0 0 1-2-2V4a2 2 0 0 1 2-2h9a2 2 0 0 1 2 2v1"/></svg>';
         const svgDel = '<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><path d="M3 6h18M8 6V4a2 2 0 0 1 2-2h4a2 2 0 0 1 2 2v2"/><path d="m19 6-.7 11.2a2 2 0 0 1-2 1.8H7.7a2 2 0 0 1-2-1.8L5 6"/></svg>';
         let html = '';
-        if (item.kind !== 'image') html += `<button class="ctx-item" data-a="fmt-up">${svgUp} IN HOA</button><button class="ctx-item" data-a="fmt-low">${svgLow} in thường</button><button class="ctx-item" data-a="fmt-noacc">${svgAcc} Bỏ dấu</button><div class="ctx-sep"></div>`;
+        if (item.kind !== 'image' && !item.sensitive) html += `<button class="ctx-item" data-a="fmt-up">${svgUp} IN HOA</button><button class="ctx-item" data-a="fmt-low">${svgLow} in thường</button><button class="ctx-item" data-a="fmt-noacc">${svgAcc} Bỏ dấu</button><div class="ctx-sep"></div>`;
         const svgVault = '<svg width="14" height="14" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2"><rect x="3" y="11" width="18" height="11" rx="2"/><path d="M7 11V7a5 5 0 0 1 10 0v4"/></svg>';
         html += `<button class="ctx-item" data-a="copy">${svgCopy} Copy</button>`;
-        if (!item.in_vault) html += `<button class="ctx-item" data-a="vault">${svgVault} Chuyển vào Vault</button>`;
-        else html += `<button class="ctx-item" data-a="unvault">${svgVault} Lấy khỏi Vault</button>`;
+        html += `<button class="ctx-item" data-a="tabs">📁 Thẻ...</button>`;
+        html += `<button class="ctx-item" data-a="vault">${svgVault} Chuyển vào Vault</button>`;
         html += `<button class="ctx-item danger" data-a="del">${svgDel} Xóa</button>`;
         m.innerHTML = html; document.body.appendChild(m);
         const r = m.getBoundingClientRect(); if (r.right > innerWidth) m.style.left = (innerWidth - r.width - 8) + 'px'; if (r.bottom > innerHeight) m.style.top = (innerHeight - r.height - 8) + 'px';
         m.addEventListener('click', async e => {
             const a = e.target.closest('.ctx-item')?.dataset.a; m.remove(); if (!a) return;
             if (a === 'copy') {
-                if (item.kind !== 'image') { await window.copas.copyToClipboard(item.contentText || item.content || ''); toast('📋 Đã copy!', 'info'); } else toast('Dán ảnh trực tiếp', 'warning');
-            } else if (a === 'del') { await window.copas.deleteItem(id); toast('🗑 Đã xóa!', 'info'); await refresh(); }
+                if (item.kind !== 'image') { await window.copas.copyToClipboard(item.contentText || item.content || '', item.id); toast('📋 Đã copy!', 'info'); } else toast('Dán ảnh trực tiếp', 'warning');
+            } else if (a === 'tabs') showItemTabsDlg([id]);
+            else if (a === 'del') { await window.copas.deleteItem(id); toast('🗑 Đã xóa!', 'info'); await refresh(); }
             else if (a.startsWith('fmt-')) {
                 let txt = item.contentText || item.content || '';
                 if (a === 'fmt-up') txt = txt.toUpperCase();
@@ -789,9 +936,9 @@
                 if (a === 'fmt-noacc') txt = txt.normalize('NFD').replace(/[\u0300-\u036f]/g, '').replace(/đ/g, 'd').replace(/Đ/g, 'D');
                 await window.copas.copyToClipboard(txt); toast('✨ Đã format và copy!', 'success');
             } else if (a === 'vault') {
-                await window.copas.moveToVault(id); toast('🔒 Đã chuyển vào Vault!', 'success'); await refresh();
-            } else if (a === 'unvault') {
-                await window.copas.removeFromVault(id); toast('🔓 Đã lấy khỏi Vault', 'info'); await refresh();
+                const res = await window.copas.moveToVault(id);
+                if (!res.success) { toast(`⚠️ ${res.error === 'Vault is locked' ? 'Mở khóa Vault trước' : (res.error || 'Không chuyển được')}`, 'warning'); return; }
+                toast('🔒 Đã mã hóa vào Vault!', 'success'); await refresh();
             }
         }); ev.stopPropagation();
     }
@@ -804,6 +951,8 @@
         return t.replace(/{date}/gi, n.toLocaleDateString('vi-VN')).replace(/{time}/gi, n.toLocaleTimeString('vi-VN')).replace(/{datetime}/gi, n.toLocaleString('vi-VN'));
     }
     function esc(s) { const d = document.createElement('div'); d.textContent = s || ''; return d.innerHTML }
+    function fileName(uri) { const p = decodeURIComponent(uri.replace(/^file:\/\/[^/]*/, '')).replace(/\/+$/, ''); return p.slice(p.lastIndexOf('/') + 1) || p }
+    function fmtSize(n) { if (n < 1024) return n + ' B'; if (n < 1048576) return (n / 1024).toFixed(1) + ' KB'; return (n / 1048576).toFixed(1) + ' MB' }
     function hi(t, q) { if (!q) return t; return t.replace(new RegExp(`(${q.replace(/[.*+?^${}()|[\]\\]/g, '\\$&')})`, 'gi'), '<mark>$1</mark>') }
     function timeAgo(iso) { const d = (Date.now() - new Date(iso)) / 1e3; if (d < 5) return 'Vừa xong'; if (d < 60) return `${~~d}s`; if (d < 3600) return `${~~(d / 60)}m`; if (d < 86400) return `${~~(d / 3600)}h`; if (d < 604800) return `${~~(d / 86400)}d`; return new Date(iso).toLocaleDateString('vi-VN') }
     function fmtB(b) { if (!b) return '0 B'; const k = 1024, s = ['B', 'KB', 'MB', 'GB'], i = Math.floor(Math.log(b) / Math.log(k)); return (b / Math.pow(k, i)).toFixed(1) + ' ' + s[i] }
@@ -1339,8 +1488,71 @@
         await window.copas.setFullscreen(false);
     }
 
+    // ===== ENCRYPTION AT REST =====
+    const encryptionError = r => toast(r.error === 'Wrong passphrase' ? 'Sai mật khẩu chính!' : `⚠️ ${r.error}`, 'error');
+
+    /** Block the app until the database is unlocked: with the master
+     * passphrase, or by retrying the keyring when the key is kept there */
+    function unlockDatabase(enc) {
+        return new Promise(resolve => {
+            const keyring = enc.source === 'keyring';
+            const ov = mk('div', 'dlg-overlay vault-dlg');
+            ov.innerHTML = `<div class="dlg-box" style="max-width:360px"><div class="dlg-title">🔐 Lịch sử đã được mã hóa</div><div class="dlg-body">${keyring ? 'Không đọc được khóa từ keyring của hệ thống. Hãy mở khóa keyring rồi thử lại.' : 'Nhập mật khẩu chính để mở lịch sử.'}</div>${keyring ? '' : '<input class="dlg-input" type="password" placeholder="Mật khẩu chính" autocomplete="off">'}<div class="dlg-foot"><button class="dlg-btn primary ok">${keyring ? 'Thử lại' : 'Mở khóa'}</button></div></div>`;
+            dlgRoot.appendChild(ov);
+            const input = ov.querySelector('.dlg-input');
+            if (input) setTimeout(() => input.focus(), 50);
+            const submit = async () => {
+                const r = await window.copas.unlockDatabase(input ? input.value : '');
+                if (r.success) { ov.remove(); resolve(); return; }
+                encryptionError(r);
+                if (input) { input.value = ''; input.focus(); }
+            };
+            ov.querySelector('.ok').addEventListener('click', submit);
+            if (input) input.addEventListener('keydown', e => { if (e.key === 'Enter') submit(); });
+        });
+    }
+
+    async function updateEncryptionUI() {
+        const enc = await window.copas.getEncryptionStatus();
+        $('#set-encryption').textContent = enc.enabled ? 'Tắt' : 'Bật';
+        $('#set-encryption-hint').textContent = !enc.enabled
+            ? 'Lịch sử và ảnh đang lưu dạng thường. Cài đặt không bao giờ được mã hóa.'
+            : enc.source === 'keyring'
+                ? 'Đang mã hóa, khóa được giữ trong keyring của hệ thống.'
+                : 'Đang mã hóa, cần nhập mật khẩu chính mỗi lần mở CoPas.';
+    }
+
+    /** Encrypt or decrypt the history in place, then reload it, since
+     * encrypted images are shown from data URLs instead of files */
+    async function toggleEncryption() {
+        const enc = await window.copas.getEncryptionStatus();
+        const run = async (call, msg) => {
+            toast('⏳ Đang chuyển đổi dữ liệu...', 'info');
+            const r = await call();
+            if (!r.success) { encryptionError(r); return false; }
+            toast(msg, 'success');
+            updateEncryptionUI();
+            await loadAllItems();
+            await loadItems();
+            return true;
+        };
+        if (!enc.enabled) {
+            showPinDialog('🔐 Mã hóa lịch sử', 'Lịch sử, ảnh và dữ liệu clipboard sẽ được mã hóa trên đĩa. Đặt mật khẩu chính để phải nhập mỗi lần mở CoPas, hoặc để trống để giữ khóa trong keyring của hệ thống.', [
+                { placeholder: 'Mật khẩu chính (ít nhất 8 ký tự)' }, { placeholder: 'Nhập lại mật khẩu chính' },
+            ], 'Mã hóa', async ([passphrase, confirm]) => {
+                if (passphrase !== confirm) { toast('Mật khẩu nhập lại không khớp', 'warning'); return false; }
+                if (passphrase && passphrase.length < 8) { toast('Mật khẩu chính cần ít nhất 8 ký tự', 'warning'); return false; }
+                return run(() => window.copas.enableEncryption(passphrase), '🔐 Đã mã hóa lịch sử!');
+            });
+        } else if (enc.source === 'keyring') {
+            showConfirm('🔓 Tắt mã hóa', 'Lịch sử và ảnh sẽ được lưu lại dạng thường trên đĩa.', () => run(() => window.copas.disableEncryption(), '🔓 Đã tắt mã hóa'), 'Tắt mã hóa');
+        } else {
+            showPinDialog('🔓 Tắt mã hóa', 'Lịch sử và ảnh sẽ được lưu lại dạng thường trên đĩa.', [{ placeholder: 'Mật khẩu chính' }], 'Tắt mã hóa',
+                ([passphrase]) => run(() => window.copas.disableEncryption(passphrase), '🔓 Đã tắt mã hóa'));
+        }
+    }
+
     // ===== VAULT =====
-    let vaultUnlocked = false;
     function bindVault() {
         $('#btn-vault').addEventListener('click', async () => {
             if (!(await requirePremium('Vault bảo mật'))) return;
@@ -1350,29 +1562,35 @@
                 $('#vault-title').textContent = 'Tạo mã PIN Vault';
                 $('#vault-desc').textContent = 'Nhập mã PIN mới (4-8 số) để bảo vệ dữ liệu của bạn';
                 $('#vault-ok').textContent = 'Tạo PIN';
+                $('#vault-recovery-opt').style.display = '';
                 showVaultOverlay(async (pin) => {
                     if (pin.length < 4) { toast('PIN cần ít nhất 4 ký tự', 'warning'); return false; }
-                    await window.copas.setVaultPin(pin);
+                    const r = await window.copas.setVaultPin(pin, $('#vault-recovery').checked);
+                    if (!r.success) { toast(`⚠️ ${r.error || 'Không tạo được PIN'}`, 'error'); return false; }
                     toast('🔐 Đã tạo PIN Vault!', 'success');
-                    vaultUnlocked = true;
-                    showVaultItems();
+                    if (r.recoveryKey) showRecoveryKey(r.recoveryKey, showVaultItems);
+                    else showVaultItems();
                     return true;
                 });
-            } else if (!vaultUnlocked) {
+            } else if (!res.unlocked) {
                 // Unlock vault
+                if (res.retryAfter > 0) { toast(`⏳ Sai PIN quá nhiều lần, thử lại sau ${res.retryAfter} giây`, 'warning'); return; }
                 $('#vault-title').textContent = 'Mở khóa Vault';
                 $('#vault-desc').textContent = 'Nhập mã PIN để truy cập dữ liệu bảo mật';
                 $('#vault-ok').textContent = 'Mở khóa';
+                if (res.hasRecovery) {
+                    $('#vault-forgot').style.display = '';
+                    $('#vault-forgot').onclick = () => { $('#vault-overlay').style.display = 'none'; showResetPin(); };
+                }
                 showVaultOverlay(async (pin) => {
                     const r = await window.copas.verifyVaultPin(pin);
                     if (r.valid) {
-                        vaultUnlocked = true;
                         toast('🔓 Vault đã mở!', 'success');
                         showVaultItems();
                         return true;
                     } else {
-                        toast('Sai mã PIN!', 'error');
-                        return false;
+                        pinError(r, 'Không mở được Vault');
+                        return r.retryAfter > 0;
                     }
                 });
             } else {
@@ -1389,7 +1607,7 @@
         pinInput.value = '';
         setTimeout(() => pinInput.focus(), 100);
 
-        const cleanup = () => { ov.style.display = 'none'; };
+        const cleanup = () => { ov.style.display = 'none'; $('#vault-recovery-opt').style.display = 'none'; $('#vault-forgot').style.display = 'none'; };
         $('#vault-cancel').onclick = cleanup;
         pinInput.onkeydown = async (e) => {
             if (e.key === 'Enter') { const ok = await onSubmit(pinInput.value); if (ok) cleanup(); }
@@ -1398,33 +1616,127 @@
         $('#vault-ok').onclick = async () => { const ok = await onSubmit(pinInput.value); if (ok) cleanup(); };
     }
 
+    /** A dialog of password fields; `onSubmit(values)` returns whether to close it */
+    function showPinDialog(title, desc, fields, okLabel, onSubmit) {
+        const ov = mk('div', 'dlg-overlay vault-dlg');
+        ov.innerHTML = `<div class="dlg-box" style="max-width:360px"><div class="dlg-title">${title}</div><div class="dlg-body">${desc}</div>${fields.map((f, i) => `<input class="dlg-input" type="${f.type || 'password'}" data-f="${i}" placeholder="${f.placeholder}" autocomplete="off">`).join('')}<div class="dlg-foot"><button class="dlg-btn cancel">Hủy</button><button class="dlg-btn primary ok">${okLabel}</button></div></div>`;
+        dlgRoot.appendChild(ov);
+        const inputs = [...ov.querySelectorAll('[data-f]')];
+        setTimeout(() => inputs[0].focus(), 50);
+        const submit = async () => { if (await onSubmit(inputs.map(i => i.value))) ov.remove(); };
+        ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
+        ov.querySelector('.ok').addEventListener('click', submit);
+        inputs.forEach(i => i.addEventListener('keydown', e => { if (e.key === 'Enter') submit(); if (e.key === 'Escape') ov.remove(); }));
+    }
+
+    /** Check a new PIN and its confirmation, toasting what's wrong */
+    function checkNewPin(pin, confirm) {
+        if (pin.length < 4) { toast('PIN cần ít nhất 4 ký tự', 'warning'); return false; }
+        if (pin !== confirm) { toast('PIN nhập lại không khớp', 'warning'); return false; }
+        return true;
+    }
+
+    /** Toast the error of a PIN check, or the backoff when there is one */
+    function pinError(r, wrong) {
+        if (r.retryAfter > 0) toast(`⏳ Sai PIN quá nhiều lần, thử lại sau ${r.retryAfter} giây`, 'warning');
+        else if (r.error === 'Wrong PIN') toast('Sai mã PIN!', 'error');
+        else if (r.error === 'Wrong recovery key') toast('Sai mã khôi phục hoặc mã đã được dùng', 'error');
+        else toast(`⚠️ ${r.error || wrong}`, 'error');
+    }
+
+    function showRecoveryKey(code, then) {
+        const ov = mk('div', 'dlg-overlay');
+        ov.innerHTML = `<div class="dlg-box" style="max-width:380px"><div class="dlg-title">🔑 Mã khôi phục Vault</div><div class="dlg-body">Mã này đặt lại PIN khi bạn quên, mà không mất dữ liệu. Hãy chép ra nơi an toàn — mã <strong>chỉ hiện một lần</strong>, chỉ dùng được một lần và thay cho mã cũ.</div><div class="vault-recovery-key">${esc(code)}</div><div class="dlg-foot"><button class="dlg-btn primary ok">Đã lưu</button></div></div>`;
+        dlgRoot.appendChild(ov);
+        ov.querySelector('.ok').addEventListener('click', () => { ov.remove(); if (then) then(); });
+    }
+
+    function showChangePin() {
+        showPinDialog('🔑 Đổi PIN Vault', 'Dữ liệu trong Vault được giữ nguyên.', [
+            { placeholder: 'PIN hiện tại' }, { placeholder: 'PIN mới (4-8 số)' }, { placeholder: 'Nhập lại PIN mới' },
+        ], 'Đổi PIN', async ([oldPin, pin, confirm]) => {
+            if (!checkNewPin(pin, confirm)) return false;
+            const r = await window.copas.changeVaultPin(oldPin, pin);
+            if (!r.success) { pinError(r, 'Không đổi được PIN'); return false; }
+            toast('🔐 Đã đổi PIN Vault!', 'success');
+            return true;
+        });
+    }
+
+    function showResetPin() {
+        showPinDialog('🔑 Quên PIN', 'Nhập mã khôi phục đã lưu khi tạo Vault. Mã sẽ hết hiệu lực sau khi dùng.', [
+            { placeholder: 'XXXX-XXXX-XXXX-XXXX-XXXX-XXXX-XXXX-XXXX', type: 'text' }, { placeholder: 'PIN mới (4-8 số)' }, { placeholder: 'Nhập lại PIN mới' },
+        ], 'Đặt lại PIN', async ([code, pin, confirm]) => {
+            if (!checkNewPin(pin, confirm)) return false;
+            const r = await window.copas.resetVaultPin(code, pin);
+            if (!r.success) { pinError(r, 'Không đặt lại được PIN'); return false; }
+            toast('🔓 Đã đặt lại PIN, Vault đã mở!', 'success');
+            showVaultItems();
+            return true;
+        });
+    }
+
     async function showVaultItems() {
         const res = await window.copas.getVaultItems();
+        if (res.error) { toast('🔒 Vault đã khóa, hãy mở lại', 'warning'); return; }
         const items = res.items || [];
-        if (items.length === 0) {
-            toast('🔒 Vault trống. Click chuột phải → "Chuyển vào Vault" để thêm mục.', 'info');
-            return;
-        }
-        // Show vault items in a dialog
-        const ov = mk('div', 'dlg-overlay');
-        let html = '<div class="dlg-box" style="max-width:420px"><div class="dlg-title">🔒 Vault (' + items.length + ' mục)</div><div class="dlg-body" style="max-height:300px;overflow-y:auto">';
-        items.forEach(item => {
-            const txt = (item.contentText || item.content || 'Hình ảnh').substring(0, 80);
-            html += `<div style="padding:8px 0;border-bottom:1px solid var(--bdr);display:flex;justify-content:space-between;align-items:center"><span style="font-size:12px;word-break:break-all">${esc(txt)}</span><button class="dlg-btn" data-vid="${item.id}" style="font-size:11px;padding:4px 10px">🔓 Lấy ra</button></div>`;
-        });
-        html += '</div><div class="dlg-foot"><button class="dlg-btn cancel">Đóng</button></div></div>';
-        ov.innerHTML = html;
+        const images = res.images || {};
+        // Show vault items in a dialog. The vault is searched on its own,
+        // the main history never contains vault items.
+        const ov = mk('div', 'dlg-overlay vault-dlg');
+        ov.innerHTML = '<div class="dlg-box" style="max-width:420px"><div class="dlg-title">🔒 Vault (' + items.length + ' mục)</div><input class="dlg-input" id="vault-search" placeholder="Tìm trong Vault..."><div class="dlg-body vault-list" style="max-height:300px;overflow-y:auto"></div><div class="dlg-foot"><button class="dlg-btn pin">🔑 Đổi PIN</button><button class="dlg-btn recovery">Mã khôi phục mới</button><button class="dlg-btn danger clear">🧹 Xóa hết</button><button class="dlg-btn lock">🔒 Khóa</button><button class="dlg-btn cancel">Đóng</button></div></div>';
         dlgRoot.appendChild(ov);
-        ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
-        ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
-        ov.querySelectorAll('[data-vid]').forEach(btn => {
-            btn.addEventListener('click', async () => {
-                await window.copas.removeFromVault(btn.dataset.vid);
-                toast('🔓 Đã lấy khỏi Vault', 'info');
-                ov.remove();
-                await refresh();
+        const listEl = ov.querySelector('.vault-list');
+        const vaultError = (r, fallback) => toast(`⚠️ ${r.error === 'Vault is locked' ? 'Vault đã khóa, hãy mở lại' : (r.error || fallback)}`, 'error');
+        const renderList = (items, images) => {
+            ov.querySelector('.dlg-title').textContent = `🔒 Vault (${items.length} mục)`;
+            listEl.innerHTML = items.length === 0 ? 'Vault trống. Click chuột phải → "Chuyển vào Vault" để thêm mục.' : items.map(item => {
+                const txt = (item.contentText || item.content || 'Hình ảnh').substring(0, 80);
+                const body = images[item.id] ? `<img src="${images[item.id]}" style="max-width:240px;max-height:80px;border-radius:4px">` : esc(txt);
+                return `<div style="padding:8px 0;border-bottom:1px solid var(--bdr);display:flex;justify-content:space-between;align-items:center;gap:6px"><span style="font-size:12px;word-break:break-all;flex:1">${body}</span><button class="dlg-btn" data-vid="${item.id}" style="font-size:11px;padding:4px 10px">🔓 Lấy ra</button><button class="dlg-btn danger" data-vdel="${item.id}" style="font-size:11px;padding:4px 10px">🗑</button></div>`;
+            }).join('');
+            listEl.querySelectorAll('[data-vid]').forEach(btn => {
+                btn.addEventListener('click', async () => {
+                    const r = await window.copas.removeFromVault(btn.dataset.vid);
+                    if (!r.success) { vaultError(r, 'Không lấy ra được'); return; }
+                    toast('🔓 Đã lấy khỏi Vault', 'info');
+                    await reload();
+                    await refresh();
+                });
+            });
+            listEl.querySelectorAll('[data-vdel]').forEach(btn => {
+                btn.addEventListener('click', async () => {
+                    const r = await window.copas.deleteItem(btn.dataset.vdel, true);
+                    if (!r.success) { vaultError(r, 'Không xóa được'); return; }
+                    toast('🗑 Đã xóa!', 'info');
+                    await reload();
+                });
             });
+        };
+        const reload = async () => {
+            const r = await window.copas.getVaultItems(ov.querySelector('#vault-search').value);
+            if (r.error === 'Vault is locked') { ov.remove(); toast('🔒 Vault đã khóa, hãy mở lại', 'warning'); return; }
+            if (r.error) { listEl.textContent = r.error; return; }
+            renderList(r.items || [], r.images || {});
+        };
+        renderList(items, images);
+        let searchTimer;
+        ov.querySelector('#vault-search').addEventListener('input', () => { clearTimeout(searchTimer); searchTimer = setTimeout(reload, 200); });
+        ov.querySelector('.clear').addEventListener('click', () => showConfirm('Xóa hết Vault?', 'Xóa vĩnh viễn mọi mục trong Vault?', async () => {
+            const r = await window.copas.clearHistory(null, true);
+            if (!r.success) { vaultError(r, 'Không xóa được'); return; }
+            toast('🧹 Đã xóa Vault!', 'info');
+            await reload();
+        }));
+        ov.querySelector('.cancel').addEventListener('click', () => ov.remove());
+        ov.querySelector('.lock').addEventListener('click', async () => { await window.copas.lockVault(); ov.remove(); toast('🔒 Đã khóa Vault', 'info'); });
+        ov.querySelector('.pin').addEventListener('click', showChangePin);
+        ov.querySelector('.recovery').addEventListener('click', async () => {
+            const r = await window.copas.newVaultRecoveryKey();
+            if (!r.success) { vaultError(r, 'Không tạo được mã'); return; }
+            showRecoveryKey(r.recoveryKey);
         });
+        ov.addEventListener('click', e => { if (e.target === ov) ov.remove(); });
     }
 
     init();